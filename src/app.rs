@@ -4,16 +4,48 @@ use glib;
 use gtk::{self, prelude::*};
 
 use crate::about_dialog::show_about_dialog;
+#[cfg(target_os = "linux")]
+use crate::capture_portal::{self, PortalError, Screencast};
+use crate::encryption;
 use crate::header_bar::HeaderBar;
+use crate::http_preview::HttpPreview;
 use crate::overlay::Overlay;
-use crate::pipeline::Pipeline;
-use crate::settings::show_settings_dialog;
+use crate::pipeline::{self, Pipeline, PipelineEvent};
+use crate::playback;
+use crate::settings::{show_settings_dialog, CloseAction, ExposureLock, Settings};
+use crate::shortcuts::show_shortcuts_window;
+use crate::sounds;
 use crate::utils;
+use crate::CommandLineOptions;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::error;
+use std::fmt;
 use std::ops;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Maximum time in milliseconds to wait for a recording to be finalized, e.g. when resetting
+const RECORDING_FINALIZE_TIMEOUT_MS: u32 = 3000;
+
+// Time in seconds for how long status messages are shown
+const STATUS_MESSAGE_TIMEOUT_SECS: u32 = 5;
+
+// Time in seconds between updates of the free disk space
+const DISK_SPACE_UPDATE_INTERVAL_SECS: u32 = 5;
+
+// Time in milliseconds to wait for further changes of the settings file before reloading it
+const SETTINGS_RELOAD_DELAY_MS: u32 = 500;
+
+// Number of attempts for reconnecting the camera after it failed before giving up, and the time in
+// milliseconds before the first attempt. The time is doubled for every further attempt
+const RECONNECT_MAX_ATTEMPTS: u32 = 5;
+const RECONNECT_INITIAL_DELAY_MS: u32 = 500;
+
+// Time in seconds between checks whether a camera was plugged in while there is none
+const NO_CAMERA_RETRY_INTERVAL_SECS: u32 = 2;
 
 // Here we specify our custom, application specific CSS styles for various widgets
 const STYLE: &str = "
@@ -22,8 +54,91 @@ const STYLE: &str = "
     color: black;
     font-size: 42pt;
     font-weight: bold;
+}
+
+#preview-placeholder {
+    font-size: 24pt;
+}
+
+#status-label {
+    background-color: rgba(192, 192, 192, 0.8);
+    color: black;
+    font-size: 14pt;
+}
+
+#countdown-label.high-contrast {
+    background-color: black;
+    color: yellow;
+    font-size: 72pt;
+}
+
+#status-label.high-contrast {
+    background-color: black;
+    color: white;
+    font-size: 24pt;
+    font-weight: bold;
+}
+
+#elapsed-label {
+    background-color: rgba(192, 192, 192, 0.8);
+    color: red;
+    font-size: 14pt;
+    font-weight: bold;
+}
+
+#elapsed-label.high-contrast {
+    background-color: black;
+    color: yellow;
+    font-size: 24pt;
+}
+
+#stats-label {
+    background-color: rgba(0, 0, 0, 0.6);
+    color: white;
+    font-family: monospace;
+}
+
+@keyframes recording-blink {
+    from { opacity: 1; }
+    to { opacity: 0.2; }
+}
+
+#recording-indicator {
+    color: red;
+    font-size: 18pt;
+    animation: recording-blink 1s ease-in-out infinite alternate;
 }";
 
+// Move a window position onto the screen if the window would be off-screen there, e.g. because
+// the monitor it was on is not connected anymore
+fn clamp_window_position(x: i32, y: i32, width: i32, height: i32) -> (i32, i32) {
+    let screen = match gdk::Screen::get_default() {
+        Some(screen) => screen,
+        None => return (x, y),
+    };
+
+    // Fine as long as the top left corner, where the header bar is, is on any monitor
+    let on_screen = (0..screen.get_n_monitors()).any(|i| {
+        let geometry = screen.get_monitor_geometry(i);
+        x >= geometry.x
+            && y >= geometry.y
+            && x < geometry.x + geometry.width
+            && y < geometry.y + geometry.height
+    });
+    if on_screen {
+        return (x, y);
+    }
+
+    // Otherwise keep as much of the window as possible on the primary monitor
+    let geometry = screen.get_monitor_geometry(screen.get_primary_monitor());
+    (
+        x.max(geometry.x)
+            .min(geometry.x + (geometry.width - width).max(0)),
+        y.max(geometry.y)
+            .min(geometry.y + (geometry.height - height).max(0)),
+    )
+}
+
 // Our refcounted application struct for containing all the state we have to carry around.
 //
 // This represents our main application window.
@@ -59,15 +174,203 @@ pub struct AppInner {
     header_bar: HeaderBar,
     overlay: Overlay,
 
-    pipeline: Pipeline,
+    // The pipeline can be replaced at runtime, e.g. when resetting
+    pipeline: RefCell<Pipeline>,
 
     timer: RefCell<Option<SnapshotTimer>>,
+
+    http_preview: RefCell<Option<HttpPreview>>,
+
+    // Timeout for hiding the current status message, if any
+    status_timeout: RefCell<Option<glib::SourceId>>,
+
+    // Timeout for reminding the user of the running recording, if any
+    record_reminder: RefCell<Option<glib::SourceId>>,
+
+    // Timeout for updating the elapsed recording time, if any
+    record_elapsed_timeout: RefCell<Option<glib::SourceId>>,
+
+    // Timeout for updating the frame rate and bitrate statistics, if they are shown
+    stats_timeout: RefCell<Option<glib::SourceId>>,
+
+    // Recording should be started once the first frame arrives after launching. This is only
+    // armed once when the application is created, and not again when it is activated later
+    auto_record_pending: Cell<bool>,
+
+    // Whether the current pipeline already delivered frames from the camera
+    receiving_frames: Cell<bool>,
+    // Captures requested on the command line before frames were flowing
+    command_line_pending: Cell<Option<CommandLineOptions>>,
+    // Length in seconds of the recording requested on the command line, until it is started
+    command_line_record_secs: Cell<Option<u32>>,
+    // Incremented for every recording that is started, to tell them apart
+    recording_id: Cell<u64>,
+
+    review_state: Cell<ReviewState>,
+
+    // The preview is hidden while recording to save power
+    preview_minimized: Cell<bool>,
+
+    // Number of chapters marked in the current recording
+    chapter_count: Cell<u32>,
+
+    // Whether the user was already warned about low disk space during the current recording
+    low_disk_space_warned: Cell<bool>,
+
+    // Reloads the user style-sheet whenever it changes, only kept around to keep it alive
+    _style_monitor: Option<gio::FileMonitor>,
+
+    // Shared screen or window that is captured instead of the camera, if any
+    #[cfg(target_os = "linux")]
+    screencast: RefCell<Option<Screencast>>,
+
+    // The last finished recording, for playing it back
+    last_recording: RefCell<Option<PathBuf>>,
+
+    // Settings as last loaded or saved, so that the settings file is only read again once it was
+    // changed
+    settings: RefCell<Settings>,
+    // Reloads the settings whenever the settings file is changed by someone else
+    settings_monitor: RefCell<Option<gio::FileMonitor>>,
+    // Timeout for reloading the settings once the settings file stopped changing, if any
+    settings_reload_timeout: RefCell<Option<glib::SourceId>>,
+
+    // Failed attempts of reconnecting the camera since it last worked
+    reconnect_attempts: Cell<u32>,
+    // Timeout for the next attempt of reconnecting the camera, if any
+    reconnect_timeout: RefCell<Option<glib::SourceId>>,
+
+    // Whether the window is fullscreen, and its size and position while it is neither fullscreen
+    // nor maximized. This is restored when leaving fullscreen and stored when shutting down
+    fullscreen: Cell<bool>,
+    windowed_size: Cell<(i32, i32)>,
+    window_position: Cell<Option<(i32, i32)>>,
+    window_maximized: Cell<bool>,
+}
+
+// Errors that can happen while starting the application
+#[derive(Debug)]
+pub enum AppStartupError {
+    // There is no camera at all right now. The application starts with a placeholder instead and
+    // uses the camera once one is plugged in
+    NoCamera,
+    // The camera pipeline could not be created, e.g. because a plugin or encoder is missing. The
+    // application can still start with a placeholder instead of the camera
+    Pipeline(Box<dyn error::Error>),
+    // The application can't run at all, e.g. because there is no display or not even the
+    // placeholder works
+    Fatal(Box<dyn error::Error>),
+}
+
+impl AppStartupError {
+    // Classify an error from creating the camera pipeline
+    fn from_pipeline_error(err: Box<dyn error::Error>) -> Self {
+        if err.is::<pipeline::NoCameraError>() {
+            AppStartupError::NoCamera
+        } else {
+            AppStartupError::Pipeline(err)
+        }
+    }
+
+    // Check if the application can still start in a degraded state
+    pub fn is_recoverable(&self) -> bool {
+        match *self {
+            AppStartupError::NoCamera | AppStartupError::Pipeline(_) => true,
+            AppStartupError::Fatal(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for AppStartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AppStartupError::NoCamera => write!(f, "{}", pipeline::NoCameraError),
+            AppStartupError::Pipeline(ref err) => write!(f, "Error creating pipeline: {}", err),
+            AppStartupError::Fatal(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for AppStartupError {}
+
+// Review mode for looking at single frames of the preview, controlled via the keyboard
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ReviewState {
+    // The preview shows the live camera image
+    Live,
+    // The preview is frozen at a single frame. Recording continues unaffected
+    Frozen,
+}
+
+// All the ways how snapshots can be taken
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SnapshotRequest {
+    // Take a single snapshot right now
+    Immediate,
+    // Count down the given number of seconds and then take a single snapshot
+    Timed(u32),
+    // Count down the given number of seconds and then take the given number of snapshots, the
+    // given number of milliseconds apart
+    Burst {
+        count: u32,
+        interval_ms: u32,
+        countdown: u32,
+    },
+    // Take a snapshot every given number of seconds until cancelled
+    Interval(u32),
+}
+
+impl SnapshotRequest {
+    // The request configured by the user in the settings
+    //
+    // A burst also starts after the countdown of the timer
+    fn from_settings(settings: &Settings) -> Self {
+        if settings.burst_count > 1 {
+            SnapshotRequest::Burst {
+                count: settings.burst_count,
+                interval_ms: settings.burst_interval_ms.max(1),
+                countdown: settings.timer_length,
+            }
+        } else if settings.timer_length == 0 {
+            SnapshotRequest::Immediate
+        } else {
+            SnapshotRequest::Timed(settings.timer_length)
+        }
+    }
+
+    // Interval between the ticks of the snapshot timer, if any is needed
+    fn tick_interval_ms(self) -> Option<u32> {
+        match self {
+            SnapshotRequest::Immediate => None,
+            SnapshotRequest::Timed(0) => None,
+            SnapshotRequest::Timed(_) => Some(1000),
+            SnapshotRequest::Burst { countdown, .. } if countdown > 0 => Some(1000),
+            SnapshotRequest::Burst { count, .. } if count <= 1 => None,
+            SnapshotRequest::Burst { interval_ms, .. } => Some(interval_ms),
+            SnapshotRequest::Interval(secs) => Some(secs.max(1) * 1000),
+        }
+    }
+}
+
+// What has to happen on a tick of the snapshot timer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SnapshotTick {
+    // Show the remaining seconds of the countdown
+    Countdown(u32),
+    // Take a snapshot, with the given number of snapshots remaining afterwards if limited
+    Capture(Option<u32>),
+    // Take the last snapshot and stop the timer
+    CaptureLast,
+    // The countdown before a burst is over, the burst itself is started with a new timer
+    StartBurst,
 }
 
 // Helper struct for the snapshot timer
 //
-// Allows counting down and removes the timeout source on Drop
+// Keeps track of the state of a snapshot request and removes the timeout source on Drop
 struct SnapshotTimer {
+    request: SnapshotRequest,
+    // Remaining seconds of the countdown or remaining number of snapshots of a burst
     remaining: u32,
     // This needs to be Option because we need to be able to take
     // the value out in Drop::drop() removing the timeout id
@@ -75,18 +378,45 @@ struct SnapshotTimer {
 }
 
 impl SnapshotTimer {
-    fn new(remaining: u32, timeout_id: glib::SourceId) -> Self {
+    // Create a new timer for the request. Without countdown, the first snapshot of a burst is
+    // expected to be already taken at this point
+    fn new(request: SnapshotRequest, timeout_id: glib::SourceId) -> Self {
+        let remaining = match request {
+            SnapshotRequest::Immediate | SnapshotRequest::Interval(_) => 0,
+            SnapshotRequest::Timed(secs) => secs,
+            SnapshotRequest::Burst { countdown, .. } if countdown > 0 => countdown,
+            SnapshotRequest::Burst { count, .. } => count - 1,
+        };
+
         Self {
+            request,
             remaining,
             timeout_id: Some(timeout_id),
         }
     }
 
-    fn tick(&mut self) -> u32 {
-        assert!(self.remaining > 0);
-        self.remaining -= 1;
-
-        self.remaining
+    fn tick(&mut self) -> SnapshotTick {
+        match self.request {
+            SnapshotRequest::Immediate => SnapshotTick::CaptureLast,
+            SnapshotRequest::Interval(_) => SnapshotTick::Capture(None),
+            SnapshotRequest::Timed(_) | SnapshotRequest::Burst { .. } => {
+                assert!(self.remaining > 0);
+                self.remaining -= 1;
+
+                match self.request {
+                    SnapshotRequest::Burst { countdown, .. } if countdown > 0 => {
+                        if self.remaining == 0 {
+                            SnapshotTick::StartBurst
+                        } else {
+                            SnapshotTick::Countdown(self.remaining)
+                        }
+                    }
+                    _ if self.remaining == 0 => SnapshotTick::CaptureLast,
+                    SnapshotRequest::Timed(_) => SnapshotTick::Countdown(self.remaining),
+                    _ => SnapshotTick::Capture(Some(self.remaining)),
+                }
+            }
+        }
     }
 }
 
@@ -161,27 +491,63 @@ pub enum Action {
     Quit,
     Settings,
     About,
+    Shortcuts,
     Snapshot(SnapshotState),
     Record(RecordState),
+    Reset,
+    // Only registered on Linux, where screens are shared via the desktop portal
+    ShareScreen,
+    PlayRecording,
+    SnapshotClipboard,
+    // Whether the running recording drops frames if the encoder or storage can't keep up
+    DropFrames(bool),
+    // Whether the running recording is paused
+    PauseRecording(bool),
+    // Whether the window is fullscreen
+    Fullscreen(bool),
+    // Whether the rule-of-thirds grid is shown
+    ShowGrid(bool),
+    // Whether the frame rate and bitrate statistics are shown
+    ShowStats(bool),
+    // Show the folder of a saved file in the file manager, the file is passed as parameter. Without
+    // a file, i.e. an empty parameter, the snapshot directory is shown
+    OpenFolder,
+    ExportSettings,
+    ImportSettings,
 }
 
 impl App {
-    fn new(application: &gtk::Application) -> Result<App, Box<dyn error::Error>> {
+    // Create the application with the camera pipeline, or with the placeholder pipeline if the
+    // camera pipeline failed before
+    fn new(application: &gtk::Application, placeholder: bool) -> Result<App, AppStartupError> {
+        // Create the pipeline first and if that fails return, before any window exists
+        let settings = utils::load_settings();
+        let pipeline = Self::create_pipeline(placeholder, &settings)?;
+
         // Here build the UI but don't show it yet
         let window = gtk::ApplicationWindow::new(application);
 
         window.set_title("WebCam Viewer");
         window.set_border_width(5);
-        window.set_position(gtk::WindowPosition::Center);
-        window.set_default_size(840, 480);
+
+        // Restore the window like it was when it was last closed. The monitors might have changed
+        // since then, so make sure that the window is not off-screen
+        window.set_default_size(settings.window_width, settings.window_height);
+        match settings.window_position {
+            Some((x, y)) => {
+                let (x, y) =
+                    clamp_window_position(x, y, settings.window_width, settings.window_height);
+                window.move_(x, y);
+            }
+            None => window.set_position(gtk::WindowPosition::Center),
+        }
+        if settings.window_maximized {
+            window.maximize();
+        }
 
         // Create headerbar for the application window
         let header_bar = HeaderBar::new(&window);
 
-        // Create the pipeline and if that fail return
-        let pipeline =
-            Pipeline::new().map_err(|err| format!("Error creating pipeline: {:?}", err))?;
-
         // Create an overlay for showing the seconds until a snapshot
         // This is hidden while we're not doing a countdown
         let overlay = Overlay::new(&window, &pipeline.get_widget());
@@ -190,10 +556,124 @@ impl App {
             main_window: window,
             header_bar,
             overlay,
-            pipeline,
+            pipeline: RefCell::new(pipeline),
             timer: RefCell::new(None),
+            http_preview: RefCell::new(None),
+            status_timeout: RefCell::new(None),
+            record_reminder: RefCell::new(None),
+            record_elapsed_timeout: RefCell::new(None),
+            stats_timeout: RefCell::new(None),
+            // Only start recording once frames are flowing to not get an empty file
+            auto_record_pending: Cell::new(settings.auto_record_on_start),
+            receiving_frames: Cell::new(false),
+            command_line_pending: Cell::new(None),
+            command_line_record_secs: Cell::new(None),
+            recording_id: Cell::new(0),
+            review_state: Cell::new(ReviewState::Live),
+            preview_minimized: Cell::new(false),
+            chapter_count: Cell::new(0),
+            low_disk_space_warned: Cell::new(false),
+            _style_monitor: Self::load_user_style(),
+            #[cfg(target_os = "linux")]
+            screencast: RefCell::new(None),
+            last_recording: RefCell::new(None),
+            settings: RefCell::new(settings.clone()),
+            settings_monitor: RefCell::new(None),
+            settings_reload_timeout: RefCell::new(None),
+            reconnect_attempts: Cell::new(0),
+            reconnect_timeout: RefCell::new(None),
+            fullscreen: Cell::new(false),
+            windowed_size: Cell::new((settings.window_width, settings.window_height)),
+            window_position: Cell::new(settings.window_position),
+            window_maximized: Cell::new(settings.window_maximized),
         }));
 
+        app.watch_settings_file();
+
+        app.connect_pipeline_events(&app.pipeline());
+
+        // Closing the window would quit the application, so ask first if a recording is running
+        // and finish the recording before quitting
+        let app_weak = app.downgrade();
+        app.main_window
+            .connect_delete_event(move |_window, _event| {
+                let app = upgrade_weak!(app_weak, gtk::Inhibit(false));
+                gtk::Inhibit(app.on_close_requested())
+            });
+
+        // Space freezes the preview for reviewing single frames, M marks a chapter while recording
+        // and Escape leaves fullscreen
+        let app_weak = app.downgrade();
+        app.main_window
+            .connect_key_press_event(move |_window, event| {
+                let app = upgrade_weak!(app_weak, gtk::Inhibit(false));
+                let keyval = event.get_keyval();
+                if keyval == gdk::enums::key::m {
+                    gtk::Inhibit(app.mark_chapter())
+                } else if keyval == gdk::enums::key::Escape && app.fullscreen.get() {
+                    let application =
+                        gio::Application::get_default().expect("No default application");
+                    Action::Fullscreen(false).trigger(&application);
+                    gtk::Inhibit(true)
+                } else {
+                    gtk::Inhibit(app.on_review_key(keyval))
+                }
+            });
+
+        // Remember the size and position of the normal window for restoring it later
+        let app_weak = app.downgrade();
+        app.main_window
+            .connect_configure_event(move |window, _event| {
+                let app = upgrade_weak!(app_weak, false);
+                let maximized_or_fullscreen = window
+                    .get_window()
+                    .map(|w| {
+                        w.get_state()
+                            .intersects(gdk::WindowState::MAXIMIZED | gdk::WindowState::FULLSCREEN)
+                    })
+                    .unwrap_or(false);
+                if !maximized_or_fullscreen && !app.fullscreen.get() {
+                    app.windowed_size.set(window.get_size());
+                    app.window_position.set(Some(window.get_position()));
+                }
+                false
+            });
+
+        // The window manager can also leave fullscreen on its own, keep the action in sync then
+        let app_weak = app.downgrade();
+        app.main_window
+            .connect_window_state_event(move |_window, event| {
+                let app = upgrade_weak!(app_weak, gtk::Inhibit(false));
+                app.window_maximized.set(
+                    event
+                        .get_new_window_state()
+                        .contains(gdk::WindowState::MAXIMIZED),
+                );
+
+                let fullscreen = event
+                    .get_new_window_state()
+                    .contains(gdk::WindowState::FULLSCREEN);
+                if !fullscreen
+                    && event
+                        .get_changed_mask()
+                        .contains(gdk::WindowState::FULLSCREEN)
+                {
+                    let application =
+                        gio::Application::get_default().expect("No default application");
+                    Action::Fullscreen(false).trigger(&application);
+                }
+                gtk::Inhibit(false)
+            });
+
+        // Keep the free disk space in the header bar up to date
+        app.update_disk_space();
+        let app_weak = app.downgrade();
+        gtk::timeout_add_seconds(DISK_SPACE_UPDATE_INTERVAL_SECS, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+            app.update_disk_space();
+            glib::Continue(true)
+        });
+
         // Create the application actions
         Action::create(&app, &application);
 
@@ -205,21 +685,62 @@ impl App {
         AppWeak(Rc::downgrade(&self.0))
     }
 
-    pub fn on_startup(application: &gtk::Application) {
-        // Load our custom CSS style-sheet and set it as the application specific style-sheet for
-        // this whole application
+    // Get the current settings
+    //
+    // These are only read from the settings file again once it changed
+    pub fn settings(&self) -> Settings {
+        self.settings.borrow().clone()
+    }
+
+    // Save the settings, which only become the current settings if writing them succeeded
+    pub fn save_settings(&self, settings: Settings) {
+        if utils::save_settings(&settings) {
+            self.set_current_settings(settings);
+        }
+    }
+
+    // Replace the cached settings, also those of the pipeline that are used for the next snapshots
+    // and recordings
+    fn set_current_settings(&self, settings: Settings) {
+        self.pipeline().set_settings(&settings);
+        *self.settings.borrow_mut() = settings;
+    }
+
+    // Load our custom CSS style-sheet and set it as the application specific style-sheet for
+    // this whole application
+    fn load_style() -> Result<(), AppStartupError> {
+        let screen = gdk::Screen::get_default()
+            .ok_or_else(|| AppStartupError::Fatal("No display available".into()))?;
+
         let provider = gtk::CssProvider::new();
         provider
             .load_from_data(STYLE.as_bytes())
-            .expect("Failed to load CSS");
+            .map_err(|err| AppStartupError::Fatal(format!("Failed to load CSS: {}", err).into()))?;
         gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::get_default().expect("Error initializing gtk css provider."),
+            &screen,
             &provider,
             gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
         );
 
-        // Create application and error out if that fails for whatever reason
-        let app = match App::new(application) {
+        Ok(())
+    }
+
+    pub fn on_startup(application: &gtk::Application) {
+        // Create application and error out if that fails for whatever reason. If the camera
+        // pipeline fails, e.g. because of a missing plugin or because there is no camera, we
+        // still start but with a placeholder, and resetting the camera tries again
+        let res = Self::load_style().and_then(|_| {
+            App::new(application, false).or_else(|err| {
+                if !err.is_recoverable() {
+                    return Err(err);
+                }
+
+                let app = App::new(application, true)?;
+                app.show_pipeline_error(&err);
+                Ok(app)
+            })
+        });
+        let app = match res {
             Ok(app) => app,
             Err(err) => {
                 utils::show_error_dialog(
@@ -238,6 +759,31 @@ impl App {
             app.on_activate();
         });
 
+        // The command line of the first process, and of every later process, is handled here by
+        // the first process. Each process already checked its options itself
+        let app_weak = app.downgrade();
+        application.connect_command_line(move |_, command_line| {
+            let app = upgrade_weak!(app_weak, 1);
+
+            let args = command_line
+                .get_arguments()
+                .into_iter()
+                .skip(1)
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            let options = CommandLineOptions::parse(&args).unwrap_or_default();
+
+            // Everything is already running if this comes from another process
+            if command_line.get_is_remote() {
+                app.present();
+            } else {
+                app.on_activate();
+            }
+            app.on_command_line(options);
+
+            0
+        });
+
         // When the application is shut down we drop our app struct
         //
         // It has to be stored in a RefCell<Option<T>> to be able to pass it to a Fn closure. With
@@ -253,139 +799,1817 @@ impl App {
         });
     }
 
+    // Load the user's style-sheet from the config directory on top of our own style-sheet
+    //
+    // The style-sheet is reloaded whenever it changes, for which the returned monitor has to be
+    // kept alive
+    fn load_user_style() -> Option<gio::FileMonitor> {
+        let path = utils::get_style_file_path();
+
+        let provider = gtk::CssProvider::new();
+        gtk::StyleContext::add_provider_for_screen(
+            &gdk::Screen::get_default()?,
+            &provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+
+        let load = move |provider: &gtk::CssProvider| {
+            if !path.is_file() {
+                let _ = provider.load_from_data(b"");
+                return;
+            }
+
+            // Keep only our own style if the user's style-sheet is broken
+            if let Err(err) = provider.load_from_path(&path.to_string_lossy()) {
+                println!("Failed to load style-sheet {}: {}", path.display(), err);
+                let _ = provider.load_from_data(b"");
+            }
+        };
+        load(&provider);
+
+        let monitor = gio::File::new_for_path(utils::get_style_file_path())
+            .monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+            .ok()?;
+        monitor.connect_changed(move |_monitor, _file, _other_file, event| match event {
+            gio::FileMonitorEvent::ChangesDoneHint
+            | gio::FileMonitorEvent::Created
+            | gio::FileMonitorEvent::Deleted => load(&provider),
+            _ => (),
+        });
+
+        Some(monitor)
+    }
+
+    // Re-apply the settings whenever the settings file changes, e.g. by a script
+    //
+    // Saving from our own settings dialog also ends up here, which is harmless as applying the
+    // same settings again does nothing
+    fn watch_settings_file(&self) {
+        let monitor = match gio::File::new_for_path(utils::get_settings_file_path())
+            .monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>)
+        {
+            Ok(monitor) => monitor,
+            Err(err) => {
+                println!("Failed to watch settings file: {}", err);
+                return;
+            }
+        };
+
+        let app_weak = self.downgrade();
+        monitor.connect_changed(move |_monitor, _file, _other_file, event| match event {
+            gio::FileMonitorEvent::ChangesDoneHint
+            | gio::FileMonitorEvent::Created
+            | gio::FileMonitorEvent::Deleted => {
+                let app = upgrade_weak!(app_weak);
+                app.schedule_settings_reload();
+            }
+            _ => (),
+        });
+
+        *self.settings_monitor.borrow_mut() = Some(monitor);
+    }
+
+    // Reload the settings a bit later, so that a burst of changes only reloads them once
+    fn schedule_settings_reload(&self) {
+        if let Some(timeout_id) = self.settings_reload_timeout.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add(SETTINGS_RELOAD_DELAY_MS, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            let _ = app.settings_reload_timeout.borrow_mut().take();
+
+            // Everything else, like sounds or the snapshot format, is taken from the settings
+            // whenever it is needed and picks up the new values automatically
+            app.set_current_settings(utils::load_settings());
+            app.apply_settings();
+
+            glib::Continue(false)
+        });
+        *self.settings_reload_timeout.borrow_mut() = Some(timeout_id);
+    }
+
     // Called on the first application instance whenever the first application instance is started,
     // or any future second application instance
     fn on_activate(&self) {
-        // Show our window and bring it to the foreground
+        self.present();
+        self.start_pipeline();
+    }
+
+    // Show our window and bring it to the foreground
+    fn present(&self) {
         self.main_window.show_all();
 
         // Have to call this instead of present() because of
         // https://gitlab.gnome.org/GNOME/gtk/issues/624
         self.main_window
             .present_with_time((glib::get_monotonic_time() / 1000) as u32);
+    }
+
+    // Take the captures that were requested on the command line
+    //
+    // Right after launching they only happen once the camera delivers frames. A recording of a
+    // given length is stopped again afterwards, unless it was stopped already. A recording that
+    // is already running is left alone
+    fn on_command_line(&self, options: CommandLineOptions) {
+        if !self.receiving_frames.get() {
+            self.command_line_pending.set(Some(options));
+            return;
+        }
+
+        if options.snapshot {
+            self.header_bar.set_snapshot_active(true);
+        }
+
+        if let Some(secs) = options.record_secs {
+            let pipeline = self.pipeline();
+            if pipeline.is_recording() || pipeline.is_stopping_recording() {
+                println!("Already recording, ignoring --record");
+            } else {
+                // The timeout is only started once the recording really started, see
+                // start_recording_now()
+                self.command_line_record_secs.set(Some(secs));
+                self.header_bar.set_record_active(true);
+            }
+        }
+    }
+
+    // Stop the recording with the given id after the given number of seconds, unless it was
+    // stopped already
+    fn stop_recording_after(&self, recording_id: u64, secs: u32) {
+        let app_weak = self.downgrade();
+        gtk::timeout_add_seconds(secs, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+            if app.recording_id.get() == recording_id && app.pipeline().is_recording() {
+                app.header_bar.set_record_active(false);
+            }
+            glib::Continue(false)
+        });
+    }
+
+    // Start the GStreamer pipeline and apply all settings to it
+    fn start_pipeline(&self) {
+        let pipeline = self.pipeline();
 
         // Once the UI is shown, start the GStreamer pipeline. If
         // an error happens, we immediately shut down
-        if let Err(err) = self.pipeline.start() {
+        if let Err(err) = pipeline.start() {
             utils::show_error_dialog(
                 true,
                 format!("Failed to set pipeline to playing: {}", err).as_str(),
             );
+            return;
+        }
+
+        // Lock the exposure right from the beginning if configured. Cameras without exposure
+        // controls are silently ignored as the setting is not shown for them anyway
+        let settings = self.settings();
+        if settings.exposure_lock == ExposureLock::Always && self.supports_exposure_lock() {
+            if let Err(err) = pipeline.set_exposure_locked(true) {
+                utils::show_error_dialog(
+                    false,
+                    format!("Failed to lock exposure: {}", err).as_str(),
+                );
+            }
         }
+
+        self.apply_settings();
     }
 
-    // Called when the application shuts down. We drop our app struct here
-    fn on_shutdown(self) {
-        // This might fail but as we shut down right now anyway this doesn't matter
-        // TODO: If a recording is currently running we would like to finish that first
-        // before quitting the pipeline and shutting down the pipeline.
-        let _ = self.pipeline.stop();
+    // Get the current pipeline
+    //
+    // This is only a cheap reference counted clone and ensures that we don't keep the pipeline
+    // borrowed while it could be replaced
+    fn pipeline(&self) -> Pipeline {
+        self.pipeline.borrow().clone()
     }
 
-    // When the snapshot button is clicked it triggers the snapshot action, which calls this
-    // function here. We have to stop an existing timer here, start a new timer or immediately
-    // snapshot.
-    fn on_snapshot_state_changed(&self, new_state: SnapshotState) {
-        let settings = utils::load_settings();
+    // Stop everything and start again with a new pipeline, e.g. if the camera got stuck
+    //
+    // A running recording is stopped first but only given a few seconds to be finalized before
+    // the old pipeline is shut down. This also goes back from a shared screen or window to the
+    // camera
+    pub fn reset(&self) {
+        #[cfg(target_os = "linux")]
+        self.switch_source(None);
+        #[cfg(not(target_os = "linux"))]
+        self.restart(|_app| ());
+    }
+
+    // Stop everything and restart with a new pipeline for the camera, or for the screencast if
+    // any
+    #[cfg(target_os = "linux")]
+    fn switch_source(&self, screencast: Option<Screencast>) {
+        self.restart(move |app| {
+            // The previous screencast is only closed once its pipeline is shut down
+            let _previous_screencast = app.screencast.replace(screencast);
+        });
+    }
 
-        // Stop snapshot timer, if any, and return
-        if new_state == SnapshotState::Idle {
-            let _ = self.timer.borrow_mut().take();
-            self.overlay.set_label_visible(false);
+    // Stop everything and restart with a new pipeline, calling the function right before the
+    // new pipeline is built
+    fn restart<F: FnOnce(&App) + 'static>(&self, before_rebuild: F) {
+        println!("Resetting");
 
-            return;
-        }
+        // Stop the snapshot timer, if any
+        self.cancel_snapshot();
 
-        if settings.timer_length == 0 {
-            // Take a snapshot immediately if there's no timer length or start the timer
+        // Unchecking the buttons triggers the actions, which for recording stops the recording
+        self.header_bar.set_snapshot_active(false);
+        self.header_bar.set_record_active(false);
 
-            // Set the togglebutton unchecked again immediately
-            self.header_bar.set_snapshot_active(false);
+        self.when_recording_finalized(move |app| {
+            before_rebuild(app);
+            app.rebuild_pipeline();
+        });
+    }
 
-            if let Err(err) = self.pipeline.take_snapshot() {
-                utils::show_error_dialog(
+    // Let the user pick a screen or window via the desktop portal and capture that instead of
+    // the camera
+    #[cfg(target_os = "linux")]
+    fn request_screencast(&self) {
+        let app_weak = self.downgrade();
+        capture_portal::request_screencast(move |res| {
+            let app = upgrade_weak!(app_weak);
+
+            match res {
+                Ok(screencast) => app.switch_source(Some(screencast)),
+                Err(PortalError::Cancelled) => {
+                    app.show_status_message("Screen sharing was cancelled")
+                }
+                Err(err) => utils::show_error_dialog(
                     false,
-                    format!("Failed to take snapshot: {}", err).as_str(),
-                );
+                    format!("Failed to share screen: {}", err).as_str(),
+                ),
             }
-        } else {
-            // Start a snapshot timer
+        });
+    }
 
-            // Make the overlay visible, remember how much we have to count down and start our
-            // timeout for the timer
-            self.overlay.set_label_visible(true);
-            self.overlay
-                .set_label_text(&settings.timer_length.to_string());
+    // Call the function once a stopped recording is finalized, or directly if there is none
+    //
+    // Finalizing is only given a few seconds before the function is called anyway
+    fn when_recording_finalized<F: FnOnce(&App) + 'static>(&self, func: F) {
+        let pipeline = self.pipeline();
+        if !pipeline.is_stopping_recording() {
+            func(self);
+            return;
+        }
 
-            let app_weak = self.downgrade();
-            // The closure is called every 1000ms
-            let timeout_id = gtk::timeout_add(1000, move || {
-                let app = upgrade_weak!(app_weak, glib::Continue(false));
-
-                let remaining = app
-                    .timer
-                    .borrow_mut()
-                    .as_mut()
-                    .map(|t| t.tick())
-                    .unwrap_or(0);
-
-                if remaining == 0 {
-                    // Set the togglebutton unchecked again and make the overlay text invisible
-                    app.overlay.set_label_visible(false);
-
-                    // Remove timer
-                    let _ = app.timer.borrow_mut().take();
-
-                    // This directly calls the surrounding function again and then removes the
-                    // timer
-                    app.header_bar.set_snapshot_active(false);
+        // Check every 100ms if the recording is finalized, and otherwise continue anyway once the
+        // timeout is reached
+        //
+        // The function has to be stored in an Option to be able to call the FnOnce from the
+        // FnMut closure
+        let mut func = Some(func);
+        let mut remaining = RECORDING_FINALIZE_TIMEOUT_MS / 100;
+        let app_weak = self.downgrade();
+        gtk::timeout_add(100, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            if pipeline.is_stopping_recording() && remaining > 0 {
+                remaining -= 1;
+                return glib::Continue(true);
+            }
 
-                    if let Err(err) = app.pipeline.take_snapshot() {
-                        utils::show_error_dialog(
-                            false,
-                            format!("Failed to take snapshot: {}", err).as_str(),
-                        );
-                    }
+            let func = func.take().expect("Called multiple times");
+            func(&app);
 
-                    glib::Continue(false)
-                } else {
-                    app.overlay.set_label_text(&remaining.to_string());
-                    glib::Continue(true)
-                }
-            });
+            glib::Continue(false)
+        });
+    }
 
-            *self.timer.borrow_mut() = Some(SnapshotTimer::new(settings.timer_length, timeout_id));
+    // Quit the application, but if a recording is running ask the user first and finalize the
+    // recording before quitting
+    pub fn request_quit(&self) {
+        if !self.pipeline().is_recording() {
+            self.quit();
+            return;
         }
-    }
 
-    // When the record button is clicked it triggers the record action, which will call this.
-    // We have to start or stop recording here
-    fn on_record_state_changed(&self, new_state: RecordState) {
-        // Start/stop recording based on button active'ness
-        match new_state {
-            RecordState::Recording => {
-                if let Err(err) = self.pipeline.start_recording() {
-                    utils::show_error_dialog(
+        let settings = self.settings();
+        if !settings.confirm_quit_while_recording {
+            self.stop_recording_and_quit();
+            return;
+        }
+
+        let app_weak = self.downgrade();
+        utils::show_confirmation_dialog(
+            "A recording is in progress. Stop and quit?",
+            "Stop and quit",
+            move |confirmed, dont_ask_again| {
+                let app = upgrade_weak!(app_weak);
+
+                if !confirmed {
+                    return;
+                }
+
+                if dont_ask_again {
+                    let mut settings = app.settings();
+                    settings.confirm_quit_while_recording = false;
+                    app.save_settings(settings);
+                }
+
+                app.stop_recording_and_quit();
+            },
+        );
+    }
+
+    // Handle the window being closed according to the settings, returns true if the window
+    // should stay open
+    //
+    // The window is only hidden while recording, and the application quits once that recording
+    // is finished. Without a recording there is nothing to keep running and closing quits
+    fn on_close_requested(&self) -> bool {
+        let settings = self.settings();
+
+        match settings.close_action {
+            CloseAction::Quit if self.pipeline().is_recording() => {
+                self.request_quit();
+                true
+            }
+            CloseAction::Quit => false,
+            CloseAction::Minimize => {
+                self.main_window.iconify();
+                true
+            }
+            CloseAction::Background if self.pipeline().is_recording() => {
+                self.main_window.hide();
+                true
+            }
+            CloseAction::Background => false,
+        }
+    }
+
+    // Check if the window was closed and hidden while recording
+    fn is_in_background(&self) -> bool {
+        !self.main_window.is_visible()
+    }
+
+    fn stop_recording_and_quit(&self) {
+        // Unchecking the button triggers the action, which stops the recording
+        self.header_bar.set_record_active(false);
+        self.when_recording_finalized(|app| app.quit());
+    }
+
+    fn quit(&self) {
+        if let Some(application) = self.main_window.get_application() {
+            application.quit();
+        }
+    }
+
+    // Create the camera pipeline, or the placeholder pipeline that is used if the camera pipeline
+    // can't be created
+    fn create_pipeline(
+        placeholder: bool,
+        settings: &Settings,
+    ) -> Result<Pipeline, AppStartupError> {
+        if placeholder {
+            Pipeline::new_placeholder(settings).map_err(AppStartupError::Fatal)
+        } else {
+            Pipeline::new(settings).map_err(AppStartupError::from_pipeline_error)
+        }
+    }
+
+    // Create the pipeline for the current source, which is the shared screen or window if any and
+    // otherwise the camera
+    fn create_source_pipeline(&self) -> Result<Pipeline, AppStartupError> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ref screencast) = *self.screencast.borrow() {
+                return Pipeline::new_screencast(screencast, &self.settings())
+                    .map_err(AppStartupError::Pipeline);
+            }
+        }
+
+        Self::create_pipeline(false, &self.settings())
+    }
+
+    // Check if a shared screen or window is captured instead of the camera
+    fn is_screencast(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            self.screencast.borrow().is_some()
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            false
+        }
+    }
+
+    // Let the user know why only the placeholder is shown instead of the camera
+    //
+    // Without any camera, the camera is used as soon as one is plugged in
+    fn show_pipeline_error(&self, err: &AppStartupError) {
+        println!("{}", err);
+
+        if let AppStartupError::NoCamera = *err {
+            self.overlay
+                .set_status_text(Some(&format!("{}\nWaiting for a camera…", err)));
+            self.wait_for_camera();
+        } else {
+            self.overlay
+                .set_status_text(Some(&format!("{}\nReset the camera to try again", err)));
+        }
+    }
+
+    // Check regularly if a camera was plugged in and then rebuild the pipeline with it
+    //
+    // This uses the reconnect timeout, so that it is cancelled whenever the pipeline is rebuilt
+    // for any other reason
+    fn wait_for_camera(&self) {
+        self.cancel_reconnect();
+
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add_seconds(NO_CAMERA_RETRY_INTERVAL_SECS, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            if Pipeline::list_video_devices().is_empty() {
+                return glib::Continue(true);
+            }
+
+            let _ = app.reconnect_timeout.borrow_mut().take();
+            app.rebuild_pipeline();
+
+            glib::Continue(false)
+        });
+        *self.reconnect_timeout.borrow_mut() = Some(timeout_id);
+    }
+
+    // Shut down the current pipeline and replace it with a new one
+    fn rebuild_pipeline(&self) {
+        // This replaces any pending attempt of reconnecting the camera
+        self.cancel_reconnect();
+
+        // Setting the old pipeline to Null forcefully shuts down everything, including any
+        // recording that is still stuck
+        let _ = self.http_preview.borrow_mut().take();
+        let _ = self.pipeline().stop();
+
+        // Fall back to the placeholder like when starting
+        let pipeline = self
+            .create_source_pipeline()
+            .map(|pipeline| (pipeline, None))
+            .or_else(|err| {
+                if !err.is_recoverable() {
+                    return Err(err);
+                }
+
+                Self::create_pipeline(true, &self.settings()).map(|pipeline| (pipeline, Some(err)))
+            });
+        let (pipeline, pipeline_error) = match pipeline {
+            Ok(res) => res,
+            Err(err) => {
+                utils::show_error_dialog(true, &err.to_string());
+                return;
+            }
+        };
+
+        self.replace_pipeline(pipeline);
+        if let Some(ref err) = pipeline_error {
+            self.show_pipeline_error(err);
+        }
+    }
+
+    // Show and start a new pipeline instead of the current one, which must be shut down already
+    fn replace_pipeline(&self, pipeline: Pipeline) {
+        self.overlay.set_content(&pipeline.get_widget());
+        self.overlay.set_video_size(None);
+        self.overlay.set_status_text(None);
+        self.review_state.set(ReviewState::Live);
+        self.preview_minimized.set(false);
+        self.receiving_frames.set(false);
+        self.connect_pipeline_events(&pipeline);
+        *self.pipeline.borrow_mut() = pipeline;
+
+        self.start_pipeline();
+    }
+
+    // The camera failed, e.g. because it was unplugged. USB cameras often come back after a
+    // moment, so try to reconnect a few times with increasing delays before giving up
+    //
+    // Network cameras drop out regularly, e.g. because of the network, so they are retried forever
+    // instead of giving up
+    fn on_source_error(&self, error: &str) {
+        let _ = self.http_preview.borrow_mut().take();
+
+        let attempts = self.reconnect_attempts.get();
+        let is_network_camera = self.is_network_camera();
+        if attempts >= RECONNECT_MAX_ATTEMPTS && !is_network_camera {
+            self.reconnect_attempts.set(0);
+            utils::show_error_dialog(
+                true,
+                format!("Camera failed and could not be reconnected: {}", error).as_str(),
+            );
+            return;
+        }
+
+        println!("Camera failed, reconnecting: {}", error);
+        self.reconnect_attempts.set(attempts + 1);
+        if is_network_camera {
+            self.overlay.set_status_text(Some(&format!(
+                "Network camera failed: {}\nReconnecting…",
+                error
+            )));
+        } else {
+            self.overlay.set_status_text(Some("Reconnecting camera…"));
+        }
+
+        let delay_ms = RECONNECT_INITIAL_DELAY_MS << attempts.min(RECONNECT_MAX_ATTEMPTS);
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add(delay_ms, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            let _ = app.reconnect_timeout.borrow_mut().take();
+            app.reconnect();
+
+            glib::Continue(false)
+        });
+        *self.reconnect_timeout.borrow_mut() = Some(timeout_id);
+    }
+
+    // Check if the video comes from a network camera instead of a local one
+    fn is_network_camera(&self) -> bool {
+        let pipeline = self.pipeline();
+        !self.is_screencast() && !pipeline.is_custom() && pipeline.get_rtsp_source().is_some()
+    }
+
+    // Try to replace the failed pipeline with a new one for the same camera
+    //
+    // Unlike when rebuilding the pipeline, this does not fall back to the placeholder as long as
+    // there are attempts left
+    fn reconnect(&self) {
+        let pipeline = self.create_source_pipeline();
+
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                self.on_source_error(&err.to_string());
+                return;
+            }
+        };
+
+        // The camera might not be back yet, in which case starting already fails here. Later
+        // errors are reported by the pipeline again
+        if let Err(err) = pipeline.start() {
+            let _ = pipeline.stop();
+            self.on_source_error(&err.to_string());
+            return;
+        }
+
+        self.replace_pipeline(pipeline);
+    }
+
+    // Stop trying to reconnect the camera
+    fn cancel_reconnect(&self) {
+        if let Some(timeout_id) = self.reconnect_timeout.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+        self.reconnect_attempts.set(0);
+    }
+
+    // Handle all events of the pipeline on the application
+    fn connect_pipeline_events(&self, pipeline: &Pipeline) {
+        let app_weak = self.downgrade();
+        pipeline.connect_event(move |event| {
+            let app = upgrade_weak!(app_weak);
+            app.on_pipeline_event(event);
+        });
+
+        // Show the color of the pixel that was clicked on in the preview
+        if !pipeline.is_preview_disabled() {
+            let widget = pipeline.get_widget();
+            widget.add_events(gdk::EventMask::BUTTON_PRESS_MASK);
+
+            let app_weak = self.downgrade();
+            widget.connect_button_press_event(move |_, event| {
+                let app = upgrade_weak!(app_weak, gtk::Inhibit(false));
+
+                let (x, y) = event.get_position();
+                if let Some((x, y, [r, g, b])) = app.pipeline().get_preview_pixel(x, y) {
+                    app.show_status_message(&format!(
+                        "Pixel {}x{}: RGB({}, {}, {}) #{:02x}{:02x}{:02x}",
+                        x, y, r, g, b, r, g, b
+                    ));
+                }
+
+                gtk::Inhibit(false)
+            });
+        }
+    }
+
+    // Show the free space in the record directory, and while recording also how much longer we
+    // can record at the current bitrate
+    fn update_disk_space(&self) {
+        let settings = self.settings();
+
+        let free_space = match utils::get_free_space(&settings.record_directory) {
+            Some(free_space) => free_space,
+            None => {
+                self.header_bar.set_subtitle(None);
+                return;
+            }
+        };
+
+        // Warn once per recording if the disk is getting full. The recording continues until the
+        // disk is really full as the user might be able to free some space in the meantime
+        let min_free_space = u64::from(settings.record_min_free_space) * 1024 * 1024;
+        if self.pipeline().is_recording()
+            && free_space < min_free_space
+            && !self.low_disk_space_warned.get()
+        {
+            self.low_disk_space_warned.set(true);
+            utils::show_error_dialog(
+                false,
+                format!(
+                    "Only {} free in {}, the recording will fail once the disk is full",
+                    utils::format_size(free_space),
+                    settings.record_directory.display()
+                )
+                .as_str(),
+            );
+        }
+
+        let mut text = format!("{} free", utils::format_size(free_space));
+        if let Some(bitrate) = self.pipeline().get_recording_bitrate() {
+            if bitrate > 0.0 {
+                let remaining_mins = (free_space as f64 / bitrate / 60.0) as u64;
+                text.push_str(&format!(
+                    ", about {}:{:02} h of recording left",
+                    remaining_mins / 60,
+                    remaining_mins % 60
+                ));
+            }
+        }
+
+        self.header_bar.set_subtitle(Some(&text));
+    }
+
+    // Show a status message on top of the video for a few seconds
+    fn show_status_message(&self, text: &str) {
+        self.overlay.set_status_text(Some(text));
+
+        // Replace the timeout of any previous message
+        if let Some(timeout_id) = self.status_timeout.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add_seconds(STATUS_MESSAGE_TIMEOUT_SECS, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            let _ = app.status_timeout.borrow_mut().take();
+            app.overlay.set_status_text(None);
+
+            glib::Continue(false)
+        });
+        *self.status_timeout.borrow_mut() = Some(timeout_id);
+    }
+
+    fn on_pipeline_event(&self, event: &PipelineEvent) {
+        match *event {
+            PipelineEvent::Buffering(percent) if percent < 100 => {
+                self.overlay
+                    .set_status_text(Some(&format!("Buffering… {}%", percent)));
+            }
+            PipelineEvent::Buffering(_) => self.overlay.set_status_text(None),
+            // Only uncheck the button, the recording is already gone. The window comes back
+            // if it was in the background so that the error can be seen
+            PipelineEvent::RecordingStopped => {
+                // The button might already be unchecked, so don't rely on the action for this
+                self.stop_record_reminder();
+                self.header_bar.set_record_active(false);
+                if self.is_in_background() {
+                    self.present();
+                }
+            }
+            // Frames were dropped if the encoder or storage could not keep up
+            PipelineEvent::RecordingFinished {
+                ref filename,
+                dropped_frames,
+            } => {
+                self.stop_record_reminder();
+                *self.last_recording.borrow_mut() = Some(filename.clone());
+                self.update_play_recording_action();
+
+                let text = match dropped_frames {
+                    0 => String::from("Recording finished"),
+                    1 => String::from("Recording finished, 1 frame dropped"),
+                    n => format!("Recording finished, {} frames dropped", n),
+                };
+                self.show_status_message(&text);
+                self.send_notification(
+                    "recording-saved",
+                    "Recording saved",
+                    &format!("Saved to {}", filename.display()),
+                    Some(filename.as_path()),
+                );
+
+                // The window was closed while recording, which only kept running for the
+                // recording
+                if self.is_in_background() {
+                    self.quit();
+                }
+            }
+            // Converting large frames can take a moment on slow systems
+            PipelineEvent::SnapshotSaving => self.overlay.set_status_text(Some("Saving…")),
+            PipelineEvent::SnapshotSaved(ref filename) => {
+                self.overlay.set_status_text(self.review_status_text());
+
+                if let Some(ref filename) = *filename {
+                    self.send_notification(
+                        "snapshot-saved",
+                        "Snapshot saved",
+                        &format!("Saved to {}", filename.display()),
+                        Some(filename.as_path()),
+                    );
+                }
+            }
+            // Let the user know which camera and format is used now
+            PipelineEvent::StreamStarted { width, height } => {
+                let pipeline = self.pipeline();
+                if pipeline.is_placeholder() {
+                    return;
+                }
+
+                self.show_status_message(&pipeline.get_stream_description(width, height));
+            }
+            PipelineEvent::SourceError(ref error) => self.on_source_error(error),
+            PipelineEvent::VideoSizeChanged { width, height } => {
+                self.overlay.set_video_size(Some((width, height)));
+            }
+            PipelineEvent::AudioLevel(peak) => self.overlay.set_audio_level(Some(peak)),
+            // Only the level meter is gone, the camera continues
+            PipelineEvent::AudioMonitorFailed(ref error) => {
+                self.overlay.set_audio_level(None);
+                utils::show_error_dialog(
+                    false,
+                    format!("Failed to measure the audio level: {}", error).as_str(),
+                );
+            }
+            PipelineEvent::FirstFrame => {
+                // The camera works (again)
+                self.reconnect_attempts.set(0);
+
+                // Recording the placeholder would only give a black video, so wait until the
+                // camera is back, e.g. after it was plugged in and the pipeline was reset
+                if !self.pipeline().is_placeholder() {
+                    self.receiving_frames.set(true);
+
+                    if self.auto_record_pending.replace(false) {
+                        self.header_bar.set_record_active(true);
+                    }
+                    if let Some(options) = self.command_line_pending.take() {
+                        self.on_command_line(options);
+                    }
+                }
+            }
+        }
+    }
+
+    // Show a desktop notification, unless disabled in the settings
+    //
+    // Notifications with the same id replace each other, e.g. for bursts of snapshots. If a file
+    // is given, the notification has a button for showing its folder
+    fn send_notification(&self, id: &str, title: &str, body: &str, filename: Option<&Path>) {
+        let settings = self.settings();
+        if !settings.notifications_enabled {
+            return;
+        }
+
+        if let Some(application) = self.main_window.get_application() {
+            let notification = gio::Notification::new(title);
+            notification.set_body(Some(body));
+            if let Some(filename) = filename {
+                notification.add_button_with_target_value(
+                    "Open folder",
+                    Action::OpenFolder.full_name(),
+                    Some(&filename.to_string_lossy().to_variant()),
+                );
+            }
+            application.send_notification(Some(id), &notification);
+        }
+    }
+
+    // Apply all settings that have an effect on the running application, e.g. after the settings
+    // dialog was closed
+    pub fn apply_settings(&self) {
+        let settings = self.settings();
+        let pipeline = self.pipeline();
+
+        self.overlay
+            .set_high_contrast(settings.high_contrast_overlay);
+        self.apply_color_balance(&settings);
+        self.set_zoom(settings.zoom);
+        self.pipeline().set_timestamp(
+            settings.burn_timestamp,
+            settings.timestamp_position,
+            settings.timestamp_font_size,
+        );
+        if let Err(err) = self.pipeline().set_watermark(
+            settings.watermark_path.as_ref().map(PathBuf::as_path),
+            settings.watermark_position,
+            settings.watermark_opacity,
+        ) {
+            utils::show_error_dialog(false, &err);
+        }
+        self.header_bar.set_grid_active(settings.show_grid);
+        if let Some(application) = self.main_window.get_application() {
+            Action::ShowStats(settings.show_stats).trigger(&application);
+        }
+
+        // Enabling or disabling the preview or deinterlacing, or changing the camera, its
+        // resolution or framerate or the custom pipeline needs a new pipeline, but we don't want to
+        // interrupt a running recording for that. It will be applied on the next reset instead
+        if (pipeline.is_preview_disabled() != settings.disable_preview
+            || pipeline.get_video_device() != settings.video_device.as_ref().map(String::as_str)
+            || pipeline.get_custom_pipeline()
+                != settings.custom_pipeline.as_ref().map(String::as_str)
+            || pipeline.get_rtsp_source() != settings.get_rtsp_source()
+            || pipeline.is_deinterlacing() != settings.deinterlace
+            || (!pipeline.is_placeholder()
+                && !pipeline.is_custom()
+                && pipeline.get_configured_capture_caps()
+                    != Pipeline::create_capture_caps(&settings).as_ref()))
+            && !pipeline.is_recording()
+            && !pipeline.is_stopping_recording()
+        {
+            self.rebuild_pipeline();
+            return;
+        }
+
+        // Same for the flip, rotating changes the resolution which would break the recording
+        if !pipeline.is_recording() && !pipeline.is_stopping_recording() {
+            pipeline.set_video_flip(settings.video_flip);
+        }
+
+        // Show the level of the audio that would be recorded, so that it can be checked before
+        // recording
+        if let Err(err) = pipeline.set_audio_monitor(settings.record_audio_source) {
+            println!("Failed to measure the audio level: {}", err);
+        }
+        if !pipeline.is_audio_monitored() {
+            self.overlay.set_audio_level(None);
+        }
+
+        // Start, restart or stop the HTTP preview as needed
+        let mut http_preview = self.http_preview.borrow_mut();
+        let address_changed = http_preview
+            .as_ref()
+            .map(|http_preview| {
+                http_preview.get_port() != settings.http_preview_port
+                    || http_preview.is_remote() != settings.http_preview_remote
+            })
+            .unwrap_or(false);
+        if !settings.http_preview_enabled || address_changed {
+            if http_preview.take().is_some() {
+                pipeline.stop_http_preview();
+            }
+        }
+
+        if settings.http_preview_enabled && http_preview.is_none() {
+            let res = pipeline.start_http_preview().and_then(|_| {
+                HttpPreview::new(
+                    settings.http_preview_port,
+                    settings.http_preview_remote,
+                    &pipeline,
+                )
+            });
+
+            match res {
+                Ok(server) => *http_preview = Some(server),
+                Err(err) => {
+                    pipeline.stop_http_preview();
+                    utils::show_error_dialog(
                         false,
-                        format!("Failed to start recording: {}", err).as_str(),
+                        format!("Failed to start HTTP preview: {}", err).as_str(),
                     );
-                    self.header_bar.set_record_active(false);
                 }
             }
-            RecordState::Idle => self.pipeline.stop_recording(),
+        }
+    }
+
+    // Apply the color adjustments of the settings to the preview
+    //
+    // This is cheap and also called while the sliders in the settings dialog are dragged
+    pub fn apply_color_balance(&self, settings: &Settings) {
+        let pipeline = self.pipeline();
+        pipeline.set_brightness(settings.brightness);
+        pipeline.set_contrast(settings.contrast);
+        pipeline.set_saturation(settings.saturation);
+        pipeline.set_hue(settings.hue);
+    }
+
+    // Zoom into the center of the video
+    //
+    // This is cheap and also called while the slider in the settings dialog is dragged
+    pub fn set_zoom(&self, zoom: f64) {
+        self.pipeline().set_zoom(zoom);
+    }
+
+    // Check if the current camera supports locking its exposure
+    pub fn supports_exposure_lock(&self) -> bool {
+        self.pipeline().supports_exposure_lock()
+    }
+
+    // Called when the application shuts down. We drop our app struct here
+    fn on_shutdown(self) {
+        self.finish_recording_on_shutdown();
+
+        // This might fail but as we shut down right now anyway this doesn't matter
+        let _ = self.http_preview.borrow_mut().take();
+        let _ = self.settings_monitor.borrow_mut().take();
+        if let Some(timeout_id) = self.settings_reload_timeout.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+        let _ = self.pipeline().stop();
+
+        // Remember the window for the next start
+        let mut settings = self.settings();
+        let (width, height) = self.windowed_size.get();
+        settings.window_width = width;
+        settings.window_height = height;
+        settings.window_position = self.window_position.get();
+        settings.window_maximized = self.window_maximized.get();
+        self.save_settings(settings);
+    }
+
+    // Finish a running recording before the pipeline is shut down, otherwise e.g. MP4 files would
+    // not be playable
+    //
+    // The main loop is not running anymore at this point, so the main context is iterated here
+    // until the recording is finalized. A stuck muxer is only given a few seconds
+    fn finish_recording_on_shutdown(&self) {
+        let pipeline = self.pipeline();
+        if pipeline.is_recording() {
+            pipeline.stop_recording();
+        }
+
+        let context = glib::MainContext::default();
+        let deadline =
+            Instant::now() + Duration::from_millis(u64::from(RECORDING_FINALIZE_TIMEOUT_MS));
+        while pipeline.is_stopping_recording() && Instant::now() < deadline {
+            // Don't block, nothing else might happen before the deadline
+            if !context.iteration(false) {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        if let Some(filename) = pipeline.abort_stopping_recording() {
+            println!(
+                "Recording could not be finished, {} may be incomplete",
+                filename.display()
+            );
+        }
+    }
+
+    // When the snapshot button is clicked it triggers the snapshot action, which calls this
+    // function here. We have to stop an existing timer here, start a new timer or immediately
+    // snapshot.
+    fn on_snapshot_state_changed(&self, new_state: SnapshotState) {
+        match new_state {
+            // Stop snapshot timer, if any
+            SnapshotState::Idle => self.cancel_snapshot(),
+            // A request is already running if it was not started via the button
+            SnapshotState::TimerRunning if self.timer.borrow().is_some() => (),
+            SnapshotState::TimerRunning => {
+                let settings = self.settings();
+                self.request_snapshot(SnapshotRequest::from_settings(&settings));
+            }
+        }
+    }
+
+    // Take snapshots as requested
+    //
+    // This is the only place where snapshots are triggered, independent of whether the request
+    // comes from the snapshot button or elsewhere. Any previously running request is cancelled.
+    pub fn request_snapshot(&self, request: SnapshotRequest) {
+        self.cancel_snapshot();
+
+        // Take a snapshot immediately if there's nothing to wait for, otherwise start the timer
+        let interval_ms = match request.tick_interval_ms() {
+            None => {
+                // Set the togglebutton unchecked again immediately
+                self.header_bar.set_snapshot_active(false);
+                if self.settings().prompt_for_location {
+                    self.take_snapshot_at_chosen_location();
+                } else {
+                    self.take_snapshot();
+                }
+                return;
+            }
+            Some(interval_ms) => interval_ms,
+        };
+
+        match request {
+            // Make the overlay visible with the number of seconds we have to count down
+            SnapshotRequest::Timed(secs)
+            | SnapshotRequest::Burst {
+                countdown: secs, ..
+            } if secs > 0 => {
+                self.overlay.set_label_visible(true);
+                self.overlay.set_label_text(&secs.to_string());
+            }
+            // Take the first snapshot right away and show how many are remaining
+            SnapshotRequest::Burst { count, .. } => {
+                self.start_snapshot_archive();
+                self.pipeline().start_snapshot_burst();
+                self.take_snapshot();
+                self.overlay.set_label_visible(true);
+                self.overlay.set_label_text(&(count - 1).to_string());
+            }
+            SnapshotRequest::Interval(_) => {
+                self.start_snapshot_archive();
+                self.take_snapshot();
+            }
+            SnapshotRequest::Immediate | SnapshotRequest::Timed(_) => unreachable!(),
+        }
+
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add(interval_ms, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            let tick = match app.timer.borrow_mut().as_mut() {
+                Some(timer) => timer.tick(),
+                None => return glib::Continue(false),
+            };
+
+            match tick {
+                SnapshotTick::Countdown(remaining) => {
+                    app.overlay.set_label_text(&remaining.to_string());
+                    glib::Continue(true)
+                }
+                SnapshotTick::Capture(remaining) => {
+                    if let Some(remaining) = remaining {
+                        app.overlay.set_label_text(&remaining.to_string());
+                    }
+                    app.take_snapshot();
+                    glib::Continue(true)
+                }
+                SnapshotTick::CaptureLast => {
+                    // Removes the timer, makes the overlay text invisible and sets the
+                    // togglebutton unchecked again
+                    app.cancel_snapshot();
+                    app.header_bar.set_snapshot_active(false);
+
+                    app.take_snapshot();
+
+                    glib::Continue(false)
+                }
+                SnapshotTick::StartBurst => {
+                    if let SnapshotRequest::Burst {
+                        count, interval_ms, ..
+                    } = request
+                    {
+                        app.request_snapshot(SnapshotRequest::Burst {
+                            count,
+                            interval_ms,
+                            countdown: 0,
+                        });
+                    }
+
+                    glib::Continue(false)
+                }
+            }
+        });
+
+        *self.timer.borrow_mut() = Some(SnapshotTimer::new(request, timeout_id));
+
+        // Check the togglebutton in case the request did not come from there, so that the user
+        // can cancel it. This does nothing as the timer is already set
+        self.header_bar.set_snapshot_active(true);
+    }
+
+    // Cancel the currently running snapshot request, if any
+    fn cancel_snapshot(&self) {
+        if self.timer.borrow_mut().take().is_some() {
+            let pipeline = self.pipeline();
+            pipeline.finish_snapshot_archive();
+            pipeline.finish_snapshot_burst();
+        }
+        self.overlay.set_label_visible(false);
+    }
+
+    // Collect the snapshots of a burst or interval session in an archive if configured
+    //
+    // If the archive can't be created, the snapshots are written as separate files
+    fn start_snapshot_archive(&self) {
+        let settings = self.settings();
+        if !settings.snapshot_archive {
+            return;
+        }
+
+        if let Err(err) = self.pipeline().start_snapshot_archive() {
+            utils::show_error_dialog(
+                false,
+                format!("Failed to create snapshot archive: {}", err).as_str(),
+            );
+        }
+    }
+
+    // Take a snapshot right now and play the capture sound
+    fn take_snapshot(&self) {
+        let settings = self.settings();
+
+        match self.pipeline().take_snapshot() {
+            Ok(_) => sounds::play_capture_sound(&settings.capture_sound),
+            Err(err) => utils::show_error_dialog(
+                false,
+                format!("Failed to take snapshot: {}", err).as_str(),
+            ),
+        }
+    }
+
+    // Ask where to save the snapshot and take it once a location was chosen
+    //
+    // The snapshot shows the image at the time the location was chosen. Nothing is taken if the
+    // user cancels
+    fn take_snapshot_at_chosen_location(&self) {
+        let settings = self.settings();
+        let filename = match pipeline::get_snapshot_filename(&settings) {
+            Ok(filename) => filename,
+            Err(err) => {
+                utils::show_error_dialog(
+                    false,
+                    format!("Failed to take snapshot: {}", err).as_str(),
+                );
+                return;
+            }
+        };
+
+        let app_weak = self.downgrade();
+        utils::show_save_dialog("Save snapshot", &filename, move |location| {
+            let app = upgrade_weak!(app_weak);
+
+            if let Some(location) = location {
+                app.pipeline().set_snapshot_location(Some(location));
+                app.take_snapshot();
+            }
+        });
+    }
+
+    // Copy the current image to the clipboard instead of saving it, e.g. for pasting it somewhere
+    fn copy_snapshot_to_clipboard(&self) {
+        match self.pipeline().get_snapshot_pixbuf() {
+            // We have no image to copy yet
+            Ok(None) => (),
+            Ok(Some(pixbuf)) => {
+                let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+                clipboard.set_image(&pixbuf);
+                self.show_status_message("Snapshot copied to clipboard");
+            }
+            Err(err) => utils::show_error_dialog(
+                false,
+                format!("Failed to copy snapshot: {}", err).as_str(),
+            ),
+        }
+    }
+
+    // Handle the keys of the review mode, returns true if the key was handled
+    //
+    // Space freezes the preview and resumes it again, while frozen the right arrow key steps to
+    // the next frame and enter takes a snapshot of exactly the shown frame
+    fn on_review_key(&self, keyval: u32) -> bool {
+        use gdk::enums::key;
+
+        let pipeline = self.pipeline();
+        if pipeline.is_preview_disabled() || self.preview_minimized.get() {
+            return false;
+        }
+
+        match (self.review_state.get(), keyval) {
+            (ReviewState::Live, key::space) => {
+                self.review_state.set(ReviewState::Frozen);
+                pipeline.freeze_preview(true);
+                self.overlay.set_status_text(self.review_status_text());
+            }
+            (ReviewState::Frozen, key::space) => {
+                self.review_state.set(ReviewState::Live);
+                pipeline.freeze_preview(false);
+                self.overlay.set_status_text(self.review_status_text());
+            }
+            (ReviewState::Frozen, key::Right) => pipeline.step_frame(),
+            (ReviewState::Frozen, key::Return) | (ReviewState::Frozen, key::KP_Enter) => {
+                let settings = self.settings();
+
+                match pipeline.take_snapshot_of_preview() {
+                    Ok(_) => sounds::play_capture_sound(&settings.capture_sound),
+                    Err(err) => utils::show_error_dialog(
+                        false,
+                        format!("Failed to take snapshot: {}", err).as_str(),
+                    ),
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    // When the grid button is toggled it triggers the show-grid action, which will call this
+    // function here. The setting is remembered for the next start
+    fn on_show_grid_changed(&self, show: bool) {
+        self.overlay.set_grid_visible(show);
+        self.header_bar.set_grid_active(show);
+
+        let mut settings = self.settings();
+        if settings.show_grid != show {
+            settings.show_grid = show;
+            self.save_settings(settings);
+        }
+    }
+
+    fn on_show_stats_changed(&self, show: bool) {
+        if show {
+            self.start_stats_timer();
+        } else {
+            self.stop_stats_timer();
+        }
+
+        let mut settings = self.settings();
+        if settings.show_stats != show {
+            settings.show_stats = show;
+            self.save_settings(settings);
+        }
+    }
+
+    // Show the frame rate and, while recording, the bitrate in the overlay and update them once a
+    // second
+    //
+    // Both are measured by the pipeline, this only shows its latest values
+    fn start_stats_timer(&self) {
+        if self.stats_timeout.borrow().is_some() {
+            return;
+        }
+
+        self.update_stats();
+
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add_seconds(1, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+            app.update_stats();
+            glib::Continue(true)
+        });
+        *self.stats_timeout.borrow_mut() = Some(timeout_id);
+    }
+
+    fn stop_stats_timer(&self) {
+        if let Some(timeout_id) = self.stats_timeout.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+        self.overlay.set_stats_text(None);
+    }
+
+    fn update_stats(&self) {
+        let pipeline = self.pipeline();
+
+        let mut text = match pipeline.current_fps() {
+            Some(fps) => format!("{:.1} fps", fps),
+            None => String::from("-- fps"),
+        };
+        if let Some(bitrate) = pipeline.get_recording_current_bitrate() {
+            text.push_str(&format!("\n{:.2} Mbit/s", bitrate * 8.0 / 1_000_000.0));
+        }
+
+        self.overlay.set_stats_text(Some(&text));
+    }
+
+    // Show only the preview on the whole screen, or go back to the window with the header bar
+    //
+    // The overlays for the countdown and the recording are on top of the preview and stay visible
+    fn on_fullscreen_changed(&self, fullscreen: bool) {
+        if fullscreen == self.fullscreen.replace(fullscreen) {
+            return;
+        }
+
+        if fullscreen {
+            self.header_bar.set_visible(false);
+            self.main_window.set_border_width(0);
+            self.main_window.fullscreen();
+        } else {
+            self.main_window.unfullscreen();
+            self.header_bar.set_visible(true);
+            self.main_window.set_border_width(5);
+
+            let (width, height) = self.windowed_size.get();
+            self.main_window.resize(width, height);
+        }
+    }
+
+    // Mark a new chapter in the running recording, returns false if nothing is recorded
+    fn mark_chapter(&self) -> bool {
+        let number = self.chapter_count.get() + 1;
+        let label = format!("Chapter {}", number);
+
+        let position = match self.pipeline().mark_chapter(&label) {
+            Some(position) => position,
+            None => return false,
+        };
+        self.chapter_count.set(number);
+
+        let secs = position.seconds().unwrap_or(0);
+        self.show_status_message(&format!(
+            "{} marked at {}:{:02}:{:02}",
+            label,
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60
+        ));
+
+        true
+    }
+
+    // Stop rendering the preview, e.g. while recording to save power
+    //
+    // Only the preview is affected, the pipeline and the recording keep running
+    fn set_preview_minimized(&self, minimized: bool) {
+        let pipeline = self.pipeline();
+        if pipeline.is_preview_disabled() {
+            return;
+        }
+
+        self.preview_minimized.set(minimized);
+
+        // A preview that was frozen for reviewing stays frozen
+        pipeline.set_preview_minimized(minimized);
+        pipeline.freeze_preview(minimized || self.review_state.get() == ReviewState::Frozen);
+        pipeline
+            .get_widget()
+            .set_opacity(if minimized { 0.2 } else { 1.0 });
+
+        if minimized {
+            self.overlay
+                .set_status_text(Some("Preview hidden while recording"));
+        } else {
+            self.overlay.set_status_text(self.review_status_text());
+        }
+    }
+
+    // Status text that explains the keys while in review mode
+    fn review_status_text(&self) -> Option<&'static str> {
+        match self.review_state.get() {
+            ReviewState::Live => None,
+            ReviewState::Frozen => Some("Review: → next frame, Enter snapshot, Space resume"),
+        }
+    }
+
+    // Remind the user every given number of minutes of recorded video that the recording is still
+    // running
+    //
+    // This checks the length of the recording every second, as the time while the recording is
+    // paused does not count
+    fn start_record_reminder(&self, interval_mins: u32) {
+        if interval_mins == 0 {
+            return;
+        }
+
+        let interval_secs = u64::from(interval_mins) * 60;
+        let mut reminders = 0;
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add_seconds(1, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+
+            // The recording is gone without going through any of the normal stop paths, e.g.
+            // because the pipeline was rebuilt
+            let pipeline = app.pipeline();
+            if !pipeline.is_recording() {
+                let _ = app.record_reminder.borrow_mut().take();
+                return glib::Continue(false);
+            }
+
+            // Not known yet right after starting
+            let duration = match pipeline.get_recording_duration() {
+                Some(duration) => duration.as_secs(),
+                None => return glib::Continue(true),
+            };
+
+            if duration / interval_secs <= reminders {
+                return glib::Continue(true);
+            }
+            reminders = duration / interval_secs;
+
+            gdk::beep();
+            app.show_status_message(&format!(
+                "Still recording ({}:{:02})",
+                duration / 60,
+                duration % 60
+            ));
+
+            glib::Continue(true)
+        });
+
+        if let Some(old_timeout_id) = self.record_reminder.replace(Some(timeout_id)) {
+            glib::source::source_remove(old_timeout_id);
+        }
+    }
+
+    // Show the elapsed recording time in the overlay and update it once a second
+    fn start_record_elapsed_timer(&self) {
+        self.update_record_elapsed();
+
+        let app_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add_seconds(1, move || {
+            let app = upgrade_weak!(app_weak, glib::Continue(false));
+            app.update_record_elapsed();
+            glib::Continue(true)
+        });
+
+        if let Some(old_timeout_id) = self.record_elapsed_timeout.replace(Some(timeout_id)) {
+            glib::source::source_remove(old_timeout_id);
+        }
+    }
+
+    fn stop_record_reminder(&self) {
+        if let Some(timeout_id) = self.record_reminder.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+    }
+
+    fn stop_record_elapsed_timer(&self) {
+        if let Some(timeout_id) = self.record_elapsed_timeout.borrow_mut().take() {
+            glib::source::source_remove(timeout_id);
+        }
+        self.overlay.set_elapsed_text(None);
+    }
+
+    fn update_record_elapsed(&self) {
+        let secs = match self.pipeline().get_recording_position() {
+            Some(position) => position.seconds().unwrap_or(0),
+            None => return,
+        };
+
+        self.overlay.set_elapsed_text(Some(&format!(
+            "{:02}:{:02}:{:02}",
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60
+        )));
+    }
+
+    // Only allow playing back the last recording if there is one that can be played
+    fn update_play_recording_action(&self) {
+        let action = gio::Application::get_default()
+            .expect("No default application")
+            .lookup_action(Action::PlayRecording.name())
+            .and_then(|action| action.downcast::<gio::SimpleAction>().ok())
+            .expect("No play recording action");
+
+        action.set_enabled(self.last_recording.borrow().is_some());
+    }
+
+    // Play back the last recording in a separate window
+    fn play_last_recording(&self) {
+        let filename = match *self.last_recording.borrow() {
+            Some(ref filename) => filename.clone(),
+            None => return,
+        };
+
+        if filename
+            .extension()
+            .map(|ext| ext == "enc")
+            .unwrap_or(false)
+        {
+            utils::show_error_dialog(
+                false,
+                "Encrypted recordings have to be decrypted before playing them back",
+            );
+            return;
+        }
+
+        if let Some(application) = self.main_window.get_application() {
+            playback::show_playback_window(&application, &filename, &self.settings());
+        }
+    }
+
+    // Let the user pick a file to write the current settings to, e.g. for copying them to another
+    // machine
+    fn export_settings(&self) {
+        let mut filename = glib::get_home_dir().unwrap_or_else(|| PathBuf::from("."));
+        filename.push("cameraview-settings.toml");
+
+        let settings = self.settings();
+        utils::show_save_dialog("Export settings", &filename, move |filename| {
+            if let Some(filename) = filename {
+                if let Err(err) = settings.export_to_file(&filename) {
+                    utils::show_error_dialog(false, &err);
+                }
+            }
+        });
+    }
+
+    // Let the user pick an exported settings file and replace the current settings with it
+    //
+    // The window size and position are kept, as they depend on the screen of this machine
+    fn import_settings(&self) {
+        let app_weak = self.downgrade();
+        utils::show_open_dialog("Import settings", move |filename| {
+            let app = upgrade_weak!(app_weak);
+            let filename = match filename {
+                Some(filename) => filename,
+                None => return,
+            };
+
+            let settings = match Settings::import_from_file(&filename) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    utils::show_error_dialog(false, &err);
+                    return;
+                }
+            };
+
+            let current = app.settings();
+            app.save_settings(Settings {
+                window_width: current.window_width,
+                window_height: current.window_height,
+                window_position: current.window_position,
+                window_maximized: current.window_maximized,
+                ..settings
+            });
+            app.apply_settings();
+        });
+    }
+
+    // Only allow changing whether frames are dropped while recording, and show the current mode
+    fn update_drop_frames_action(&self, enabled: bool, drop: bool) {
+        let action = gio::Application::get_default()
+            .expect("No default application")
+            .lookup_action(Action::DropFrames(drop).name())
+            .and_then(|action| action.downcast::<gio::SimpleAction>().ok())
+            .expect("No drop frames action");
+
+        action.set_state(&drop.to_variant());
+        action.set_enabled(enabled);
+    }
+
+    // Enable or disable the pause-recording action, and show the pause button accordingly
+    fn update_pause_recording_action(&self, enabled: bool) {
+        let action = gio::Application::get_default()
+            .expect("No default application")
+            .lookup_action(Action::PauseRecording(false).name())
+            .and_then(|action| action.downcast::<gio::SimpleAction>().ok())
+            .expect("No pause recording action");
+
+        action.set_state(&false.to_variant());
+        action.set_enabled(enabled);
+        self.header_bar.set_pause_visible(enabled);
+    }
+
+    // When the pause button is toggled it triggers the pause-recording action, which will call
+    // this
+    fn on_pause_recording_changed(&self, paused: bool) {
+        let pipeline = self.pipeline();
+        if paused == pipeline.is_recording_paused() {
+            return;
+        }
+
+        if paused {
+            if pipeline.pause_recording() {
+                self.overlay.set_status_text(Some("Recording paused"));
+            }
+        } else {
+            pipeline.resume_recording();
+            self.overlay.set_status_text(None);
+        }
+        self.header_bar.set_pause_active(paused);
+    }
+
+    // Start recording, after asking where to save it if configured
+    //
+    // Nothing is started if the user cancels, so that the recording branch is only added once the
+    // location is known
+    fn start_recording(&self, lock_exposure: bool) {
+        let settings = self.settings();
+
+        if !settings.prompt_for_location {
+            self.pipeline().set_recording_location(None);
+            self.start_recording_now(lock_exposure);
+            return;
+        }
+
+        let filename = match pipeline::get_recording_filename(&settings, settings.record_container)
+        {
+            Ok(filename) => filename,
+            Err(err) => {
+                utils::show_error_dialog(
+                    false,
+                    format!("Failed to start recording: {}", err).as_str(),
+                );
+                self.header_bar.set_record_active(false);
+                return;
+            }
+        };
+
+        let app_weak = self.downgrade();
+        utils::show_save_dialog("Save recording", &filename, move |location| {
+            let app = upgrade_weak!(app_weak);
+
+            match location {
+                Some(location) => {
+                    app.pipeline().set_recording_location(Some(location));
+                    app.start_recording_now(lock_exposure);
+                }
+                None => app.header_bar.set_record_active(false),
+            }
+        });
+    }
+
+    // Start recording with everything that belongs to it
+    fn start_recording_now(&self, lock_exposure: bool) {
+        let settings = self.settings();
+        let pipeline = self.pipeline();
+
+        // Lock the exposure before the first frame is recorded
+        if lock_exposure {
+            if let Err(err) = pipeline.set_exposure_locked(true) {
+                utils::show_error_dialog(
+                    false,
+                    format!("Failed to lock exposure: {}", err).as_str(),
+                );
+            }
+        }
+
+        if let Err(err) = pipeline.start_recording() {
+            utils::show_error_dialog(
+                false,
+                format!("Failed to start recording: {}", err).as_str(),
+            );
+            self.header_bar.set_record_active(false);
+            return;
+        }
+        self.chapter_count.set(0);
+        self.low_disk_space_warned.set(false);
+
+        let recording_id = self.recording_id.get() + 1;
+        self.recording_id.set(recording_id);
+        if let Some(secs) = self.command_line_record_secs.take() {
+            self.stop_recording_after(recording_id, secs);
+        }
+
+        if settings.snapshot_on_record_start {
+            // The snapshot is taken from the preview sink and not from the recording
+            // branch, so this does not interfere with the recording at all
+            self.take_snapshot();
+        }
+
+        self.start_record_reminder(settings.record_reminder_interval);
+        self.start_record_elapsed_timer();
+        self.overlay.set_recording_indicator_visible(true);
+        self.update_drop_frames_action(true, settings.get_record_output_tuning().leaky);
+        self.update_pause_recording_action(true);
+
+        if settings.minimize_preview_while_recording {
+            self.set_preview_minimized(true);
+        }
+    }
+
+    // When the record button is clicked it triggers the record action, which will call this.
+    // We have to start or stop recording here
+    fn on_record_state_changed(&self, new_state: RecordState) {
+        let settings = self.settings();
+        let pipeline = self.pipeline();
+
+        // Pressing record again while the previous recording is still being finalized means that
+        // finalizing got stuck. It is given a bit more time and then torn down, and the button is
+        // reset instead of starting a new recording
+        if new_state == RecordState::Recording && pipeline.is_stopping_recording() {
+            self.header_bar.set_record_active(false);
+            self.overlay
+                .set_status_text(Some("Recording is stuck, stopping it…"));
+
+            self.when_recording_finalized(|app| {
+                if let Some(filename) = app.pipeline().abort_stopping_recording() {
+                    app.show_status_message(&format!(
+                        "Recording could not be finished, {} may be incomplete",
+                        filename.display()
+                    ));
+                }
+            });
+            return;
+        }
+
+        // Only lock the exposure for the duration of the recording if configured like that
+        let lock_exposure =
+            settings.exposure_lock == ExposureLock::WhileRecording && self.supports_exposure_lock();
+
+        // Start/stop recording based on button active'ness
+        match new_state {
+            // Encrypted recordings only start once the passphrase was entered
+            RecordState::Recording if settings.record_encrypt => {
+                let app_weak = self.downgrade();
+                utils::show_passphrase_dialog(
+                    "Passphrase for encrypting the recording",
+                    "Record",
+                    move |passphrase| {
+                        let app = upgrade_weak!(app_weak);
+
+                        let passphrase = match passphrase {
+                            Some(passphrase) => passphrase,
+                            None => {
+                                app.header_bar.set_record_active(false);
+                                return;
+                            }
+                        };
+
+                        app.overlay.set_status_text(Some("Preparing encryption…"));
+
+                        let app_weak = app.downgrade();
+                        encryption::Key::derive_async(passphrase, move |key| {
+                            let app = upgrade_weak!(app_weak);
+
+                            app.overlay.set_status_text(None);
+
+                            // Recording might have been stopped again in the meantime
+                            if !app.header_bar.is_record_active() {
+                                return;
+                            }
+
+                            app.pipeline().set_recording_key(Some(key));
+                            app.start_recording(lock_exposure);
+                        });
+                    },
+                );
+            }
+            RecordState::Recording => self.start_recording(lock_exposure),
+            RecordState::Idle => {
+                // Also if the recording from the command line was never started, e.g. because
+                // the passphrase dialog was cancelled
+                self.command_line_record_secs.set(None);
+
+                pipeline.stop_recording();
+                pipeline.set_recording_key(None);
+                self.update_drop_frames_action(false, false);
+                self.update_pause_recording_action(false);
+
+                if self.preview_minimized.get() {
+                    self.set_preview_minimized(false);
+                }
+
+                // Finalizing can take a moment, or get stuck in which case recording has to be
+                // pressed again
+                if pipeline.is_stopping_recording() {
+                    self.overlay.set_status_text(Some("Finishing recording…"));
+                }
+
+                self.stop_record_reminder();
+                self.stop_record_elapsed_timer();
+                self.overlay.set_recording_indicator_visible(false);
+
+                // And release the lock again
+                if lock_exposure {
+                    let _ = pipeline.set_exposure_locked(false);
+                }
+            }
         }
     }
 }
 
+// Show the folder that contains the file in the file manager
+fn open_folder_of(filename: &Path) {
+    open_folder(filename.parent().unwrap_or(filename));
+}
+
+// Show the folder in the file manager
+fn open_folder(folder: &Path) {
+    let res = glib::filename_to_uri(folder, None)
+        .and_then(|uri| gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>));
+
+    if let Err(err) = res {
+        utils::show_error_dialog(
+            false,
+            format!("Failed to open {}: {}", folder.display(), err).as_str(),
+        );
+    }
+}
+
 impl Action {
+    // The action name as it is registered on the application
+    pub fn name(self) -> &'static str {
+        // Strip the "app." prefix so that the names are only defined in a single place
+        &self.full_name()["app.".len()..]
+    }
+
     // The full action name as is used in e.g. menu models
     pub fn full_name(self) -> &'static str {
         match self {
             Action::Quit => "app.quit",
             Action::Settings => "app.settings",
             Action::About => "app.about",
+            Action::Shortcuts => "app.shortcuts",
             Action::Snapshot(_) => "app.snapshot",
             Action::Record(_) => "app.record",
+            Action::Reset => "app.reset",
+            Action::ShareScreen => "app.share-screen",
+            Action::PlayRecording => "app.play-recording",
+            Action::SnapshotClipboard => "app.snapshot-clipboard",
+            Action::DropFrames(_) => "app.drop-frames",
+            Action::PauseRecording(_) => "app.pause-recording",
+            Action::Fullscreen(_) => "app.fullscreen",
+            Action::ShowGrid(_) => "app.show-grid",
+            Action::ShowStats(_) => "app.show-stats",
+            Action::OpenFolder => "app.open-folder",
+            Action::ExportSettings => "app.export-settings",
+            Action::ImportSettings => "app.import-settings",
         }
     }
 
@@ -394,17 +2618,19 @@ impl Action {
     // These are connected to our buttons and can be triggered by the buttons, as well as remotely
     fn create(app: &App, application: &gtk::Application) {
         // When activated, show a settings dialog
-        let settings = gio::SimpleAction::new("settings", None);
+        let settings = gio::SimpleAction::new(Action::Settings.name(), None);
         let weak_application = application.downgrade();
+        let weak_app = app.downgrade();
         settings.connect_activate(move |_action, _parameter| {
             let application = upgrade_weak!(weak_application);
+            let app = upgrade_weak!(weak_app);
 
-            show_settings_dialog(&application);
+            show_settings_dialog(&application, &app);
         });
         application.add_action(&settings);
 
         // about action: when activated it will show an about dialog
-        let about = gio::SimpleAction::new("about", None);
+        let about = gio::SimpleAction::new(Action::About.name(), None);
         let weak_application = application.downgrade();
         about.connect_activate(move |_action, _parameter| {
             let application = upgrade_weak!(weak_application);
@@ -412,21 +2638,115 @@ impl Action {
         });
         application.add_action(&about);
 
-        // When activated, shuts down the application
-        let quit = gio::SimpleAction::new("quit", None);
+        // shortcuts action: when activated it will show a window with all keyboard shortcuts
+        let shortcuts = gio::SimpleAction::new(Action::Shortcuts.name(), None);
         let weak_application = application.downgrade();
-        quit.connect_activate(move |_action, _parameter| {
+        shortcuts.connect_activate(move |_action, _parameter| {
             let application = upgrade_weak!(weak_application);
-            application.quit();
+            show_shortcuts_window(&application);
+        });
+        application.add_action(&shortcuts);
+
+        // When activated, shuts down the application
+        let quit = gio::SimpleAction::new(Action::Quit.name(), None);
+        let weak_app = app.downgrade();
+        quit.connect_activate(move |_action, _parameter| {
+            let app = upgrade_weak!(weak_app);
+            app.request_quit();
         });
         application.add_action(&quit);
 
-        // And add an accelerator for triggering the action on ctrl+q
+        // When activated, stops everything and restarts with a new pipeline
+        let reset = gio::SimpleAction::new(Action::Reset.name(), None);
+        let weak_app = app.downgrade();
+        reset.connect_activate(move |_action, _parameter| {
+            let app = upgrade_weak!(weak_app);
+            app.reset();
+        });
+        application.add_action(&reset);
+
+        // When activated, lets the user pick a screen or window to capture instead of the camera
+        #[cfg(target_os = "linux")]
+        {
+            let share_screen = gio::SimpleAction::new(Action::ShareScreen.name(), None);
+            let weak_app = app.downgrade();
+            share_screen.connect_activate(move |_action, _parameter| {
+                let app = upgrade_weak!(weak_app);
+                app.request_screencast();
+            });
+            application.add_action(&share_screen);
+        }
+
+        // When activated, plays back the last recording. Only enabled once there is one
+        let play_recording = gio::SimpleAction::new(Action::PlayRecording.name(), None);
+        play_recording.set_enabled(false);
+        let weak_app = app.downgrade();
+        play_recording.connect_activate(move |_action, _parameter| {
+            let app = upgrade_weak!(weak_app);
+            app.play_last_recording();
+        });
+        application.add_action(&play_recording);
+
+        // When activated, copy the current image to the clipboard
+        let snapshot_clipboard = gio::SimpleAction::new(Action::SnapshotClipboard.name(), None);
+        let weak_app = app.downgrade();
+        snapshot_clipboard.connect_activate(move |_action, _parameter| {
+            let app = upgrade_weak!(weak_app);
+            app.copy_snapshot_to_clipboard();
+        });
+        application.add_action(&snapshot_clipboard);
+
+        // When activated, show the folder of the given file, e.g. from a notification about a
+        // saved snapshot or recording, or the snapshot directory if no file is given
+        let open_folder = gio::SimpleAction::new(
+            Action::OpenFolder.name(),
+            Some(glib::VariantTy::new("s").expect("Invalid variant type")),
+        );
+        let weak_app = app.downgrade();
+        open_folder.connect_activate(move |_action, parameter| {
+            match parameter.and_then(|parameter| parameter.get::<String>()) {
+                Some(ref filename) if !filename.is_empty() => open_folder_of(Path::new(filename)),
+                _ => {
+                    let app = upgrade_weak!(weak_app);
+                    open_folder(&app.settings().snapshot_directory);
+                }
+            }
+        });
+        application.add_action(&open_folder);
+
+        // When activated, let the user pick a file to copy the settings to or from
+        let export_settings = gio::SimpleAction::new(Action::ExportSettings.name(), None);
+        let weak_app = app.downgrade();
+        export_settings.connect_activate(move |_action, _parameter| {
+            let app = upgrade_weak!(weak_app);
+            app.export_settings();
+        });
+        application.add_action(&export_settings);
+
+        let import_settings = gio::SimpleAction::new(Action::ImportSettings.name(), None);
+        let weak_app = app.downgrade();
+        import_settings.connect_activate(move |_action, _parameter| {
+            let app = upgrade_weak!(weak_app);
+            app.import_settings();
+        });
+        application.add_action(&import_settings);
+
+        // And add accelerators for triggering the actions on ctrl+q, ctrl+comma, ctrl+? and
+        // ctrl+shift+c. These are all listed in the shortcuts window too
         application.set_accels_for_action(Action::Quit.full_name(), &["<Primary>Q"]);
+        application.set_accels_for_action(Action::Settings.full_name(), &["<Primary>comma"]);
+        application.set_accels_for_action(Action::Shortcuts.full_name(), &["<Primary>question"]);
+        application.set_accels_for_action(
+            Action::SnapshotClipboard.full_name(),
+            &["<Primary><Shift>C"],
+        );
 
         // snapshot action: changes state between true/false
-        let snapshot =
-            gio::SimpleAction::new_stateful("snapshot", None, &SnapshotState::Idle.into());
+        let snapshot = gio::SimpleAction::new_stateful(
+            Action::Snapshot(SnapshotState::Idle).name(),
+            None,
+            &SnapshotState::Idle.into(),
+        );
         let weak_app = app.downgrade();
         snapshot.connect_change_state(move |action, state| {
             let app = upgrade_weak!(weak_app);
@@ -439,7 +2759,11 @@ impl Action {
         application.add_action(&snapshot);
 
         // record action: changes state between true/false
-        let record = gio::SimpleAction::new_stateful("record", None, &RecordState::Idle.into());
+        let record = gio::SimpleAction::new_stateful(
+            Action::Record(RecordState::Idle).name(),
+            None,
+            &RecordState::Idle.into(),
+        );
         let weak_app = app.downgrade();
         record.connect_change_state(move |action, state| {
             let app = upgrade_weak!(weak_app);
@@ -450,16 +2774,323 @@ impl Action {
             action.set_state(state);
         });
         application.add_action(&record);
+
+        // drop-frames action: changes state between true/false, only enabled while recording
+        let drop_frames = gio::SimpleAction::new_stateful(
+            Action::DropFrames(false).name(),
+            None,
+            &false.to_variant(),
+        );
+        drop_frames.set_enabled(false);
+        let weak_app = app.downgrade();
+        drop_frames.connect_change_state(move |action, state| {
+            let app = upgrade_weak!(weak_app);
+            let state = state.expect("No state provided");
+            app.pipeline()
+                .set_recording_leaky(state.get::<bool>().expect("Invalid drop frames state type"));
+
+            // Let the action store the new state
+            action.set_state(state);
+        });
+        application.add_action(&drop_frames);
+
+        // pause-recording action: changes state between true/false, only enabled while recording
+        let pause_recording = gio::SimpleAction::new_stateful(
+            Action::PauseRecording(false).name(),
+            None,
+            &false.to_variant(),
+        );
+        pause_recording.set_enabled(false);
+        let weak_app = app.downgrade();
+        pause_recording.connect_change_state(move |action, state| {
+            let app = upgrade_weak!(weak_app);
+            let state = state.expect("No state provided");
+            app.on_pause_recording_changed(
+                state
+                    .get::<bool>()
+                    .expect("Invalid pause recording state type"),
+            );
+
+            // Let the action store the new state
+            action.set_state(state);
+        });
+        application.add_action(&pause_recording);
+
+        // fullscreen action: changes state between true/false. Activating it without a state, e.g.
+        // via F11, toggles the state
+        let fullscreen = gio::SimpleAction::new_stateful(
+            Action::Fullscreen(false).name(),
+            None,
+            &false.to_variant(),
+        );
+        let weak_app = app.downgrade();
+        fullscreen.connect_change_state(move |action, state| {
+            let app = upgrade_weak!(weak_app);
+            let state = state.expect("No state provided");
+            app.on_fullscreen_changed(state.get::<bool>().expect("Invalid fullscreen state type"));
+
+            // Let the action store the new state
+            action.set_state(state);
+        });
+        application.add_action(&fullscreen);
+        application.set_accels_for_action(Action::Fullscreen(false).full_name(), &["F11"]);
+
+        // show-grid action: changes state between true/false, starting with the stored setting
+        let show_grid = gio::SimpleAction::new_stateful(
+            Action::ShowGrid(false).name(),
+            None,
+            &app.settings().show_grid.to_variant(),
+        );
+        let weak_app = app.downgrade();
+        show_grid.connect_change_state(move |action, state| {
+            let app = upgrade_weak!(weak_app);
+            let state = state.expect("No state provided");
+            app.on_show_grid_changed(state.get::<bool>().expect("Invalid show grid state type"));
+
+            // Let the action store the new state
+            action.set_state(state);
+        });
+        application.add_action(&show_grid);
+
+        // show-stats action: changes state between true/false, starting with the stored setting
+        let show_stats = gio::SimpleAction::new_stateful(
+            Action::ShowStats(false).name(),
+            None,
+            &app.settings().show_stats.to_variant(),
+        );
+        let weak_app = app.downgrade();
+        show_stats.connect_change_state(move |action, state| {
+            let app = upgrade_weak!(weak_app);
+            let state = state.expect("No state provided");
+            app.on_show_stats_changed(state.get::<bool>().expect("Invalid show stats state type"));
+
+            // Let the action store the new state
+            action.set_state(state);
+        });
+        application.add_action(&show_stats);
     }
 
     // Triggers the provided action on the application
     pub fn trigger<A: IsA<gio::Application> + IsA<gio::ActionGroup>>(self, app: &A) {
         match self {
-            Action::Quit => app.activate_action("quit", None),
-            Action::Settings => app.activate_action("settings", None),
-            Action::About => app.activate_action("about", None),
-            Action::Snapshot(new_state) => app.change_action_state("snapshot", &new_state.into()),
-            Action::Record(new_state) => app.change_action_state("record", &new_state.into()),
+            Action::Snapshot(new_state) => app.change_action_state(self.name(), &new_state.into()),
+            Action::Record(new_state) => app.change_action_state(self.name(), &new_state.into()),
+            Action::DropFrames(drop) => app.change_action_state(self.name(), &drop.to_variant()),
+            Action::PauseRecording(paused) => {
+                app.change_action_state(self.name(), &paused.to_variant())
+            }
+            Action::Fullscreen(fullscreen) => {
+                app.change_action_state(self.name(), &fullscreen.to_variant())
+            }
+            Action::ShowGrid(show) => app.change_action_state(self.name(), &show.to_variant()),
+            Action::ShowStats(show) => app.change_action_state(self.name(), &show.to_variant()),
+            Action::Quit
+            | Action::Settings
+            | Action::About
+            | Action::Shortcuts
+            | Action::Reset
+            | Action::ShareScreen
+            | Action::PlayRecording
+            | Action::SnapshotClipboard
+            | Action::ExportSettings
+            | Action::ImportSettings => app.activate_action(self.name(), None),
+            // Only the notifications know the saved file, so show the snapshot directory here
+            Action::OpenFolder => app.activate_action(self.name(), Some(&"".to_variant())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // Create a timer with a timeout source that only records whether it was ever dispatched
+    fn new_timer(request: SnapshotRequest, dispatched: &Arc<AtomicBool>) -> SnapshotTimer {
+        let dispatched = dispatched.clone();
+        let timeout_id = glib::timeout_add(0, move || {
+            dispatched.store(true, Ordering::SeqCst);
+            glib::Continue(false)
+        });
+
+        SnapshotTimer::new(request, timeout_id)
+    }
+
+    // One of each action, independent of their state
+    const ACTIONS: &[Action] = &[
+        Action::Quit,
+        Action::Settings,
+        Action::About,
+        Action::Shortcuts,
+        Action::Snapshot(SnapshotState::Idle),
+        Action::Record(RecordState::Idle),
+        Action::Reset,
+        Action::ShareScreen,
+        Action::PlayRecording,
+        Action::SnapshotClipboard,
+        Action::DropFrames(false),
+        Action::PauseRecording(false),
+        Action::Fullscreen(false),
+        Action::ShowGrid(false),
+        Action::ShowStats(false),
+        Action::OpenFolder,
+        Action::ExportSettings,
+        Action::ImportSettings,
+    ];
+
+    #[test]
+    fn action_names() {
+        assert_eq!(Action::Snapshot(SnapshotState::Idle).name(), "snapshot");
+        assert_eq!(Action::Record(RecordState::Idle).full_name(), "app.record");
+
+        for action in ACTIONS {
+            let full_name = action.full_name();
+            assert!(full_name.starts_with("app."), "{}", full_name);
+            assert_eq!(&full_name["app.".len()..], action.name());
+
+            let name = action.name();
+            assert!(!name.is_empty());
+            assert!(
+                name.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+                "Invalid action name {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn action_names_are_unique() {
+        let names: HashSet<_> = ACTIONS.iter().map(|action| action.name()).collect();
+        assert_eq!(names.len(), ACTIONS.len());
+
+        // The state does not change the action
+        assert_eq!(
+            Action::Snapshot(SnapshotState::Idle).name(),
+            Action::Snapshot(SnapshotState::TimerRunning).name()
+        );
+        assert_eq!(
+            Action::Record(RecordState::Idle).name(),
+            Action::Record(RecordState::Recording).name()
+        );
+        assert_eq!(
+            Action::ShowGrid(false).name(),
+            Action::ShowGrid(true).name()
+        );
+    }
+
+    #[test]
+    fn startup_error_classification() {
+        assert!(AppStartupError::NoCamera.is_recoverable());
+        assert!(AppStartupError::Pipeline("Missing plugin".into()).is_recoverable());
+        assert!(!AppStartupError::Fatal("No display".into()).is_recoverable());
+
+        match AppStartupError::from_pipeline_error(pipeline::NoCameraError.into()) {
+            AppStartupError::NoCamera => (),
+            err => panic!("Wrong classification: {:?}", err),
+        }
+        match AppStartupError::from_pipeline_error("Missing plugin".into()) {
+            AppStartupError::Pipeline(_) => (),
+            err => panic!("Wrong classification: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn tick_interval() {
+        assert_eq!(SnapshotRequest::Immediate.tick_interval_ms(), None);
+        assert_eq!(SnapshotRequest::Timed(0).tick_interval_ms(), None);
+        assert_eq!(SnapshotRequest::Timed(3).tick_interval_ms(), Some(1000));
+        assert_eq!(
+            SnapshotRequest::Burst {
+                count: 1,
+                interval_ms: 200,
+                countdown: 0,
+            }
+            .tick_interval_ms(),
+            None
+        );
+        assert_eq!(
+            SnapshotRequest::Burst {
+                count: 3,
+                interval_ms: 200,
+                countdown: 0,
+            }
+            .tick_interval_ms(),
+            Some(200)
+        );
+        assert_eq!(SnapshotRequest::Interval(0).tick_interval_ms(), Some(1000));
+        assert_eq!(SnapshotRequest::Interval(5).tick_interval_ms(), Some(5000));
+    }
+
+    #[test]
+    fn countdown_fires_once() {
+        let dispatched = Arc::new(AtomicBool::new(false));
+        let mut timer = new_timer(SnapshotRequest::Timed(3), &dispatched);
+
+        assert_eq!(timer.tick(), SnapshotTick::Countdown(2));
+        assert_eq!(timer.tick(), SnapshotTick::Countdown(1));
+        assert_eq!(timer.tick(), SnapshotTick::CaptureLast);
+        assert_eq!(timer.remaining, 0);
+    }
+
+    #[test]
+    fn burst_counts_down_remaining_snapshots() {
+        let dispatched = Arc::new(AtomicBool::new(false));
+        let mut timer = new_timer(
+            SnapshotRequest::Burst {
+                count: 3,
+                interval_ms: 200,
+                countdown: 0,
+            },
+            &dispatched,
+        );
+
+        // The first snapshot is taken before the timer is started
+        assert_eq!(timer.tick(), SnapshotTick::Capture(Some(1)));
+        assert_eq!(timer.tick(), SnapshotTick::CaptureLast);
+    }
+
+    #[test]
+    fn burst_honors_countdown() {
+        let request = SnapshotRequest::Burst {
+            count: 3,
+            interval_ms: 200,
+            countdown: 2,
+        };
+        assert_eq!(request.tick_interval_ms(), Some(1000));
+
+        // The burst itself is started with a new timer once the countdown is over
+        let dispatched = Arc::new(AtomicBool::new(false));
+        let mut timer = new_timer(request, &dispatched);
+        assert_eq!(timer.tick(), SnapshotTick::Countdown(1));
+        assert_eq!(timer.tick(), SnapshotTick::StartBurst);
+    }
+
+    #[test]
+    fn interval_never_finishes() {
+        let dispatched = Arc::new(AtomicBool::new(false));
+        let mut timer = new_timer(SnapshotRequest::Interval(1), &dispatched);
+
+        for _ in 0..10 {
+            assert_eq!(timer.tick(), SnapshotTick::Capture(None));
         }
     }
+
+    #[test]
+    fn cancel_removes_timeout() {
+        let dispatched = Arc::new(AtomicBool::new(false));
+        let mut timer = new_timer(SnapshotRequest::Timed(3), &dispatched);
+        assert_eq!(timer.tick(), SnapshotTick::Countdown(2));
+
+        // Dropping the timer is how a running request is cancelled, afterwards the timeout must
+        // never fire
+        drop(timer);
+
+        let context = glib::MainContext::default();
+        while context.iteration(false) {}
+        assert!(!dispatched.load(Ordering::SeqCst));
+    }
 }
@@ -0,0 +1,247 @@
+use glib;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use dbus::arg::{RefArg, Variant};
+use dbus::{BusType, Connection, Message, MessageType, OwnedFd, Path};
+
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREENCAST_INTERFACE: &str = "org.freedesktop.portal.ScreenCast";
+const REQUEST_INTERFACE: &str = "org.freedesktop.portal.Request";
+
+// Maximum time in milliseconds for a single call to the portal. Waiting for the user to pick
+// something is not limited
+const CALL_TIMEOUT_MS: i32 = 5000;
+
+// Source types from the portal specification, the user can pick either of them
+const SOURCE_TYPE_MONITOR: u32 = 1;
+const SOURCE_TYPE_WINDOW: u32 = 2;
+
+// For creating unique tokens for the portal requests
+static REQUEST_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Ways how the portal handshake can fail
+#[derive(Debug)]
+pub enum PortalError {
+    // The user closed the portal's dialog without picking anything
+    Cancelled,
+    // The portal did not allow sharing, e.g. because of the system configuration
+    Denied,
+    // Anything else, e.g. no portal running at all
+    Failed(String),
+}
+
+impl fmt::Display for PortalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PortalError::Cancelled => write!(f, "Cancelled by the user"),
+            PortalError::Denied => write!(f, "Permission denied"),
+            PortalError::Failed(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// A screen or window that is shared via the screencast portal
+//
+// The portal session is kept open by a thread until this is dropped. The video is read with a
+// pipewiresrc from the PipeWire connection given by the portal
+pub struct Screencast {
+    fd: OwnedFd,
+    node_id: u32,
+    // Dropping this lets the thread close the session
+    _stop: mpsc::Sender<()>,
+}
+
+impl Screencast {
+    // Description of the source element for capturing from this screencast
+    pub fn get_source_description(&self) -> String {
+        format!(
+            "pipewiresrc fd={} path={} do-timestamp=true",
+            self.fd.as_raw_fd(),
+            self.node_id
+        )
+    }
+}
+
+// Let the user pick a screen or window to share via the xdg-desktop-portal
+//
+// The portal shows its own dialog for that, which can take as long as the user wants, so
+// everything happens on a separate thread and the callback is called on the main thread once done
+pub fn request_screencast<F: FnOnce(Result<Screencast, PortalError>) + 'static>(func: F) {
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+
+    thread::spawn(move || {
+        let connection = match Connection::get_private(BusType::Session) {
+            Ok(connection) => connection,
+            Err(err) => {
+                let _ = sender.send(Err(PortalError::Failed(format!(
+                    "Failed to connect to the session bus: {}",
+                    err
+                ))));
+                return;
+            }
+        };
+
+        let res = start_screencast(&connection);
+        let started = res.is_ok();
+        let _ = sender.send(res);
+
+        // The portal closes the session once our connection is gone, so keep it until the
+        // screencast is dropped and the sender with it
+        if started {
+            let _ = stop_receiver.recv();
+        }
+    });
+
+    // The callback and the stop sender have to be stored in an Option to be able to move them
+    // out of the FnMut closure
+    let mut func = Some(func);
+    let mut stop_sender = Some(stop_sender);
+    receiver.attach(None, move |res| {
+        let func = func.take().expect("Called multiple times");
+        func(res.map(|(fd, node_id)| Screencast {
+            fd,
+            node_id,
+            _stop: stop_sender.take().expect("Called multiple times"),
+        }));
+
+        glib::Continue(false)
+    });
+}
+
+// Run the whole portal handshake and return the PipeWire connection and the node to capture from
+fn start_screencast(connection: &Connection) -> Result<(OwnedFd, u32), PortalError> {
+    let mut options = HashMap::new();
+    options.insert("session_handle_token", variant(new_token()));
+    let results = call_request(connection, "CreateSession", |msg| msg, options)?;
+    let session = results
+        .get("session_handle")
+        .and_then(|handle| handle.0.as_str())
+        .and_then(|handle| Path::new(handle).ok())
+        .ok_or_else(|| PortalError::Failed(String::from("Portal returned no session")))?;
+
+    let mut options = HashMap::new();
+    options.insert("types", variant(SOURCE_TYPE_MONITOR | SOURCE_TYPE_WINDOW));
+    options.insert("multiple", variant(false));
+    let session_clone = session.clone();
+    call_request(
+        connection,
+        "SelectSources",
+        move |msg| msg.append1(session_clone),
+        options,
+    )?;
+
+    // This shows the dialog for picking the screen or window
+    let session_clone = session.clone();
+    let results = call_request(
+        connection,
+        "Start",
+        move |msg| msg.append2(session_clone, ""),
+        HashMap::new(),
+    )?;
+
+    // Each stream is a struct of the PipeWire node id and its properties. We only allowed
+    // picking a single one
+    let node_id = results
+        .get("streams")
+        .and_then(|streams| streams.0.as_iter()?.next()?.as_iter()?.next()?.as_u64())
+        .ok_or_else(|| PortalError::Failed(String::from("Portal returned no stream")))?;
+
+    let msg = new_method_call("OpenPipeWireRemote")?
+        .append2(session, HashMap::<&str, Variant<Box<dyn RefArg>>>::new());
+    let fd = connection
+        .send_with_reply_and_block(msg, CALL_TIMEOUT_MS)
+        .map_err(|err| PortalError::Failed(format!("Failed to open PipeWire remote: {}", err)))?
+        .read1::<OwnedFd>()
+        .map_err(|err| PortalError::Failed(format!("Failed to open PipeWire remote: {}", err)))?;
+
+    Ok((fd, node_id as u32))
+}
+
+// Call a portal method that answers via a request object and wait for its response
+//
+// The request object path is derived from our unique name and the token, so we can subscribe to
+// its response before calling the method and can't miss it
+fn call_request<F: FnOnce(Message) -> Message>(
+    connection: &Connection,
+    method: &str,
+    append_args: F,
+    mut options: HashMap<&'static str, Variant<Box<dyn RefArg>>>,
+) -> Result<HashMap<String, Variant<Box<dyn RefArg>>>, PortalError> {
+    let token = new_token();
+    let sender = connection.unique_name();
+    let request_path = format!(
+        "{}/request/{}/{}",
+        PORTAL_OBJECT_PATH,
+        sender.trim_start_matches(':').replace('.', "_"),
+        token
+    );
+    let rule = format!(
+        "type='signal',interface='{}',member='Response',path='{}'",
+        REQUEST_INTERFACE, request_path
+    );
+    connection
+        .add_match(&rule)
+        .map_err(|err| PortalError::Failed(format!("Failed to call {}: {}", method, err)))?;
+
+    options.insert("handle_token", variant(token));
+    let msg = append_args(new_method_call(method)?).append1(options);
+    connection
+        .send_with_reply_and_block(msg, CALL_TIMEOUT_MS)
+        .map_err(|err| PortalError::Failed(format!("Failed to call {}: {}", method, err)))?;
+
+    loop {
+        for msg in connection.incoming(1000) {
+            if msg.msg_type() != MessageType::Signal
+                || msg
+                    .path()
+                    .map(|path| &*path != request_path)
+                    .unwrap_or(true)
+            {
+                continue;
+            }
+
+            let _ = connection.remove_match(&rule);
+
+            let (response, results) = msg
+                .read2::<u32, HashMap<String, Variant<Box<dyn RefArg>>>>()
+                .map_err(|err| {
+                    PortalError::Failed(format!("Invalid response to {}: {}", method, err))
+                })?;
+
+            return match response {
+                0 => Ok(results),
+                1 => Err(PortalError::Cancelled),
+                _ => Err(PortalError::Denied),
+            };
+        }
+    }
+}
+
+fn new_method_call(method: &str) -> Result<Message, PortalError> {
+    Message::new_method_call(
+        PORTAL_BUS_NAME,
+        PORTAL_OBJECT_PATH,
+        SCREENCAST_INTERFACE,
+        method,
+    )
+    .map_err(PortalError::Failed)
+}
+
+fn new_token() -> String {
+    format!(
+        "cameraview{}",
+        REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn variant<T: RefArg + 'static>(value: T) -> Variant<Box<dyn RefArg>> {
+    Variant(Box::new(value))
+}
@@ -0,0 +1,328 @@
+use glib;
+use gst::{self, prelude::*};
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher, SyncStreamCipherSeek};
+use aes_ctr::Aes256Ctr;
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2;
+use rand;
+use sha2::Sha256;
+
+// Identifies encrypted recordings and the version of the format
+const MAGIC: &[u8; 8] = b"CAMVENC2";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+// Derived from the passphrase together with the keys, to detect wrong passphrases when decrypting
+const CHECK_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN + CHECK_LEN;
+// HMAC-SHA256 over the header and the encrypted data, appended once the recording is finished
+const TAG_LEN: usize = 32;
+
+// Number of PBKDF2 rounds for deriving the keys from the passphrase
+const KDF_ROUNDS: usize = 100_000;
+
+// Encrypted files start with a header containing everything needed for decrypting them apart from
+// the passphrase, followed by the AES-256-CTR encrypted data and the authentication tag
+//
+// CTR mode allows encrypting at arbitrary positions, which is needed as muxers seek back in the
+// file to rewrite headers. For the same reason the tag can only be calculated once everything is
+// written
+struct Header {
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    check: [u8; CHECK_LEN],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.check);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(String::from("Not an encrypted recording"));
+        }
+
+        let mut header = Header {
+            salt: [0; SALT_LEN],
+            nonce: [0; NONCE_LEN],
+            check: [0; CHECK_LEN],
+        };
+        let bytes = &bytes[MAGIC.len()..];
+        header.salt.copy_from_slice(&bytes[..SALT_LEN]);
+        header
+            .nonce
+            .copy_from_slice(&bytes[SALT_LEN..SALT_LEN + NONCE_LEN]);
+        header
+            .check
+            .copy_from_slice(&bytes[SALT_LEN + NONCE_LEN..HEADER_LEN - MAGIC.len()]);
+
+        Ok(header)
+    }
+}
+
+// Keys derived from a passphrase for encrypting recordings
+//
+// The same keys are used for all recordings until the passphrase is entered again, every
+// recording gets its own random nonce
+#[derive(Clone)]
+pub struct Key {
+    salt: [u8; SALT_LEN],
+    key: [u8; KEY_LEN],
+    mac_key: [u8; KEY_LEN],
+    check: [u8; CHECK_LEN],
+}
+
+impl Key {
+    // Derive the keys and the check value from the passphrase
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Self {
+        let mut derived = [0u8; 2 * KEY_LEN + CHECK_LEN];
+        pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), &salt, KDF_ROUNDS, &mut derived);
+
+        let mut key = Key {
+            salt,
+            key: [0; KEY_LEN],
+            mac_key: [0; KEY_LEN],
+            check: [0; CHECK_LEN],
+        };
+        key.key.copy_from_slice(&derived[..KEY_LEN]);
+        key.mac_key.copy_from_slice(&derived[KEY_LEN..2 * KEY_LEN]);
+        key.check.copy_from_slice(&derived[2 * KEY_LEN..]);
+
+        key
+    }
+
+    // Derive the keys with a random salt from the passphrase
+    //
+    // This takes a moment on purpose, so it happens on a separate thread and the callback is
+    // called on the main thread once done
+    pub fn derive_async<F: FnOnce(Key) + 'static>(passphrase: String, func: F) {
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+        thread::spawn(move || {
+            let salt = rand::random::<[u8; SALT_LEN]>();
+            let _ = sender.send(Key::derive(&passphrase, salt));
+        });
+
+        // The callback has to be stored in an Option to be able to move it out of the FnMut
+        // closure
+        let mut func = Some(func);
+        receiver.attach(None, move |key| {
+            let func = func.take().expect("Called multiple times");
+            func(key);
+
+            glib::Continue(false)
+        });
+    }
+
+    fn create_mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_varkey(&self.mac_key).expect("HMAC accepts keys of any length")
+    }
+}
+
+fn create_cipher(key: &Key, nonce: &[u8; NONCE_LEN]) -> Aes256Ctr {
+    Aes256Ctr::new(
+        GenericArray::from_slice(&key.key),
+        GenericArray::from_slice(nonce),
+    )
+}
+
+// Feed everything from the current position of the file up to the given length into the MAC
+fn input_mac<R: Read>(mac: &mut Hmac<Sha256>, reader: &mut R, len: u64) -> io::Result<()> {
+    let mut data = vec![0u8; 1024 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = std::cmp::min(remaining, data.len() as u64) as usize;
+        reader.read_exact(&mut data[..chunk])?;
+        mac.input(&data[..chunk]);
+        remaining -= chunk as u64;
+    }
+
+    Ok(())
+}
+
+// A new encrypted file that the sink writes to via its file descriptor
+//
+// It only becomes a complete encrypted file once it is finished after everything is written
+pub struct EncryptedFile {
+    file: File,
+    key: Key,
+}
+
+impl EncryptedFile {
+    // Create a new file with a header for the salt of the key and a random nonce, and return the
+    // cipher for encrypting the data following it
+    pub fn create(filename: &Path, key: &Key) -> Result<(Self, Aes256Ctr), String> {
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+
+        let header = Header {
+            salt: key.salt,
+            nonce,
+            check: key.check,
+        };
+
+        let file = File::create(filename)
+            .and_then(|mut file| file.write_all(&header.to_bytes()).map(|_| file))
+            .map_err(|err| format!("Failed to create {}: {}", filename.display(), err))?;
+
+        Ok((
+            EncryptedFile {
+                file,
+                key: key.clone(),
+            },
+            create_cipher(key, &nonce),
+        ))
+    }
+
+    pub fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    // Append the authentication tag over the header and the encrypted data
+    //
+    // This reads the whole file again, so should not be called from the main thread
+    pub fn finish(mut self) -> Result<(), String> {
+        let mut mac = self.key.create_mac();
+
+        let res = self.file.seek(SeekFrom::End(0)).and_then(|len| {
+            self.file.seek(SeekFrom::Start(0))?;
+            input_mac(&mut mac, &mut self.file, len)?;
+            self.file.write_all(&mac.result().code())?;
+            self.file.sync_all()
+        });
+
+        res.map_err(|err| format!("Failed to finish encrypted recording: {}", err))
+    }
+}
+
+// Encrypt everything the sink writes to the file, behind the header
+//
+// The muxer knows nothing about the header, so all its byte positions are shifted by the header
+// length. This costs some CPU time in the sink's streaming thread for every buffer
+pub fn encrypt_writes(sink: &gst::Element, cipher: Aes256Ctr) {
+    let state = Mutex::new((cipher, 0u64));
+    sink.get_static_pad("sink")
+        .expect("Sink has no sinkpad")
+        .add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+            move |_pad, info| {
+                let mut state = state.lock().unwrap();
+                let (ref mut cipher, ref mut position) = *state;
+
+                match info.data {
+                    Some(gst::PadProbeData::Buffer(ref mut buffer)) => {
+                        let buffer = buffer.make_mut();
+                        let size = buffer.get_size();
+                        let mut map = buffer
+                            .map_writable()
+                            .expect("Failed to map buffer writable");
+
+                        cipher.seek(*position);
+                        cipher.apply_keystream(map.as_mut_slice());
+                        *position += size as u64;
+                    }
+                    Some(gst::PadProbeData::Event(ref mut event)) => {
+                        let shifted = match event.view() {
+                            gst::EventView::Segment(e) => {
+                                match e.get_segment().downcast_ref::<gst::format::Bytes>() {
+                                    Some(segment) => {
+                                        let start = segment.get_start().0.unwrap_or(0);
+                                        *position = start;
+
+                                        let mut segment = segment.clone();
+                                        segment.set_start(start + HEADER_LEN as u64);
+                                        Some(
+                                            gst::Event::new_segment(&segment)
+                                                .seqnum(event.get_seqnum())
+                                                .build(),
+                                        )
+                                    }
+                                    None => None,
+                                }
+                            }
+                            _ => None,
+                        };
+
+                        if let Some(shifted) = shifted {
+                            *event = shifted;
+                        }
+                    }
+                    _ => (),
+                }
+
+                gst::PadProbeReturn::Ok
+            },
+        );
+}
+
+// Decrypt an encrypted recording into a new file
+//
+// Nothing is written unless the authentication tag of the whole file is valid
+pub fn decrypt_file(input: &Path, output: &Path, passphrase: &str) -> Result<(), String> {
+    let read_error = |err: io::Error| format!("Failed to read {}: {}", input.display(), err);
+
+    let mut input_file =
+        File::open(input).map_err(|err| format!("Failed to open {}: {}", input.display(), err))?;
+    let len = input_file.metadata().map_err(read_error)?.len();
+    if len < (HEADER_LEN + TAG_LEN) as u64 {
+        return Err(String::from("Not an encrypted recording or incomplete"));
+    }
+
+    let mut header = [0u8; HEADER_LEN];
+    input_file.read_exact(&mut header).map_err(read_error)?;
+    let header = Header::from_bytes(&header)?;
+
+    let key = Key::derive(passphrase, header.salt);
+    if key.check != header.check {
+        return Err(String::from("Wrong passphrase"));
+    }
+
+    // Check the tag first, so that no modified data is ever decrypted
+    let data_len = len - (HEADER_LEN + TAG_LEN) as u64;
+    let mut mac = key.create_mac();
+    input_file.seek(SeekFrom::Start(0)).map_err(read_error)?;
+    input_mac(&mut mac, &mut input_file, HEADER_LEN as u64 + data_len).map_err(read_error)?;
+    let mut tag = [0u8; TAG_LEN];
+    input_file.read_exact(&mut tag).map_err(read_error)?;
+    if mac.verify(&tag).is_err() {
+        return Err(String::from("The recording was modified or is incomplete"));
+    }
+
+    let mut cipher = create_cipher(&key, &header.nonce);
+
+    let mut output_file = File::create(output)
+        .map_err(|err| format!("Failed to create {}: {}", output.display(), err))?;
+
+    input_file
+        .seek(SeekFrom::Start(HEADER_LEN as u64))
+        .map_err(read_error)?;
+    let mut data = vec![0u8; 1024 * 1024];
+    let mut remaining = data_len;
+    while remaining > 0 {
+        let chunk = std::cmp::min(remaining, data.len() as u64) as usize;
+        input_file
+            .read_exact(&mut data[..chunk])
+            .map_err(read_error)?;
+        remaining -= chunk as u64;
+
+        cipher.apply_keystream(&mut data[..chunk]);
+        output_file
+            .write_all(&data[..chunk])
+            .map_err(|err| format!("Failed to write {}: {}", output.display(), err))?;
+    }
+
+    Ok(())
+}
@@ -1,11 +1,15 @@
+use atk::prelude::*;
 use gio;
 use gtk::{self, prelude::*};
 
 use crate::app::{Action, RecordState, SnapshotState};
 
 pub struct HeaderBar {
+    header_bar: gtk::HeaderBar,
     snapshot: gtk::ToggleButton,
     record: gtk::ToggleButton,
+    pause: gtk::ToggleButton,
+    grid: gtk::ToggleButton,
 }
 
 // Create headerbar for the application
@@ -23,22 +27,70 @@ impl HeaderBar {
         let main_menu_image =
             gtk::Image::new_from_icon_name(Some("open-menu-symbolic"), gtk::IconSize::Menu);
         main_menu.set_image(Some(&main_menu_image));
+        set_accessible_name(&main_menu, "Menu");
 
         // Create the menu model with the menu items. These directly activate our application
         // actions by their name
         let main_menu_model = gio::Menu::new();
         main_menu_model.append(Some("Settings"), Some(Action::Settings.full_name()));
+        main_menu_model.append(Some("Reset camera"), Some(Action::Reset.full_name()));
+        #[cfg(target_os = "linux")]
+        main_menu_model.append(Some("Share screen…"), Some(Action::ShareScreen.full_name()));
+        main_menu_model.append(
+            Some("Play last recording"),
+            Some(Action::PlayRecording.full_name()),
+        );
+        main_menu_model.append(
+            Some("Drop frames while recording"),
+            Some(Action::DropFrames(false).full_name()),
+        );
+        main_menu_model.append(
+            Some("Fullscreen"),
+            Some(Action::Fullscreen(false).full_name()),
+        );
+        main_menu_model.append(
+            Some("Show statistics"),
+            Some(Action::ShowStats(false).full_name()),
+        );
+        main_menu_model.append(
+            Some("Export settings…"),
+            Some(Action::ExportSettings.full_name()),
+        );
+        main_menu_model.append(
+            Some("Import settings…"),
+            Some(Action::ImportSettings.full_name()),
+        );
+        main_menu_model.append(
+            Some("Keyboard shortcuts"),
+            Some(Action::Shortcuts.full_name()),
+        );
         main_menu_model.append(Some("About"), Some(Action::About.full_name()));
         main_menu.set_menu_model(Some(&main_menu_model));
 
         // And place it on the right (end) side of the header bar
         header_bar.pack_end(&main_menu);
 
+        // Create grid button and let it trigger the show-grid action
+        let grid_button = gtk::ToggleButton::new();
+        let grid_button_image =
+            gtk::Image::new_from_icon_name(Some("view-grid-symbolic"), gtk::IconSize::Menu);
+        grid_button.set_image(Some(&grid_button_image));
+        set_accessible_name(&grid_button, "Show grid");
+
+        grid_button.connect_toggled(|grid_button| {
+            let app = gio::Application::get_default().expect("No default application");
+            Action::ShowGrid(grid_button.get_active()).trigger(&app);
+        });
+
+        // Place the grid button left of the menu
+        header_bar.pack_end(&grid_button);
+
         // Create snapshot button and let it trigger the snapshot action
         let snapshot_button = gtk::ToggleButton::new();
         let snapshot_button_image =
             gtk::Image::new_from_icon_name(Some("camera-photo-symbolic"), gtk::IconSize::Menu);
         snapshot_button.set_image(Some(&snapshot_button_image));
+        set_accessible_name(&snapshot_button, "Take snapshot");
 
         snapshot_button.connect_toggled(|snapshot_button| {
             let app = gio::Application::get_default().expect("No default application");
@@ -54,6 +106,7 @@ impl HeaderBar {
         let record_button_image =
             gtk::Image::new_from_icon_name(Some("media-record"), gtk::IconSize::Menu);
         record_button.set_image(Some(&record_button_image));
+        set_accessible_name(&record_button, "Record");
 
         record_button.connect_toggled(|record_button| {
             let app = gio::Application::get_default().expect("No default application");
@@ -63,12 +116,35 @@ impl HeaderBar {
         // Place the record button on the left, right of the snapshot button
         header_bar.pack_start(&record_button);
 
+        // Create pause button and let it trigger the pause-recording action. This is only shown
+        // while recording, so together with the record button it shows whether we're idle,
+        // recording or paused
+        let pause_button = gtk::ToggleButton::new();
+        let pause_button_image = gtk::Image::new_from_icon_name(
+            Some("media-playback-pause-symbolic"),
+            gtk::IconSize::Menu,
+        );
+        pause_button.set_image(Some(&pause_button_image));
+        set_accessible_name(&pause_button, "Pause recording");
+        pause_button.set_no_show_all(true);
+
+        pause_button.connect_toggled(|pause_button| {
+            let app = gio::Application::get_default().expect("No default application");
+            Action::PauseRecording(pause_button.get_active()).trigger(&app);
+        });
+
+        // Place the pause button right of the record button
+        header_bar.pack_start(&pause_button);
+
         // Insert the headerbar as titlebar into the window
         window.set_titlebar(Some(&header_bar));
 
         HeaderBar {
+            header_bar,
             snapshot: snapshot_button,
             record: record_button,
+            pause: pause_button,
+            grid: grid_button,
         }
     }
 
@@ -79,4 +155,42 @@ impl HeaderBar {
     pub fn set_record_active(&self, active: bool) {
         self.record.set_active(active);
     }
+
+    pub fn is_record_active(&self) -> bool {
+        self.record.get_active()
+    }
+
+    // Show the pause button, or hide and release it if not recording
+    pub fn set_pause_visible(&self, visible: bool) {
+        if !visible {
+            self.pause.set_active(false);
+        }
+        self.pause.set_visible(visible);
+    }
+
+    pub fn set_pause_active(&self, active: bool) {
+        self.pause.set_active(active);
+    }
+
+    pub fn set_grid_active(&self, active: bool) {
+        self.grid.set_active(active);
+    }
+
+    // Hide the header bar, e.g. while fullscreen
+    pub fn set_visible(&self, visible: bool) {
+        self.header_bar.set_visible(visible);
+    }
+
+    // Show some secondary information below the title, or nothing if None
+    pub fn set_subtitle(&self, subtitle: Option<&str>) {
+        self.header_bar.set_subtitle(subtitle);
+    }
+}
+
+// Give icon-only buttons a name for screen readers, which is also shown as tooltip
+fn set_accessible_name<W: IsA<gtk::Widget>>(widget: &W, name: &str) {
+    widget.set_tooltip_text(Some(name));
+    if let Some(accessible) = widget.get_accessible() {
+        accessible.set_name(name);
+    }
 }
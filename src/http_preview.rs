@@ -0,0 +1,223 @@
+use gio::{self, prelude::*};
+use glib;
+use gtk;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error;
+use std::rc::Rc;
+
+use crate::pipeline::{Pipeline, PipelineWeak};
+
+// Boundary between the single JPEG frames in the multipart stream
+const BOUNDARY: &str = "frame";
+
+// Maximum number of frames waiting to be sent to a single client. Further frames are dropped for
+// that client until it has caught up again
+const MAX_QUEUED_FRAMES: usize = 2;
+
+// Minimal HTTP server for remote monitoring
+//
+// Every client gets a never-ending multipart response with one JPEG frame of the preview per
+// second. This works in all browsers and most video players without needing a full RTSP server.
+//
+// Everything here runs on the main thread as part of the main loop. The client sockets are
+// non-blocking, so a slow client only gets fewer frames but never blocks the application.
+//
+// By default only connections from the local machine are accepted, e.g. via an SSH tunnel.
+pub struct HttpPreview {
+    port: u16,
+    remote: bool,
+    service: gio::SocketService,
+    // This needs to be Option because we need to be able to take
+    // the value out in Drop::drop() removing the timeout id
+    timeout_id: Option<glib::SourceId>,
+}
+
+// A connected client and the data that still has to be sent to it
+struct Client {
+    // Keeps the connection open
+    _connection: gio::SocketConnection,
+    socket: gio::Socket,
+    // The first entry might already be sent partially, up to the offset
+    queue: VecDeque<Rc<Vec<u8>>>,
+    offset: usize,
+}
+
+impl Client {
+    fn new(connection: &gio::SocketConnection) -> Option<Self> {
+        let socket = connection.get_socket()?;
+        socket.set_blocking(false);
+
+        Some(Client {
+            _connection: connection.clone(),
+            socket,
+            queue: VecDeque::new(),
+            offset: 0,
+        })
+    }
+
+    // Queue data for sending, unless the client is already too far behind
+    fn queue(&mut self, data: Rc<Vec<u8>>) {
+        if self.queue.len() < MAX_QUEUED_FRAMES {
+            self.queue.push_back(data);
+        }
+    }
+
+    // Send as much of the queued data as possible without blocking, returns false if the client
+    // is gone
+    fn flush(&mut self) -> bool {
+        while let Some(data) = self.queue.front() {
+            match self
+                .socket
+                .send(&data[self.offset..], None::<&gio::Cancellable>)
+            {
+                Ok(len) => {
+                    self.offset += len as usize;
+                    if self.offset >= data.len() {
+                        self.queue.pop_front();
+                        self.offset = 0;
+                    }
+                }
+                Err(ref err)
+                    if err.kind::<gio::IOErrorEnum>() == Some(gio::IOErrorEnum::WouldBlock) =>
+                {
+                    return true;
+                }
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+impl HttpPreview {
+    // Start serving the preview on the given port, either only for the local machine or for
+    // everybody who can reach it if remote is set
+    pub fn new(
+        port: u16,
+        remote: bool,
+        pipeline: &Pipeline,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let service = gio::SocketService::new();
+        let res = if remote {
+            service.add_inet_port(port, None::<&glib::Object>)
+        } else {
+            let address = gio::InetSocketAddress::new(
+                &gio::InetAddress::new_loopback(gio::SocketFamily::Ipv4),
+                port,
+            );
+            service
+                .add_address(
+                    &address,
+                    gio::SocketType::Stream,
+                    gio::SocketProtocol::Tcp,
+                    None::<&glib::Object>,
+                )
+                .map(|_| ())
+        };
+        res.map_err(|err| format!("Failed to listen on port {}: {}", port, err))?;
+
+        let clients = Rc::new(RefCell::new(Vec::<Client>::new()));
+
+        // Send the HTTP response header to every new client and remember the connection for
+        // sending frames to it later
+        //
+        // We don't care about the actual request: we only serve one thing anyway
+        let clients_clone = clients.clone();
+        service.connect_incoming(move |_service, connection, _source| {
+            let header = format!(
+                "HTTP/1.0 200 OK\r\n\
+                 Content-Type: multipart/x-mixed-replace; boundary={}\r\n\
+                 Cache-Control: no-cache\r\n\
+                 Connection: close\r\n\r\n",
+                BOUNDARY
+            );
+
+            if let Some(mut client) = Client::new(connection) {
+                client.queue(Rc::new(header.into_bytes()));
+                if client.flush() {
+                    clients_clone.borrow_mut().push(client);
+                }
+            }
+
+            // We handled the connection
+            true
+        });
+
+        // Once per second queue the latest frame for all clients and forget about all clients
+        // that can't be written to anymore
+        let pipeline_weak = pipeline.downgrade();
+        let timeout_id = gtk::timeout_add(1000, move || {
+            Self::send_frame(&pipeline_weak, &clients);
+
+            glib::Continue(true)
+        });
+
+        service.start();
+
+        println!("Serving preview on http://localhost:{}", port);
+
+        Ok(HttpPreview {
+            port,
+            remote,
+            service,
+            timeout_id: Some(timeout_id),
+        })
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    fn send_frame(pipeline_weak: &PipelineWeak, clients: &RefCell<Vec<Client>>) {
+        let mut clients = clients.borrow_mut();
+        if clients.is_empty() {
+            return;
+        }
+
+        // Whatever could not be sent the last time is sent first, and if there's no new frame
+        // yet this at least makes progress with that
+        let pipeline = upgrade_weak!(pipeline_weak);
+        let frame = pipeline.get_http_preview_frame().map(|frame| {
+            let mut part = format!(
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                BOUNDARY,
+                frame.len()
+            )
+            .into_bytes();
+            part.extend_from_slice(&frame);
+            part.extend_from_slice(b"\r\n");
+            Rc::new(part)
+        });
+
+        flush_all(&mut clients);
+        if let Some(frame) = frame {
+            for client in clients.iter_mut() {
+                client.queue(frame.clone());
+            }
+        }
+        flush_all(&mut clients);
+    }
+}
+
+impl Drop for HttpPreview {
+    fn drop(&mut self) {
+        self.service.stop();
+        self.service.close();
+        glib::source::source_remove(self.timeout_id.take().expect("No timeout id"));
+    }
+}
+
+// Send as much as possible to all clients and forget about the ones that are gone
+fn flush_all(clients: &mut Vec<Client>) {
+    *clients = clients
+        .drain(..)
+        .filter_map(|mut client| if client.flush() { Some(client) } else { None })
+        .collect();
+}
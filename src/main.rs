@@ -2,16 +2,25 @@
 mod macros;
 mod about_dialog;
 mod app;
+#[cfg(target_os = "linux")]
+mod capture_portal;
+mod encryption;
 mod header_bar;
+mod http_preview;
 mod overlay;
 mod pipeline;
+mod playback;
 mod settings;
+mod shortcuts;
+mod sounds;
 mod utils;
 
 use gio::prelude::*;
 
 use std::env::args;
 use std::error;
+use std::io;
+use std::path::PathBuf;
 
 use crate::app::App;
 
@@ -20,14 +29,136 @@ use crate::app::App;
 // This is used for ensuring that there's only ever a single instance of our application
 pub const APPLICATION_NAME: &str = "com.github.gtk-rs.cameraview";
 
+// Captures that can be requested on the command line
+//
+// Usage: cameraview [--snapshot] [--record SECONDS]. If the application is already running, the
+// captures happen in the running instance and the new process exits right away. Otherwise the
+// application starts and the captures happen once the camera delivers frames. A recording is
+// stopped again after the given number of seconds, but the application keeps running in any case
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CommandLineOptions {
+    // Take a snapshot like the snapshot button does, i.e. with the configured timer
+    pub snapshot: bool,
+    // Record for this many seconds
+    pub record_secs: Option<u32>,
+}
+
+impl CommandLineOptions {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = CommandLineOptions::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--snapshot" => options.snapshot = true,
+                "--record" => {
+                    let secs = args
+                        .next()
+                        .and_then(|secs| secs.parse::<u32>().ok())
+                        .filter(|&secs| secs > 0)
+                        .ok_or("--record needs the number of seconds to record")?;
+                    options.record_secs = Some(secs);
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown argument {}\nUsage: cameraview [--snapshot] [--record SECONDS]",
+                        arg
+                    ))
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+// Decrypt an encrypted recording, reading the passphrase from stdin
+//
+// Usage: cameraview decrypt INPUT [OUTPUT]. By default the output is the input without ".enc".
+// The passphrase is not shown while typing it on Linux, but it is on other systems
+fn decrypt(args: &[String]) -> Result<(), Box<dyn error::Error>> {
+    let input = match args.get(0) {
+        Some(input) => PathBuf::from(input),
+        None => return Err("Usage: decrypt INPUT [OUTPUT]".into()),
+    };
+    let output = match args.get(1) {
+        Some(output) => PathBuf::from(output),
+        None if input.extension().map(|ext| ext == "enc").unwrap_or(false) => {
+            input.with_extension("")
+        }
+        None => return Err("No output file given".into()),
+    };
+
+    if cfg!(target_os = "linux") {
+        println!("Passphrase:");
+    } else {
+        println!("Passphrase (shown while typing):");
+    }
+    let passphrase = read_passphrase()?;
+    let passphrase = passphrase.trim_end_matches(|c| c == '\n' || c == '\r');
+
+    encryption::decrypt_file(&input, &output, passphrase)?;
+
+    println!("Decrypted to {}", output.display());
+
+    Ok(())
+}
+
+// Read a line from stdin without showing it on the terminal
+//
+// If stdin is not a terminal, e.g. because the passphrase is piped in, the line is read as is
+#[cfg(target_os = "linux")]
+fn read_passphrase() -> io::Result<String> {
+    use std::mem;
+
+    let mut termios = unsafe { mem::zeroed::<libc::termios>() };
+    let is_terminal = unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } == 0;
+    if is_terminal {
+        let mut no_echo = termios;
+        no_echo.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &no_echo) };
+    }
+
+    let mut passphrase = String::new();
+    let res = io::stdin().read_line(&mut passphrase);
+
+    if is_terminal {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) };
+        // The newline was not echoed either
+        println!();
+    }
+
+    res.map(|_| passphrase)
+}
+
+// Other systems have no way to hide the input here, which is mentioned in the prompt
+#[cfg(not(target_os = "linux"))]
+fn read_passphrase() -> io::Result<String> {
+    let mut passphrase = String::new();
+    io::stdin().read_line(&mut passphrase)?;
+    Ok(passphrase)
+}
+
 fn main() -> Result<(), Box<dyn error::Error>> {
+    // Decrypting recordings does not need any UI
+    let args = args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("decrypt") {
+        return decrypt(&args[2..]);
+    }
+
+    // Check the options already here, so that mistakes are reported by the process that was
+    // started even if the options are then handled by an already running instance
+    CommandLineOptions::parse(&args[1..])?;
+
     // Initialize GStreamer. This checks, among other things, what plugins are available
     gst::init()?;
 
-    // Create an application with our name and the default flags. By default, applications can only
-    // have a single instance and any second instance will only activate the first one again
-    let application =
-        gtk::Application::new(Some(APPLICATION_NAME), gio::ApplicationFlags::empty())?;
+    // Create an application with our name that handles the command line itself. Applications can
+    // only have a single instance and any second instance passes its command line to the first one
+    let application = gtk::Application::new(
+        Some(APPLICATION_NAME),
+        gio::ApplicationFlags::HANDLES_COMMAND_LINE,
+    )?;
 
     // On application startup (of the first instance) we create our application. A second instance
     // would not run this
@@ -36,7 +167,7 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     });
 
     // And now run the application until the end
-    application.run(&args().collect::<Vec<_>>());
+    application.run(&args);
 
     Ok(())
 }
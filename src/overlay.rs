@@ -1,8 +1,27 @@
 use gtk::{self, prelude::*};
 
+use std::cell::Cell;
+use std::rc::Rc;
+
 pub struct Overlay {
+    overlay: gtk::Overlay,
     // The Countdown label, hidden by default
     label: gtk::Label,
+    // Label for status information at the bottom, e.g. while buffering. Hidden by default
+    status_label: gtk::Label,
+    // Blinking dot at the top right while recording. Hidden by default
+    recording_indicator: gtk::Label,
+    // Label for the elapsed recording time next to the recording indicator. Hidden by default
+    elapsed_label: gtk::Label,
+    // Loudness of the recorded audio next to the elapsed time. Hidden by default
+    audio_level: gtk::LevelBar,
+    // Frame rate and bitrate at the top left, for debugging. Hidden by default
+    stats_label: gtk::Label,
+    // Rule-of-thirds grid over the video. Hidden by default
+    grid: gtk::DrawingArea,
+    // Displayed size of the video, for drawing the grid only over the video and not over the
+    // borders around it
+    video_size: Rc<Cell<Option<(u32, u32)>>>,
 }
 
 impl Overlay {
@@ -10,6 +29,47 @@ impl Overlay {
         // Create an overlay for showing the seconds until a snapshot This is hidden while we're
         // not doing a countdown
         let overlay = gtk::Overlay::new();
+
+        // The grid is drawn by GTK on top of the video sink's widget, so it is never part of
+        // snapshots or recordings. It is added first to be below all the labels, and lets clicks
+        // pass through to the video below
+        let grid = gtk::DrawingArea::new();
+        let video_size = Rc::new(Cell::new(None));
+        let video_size_clone = video_size.clone();
+        grid.connect_draw(move |grid, cr| {
+            let allocation = grid.get_allocation();
+            let (widget_width, widget_height) =
+                (f64::from(allocation.width), f64::from(allocation.height));
+
+            // The video is scaled to the widget size while keeping the aspect ratio and centered
+            let (width, height) = match video_size_clone.get() {
+                Some((width, height)) if width > 0 && height > 0 => {
+                    let (width, height) = (f64::from(width), f64::from(height));
+                    let scale = f64::min(widget_width / width, widget_height / height);
+                    (width * scale, height * scale)
+                }
+                _ => (widget_width, widget_height),
+            };
+            let (x, y) = ((widget_width - width) / 2.0, (widget_height - height) / 2.0);
+
+            cr.set_source_rgba(1.0, 1.0, 1.0, 0.6);
+            cr.set_line_width(1.0);
+            for i in 1..3 {
+                let offset = f64::from(i) / 3.0;
+                cr.move_to(x + width * offset, y);
+                cr.line_to(x + width * offset, y + height);
+                cr.move_to(x, y + height * offset);
+                cr.line_to(x + width, y + height * offset);
+            }
+            cr.stroke();
+
+            gtk::Inhibit(false)
+        });
+        grid.set_no_show_all(true);
+        grid.set_visible(false);
+        overlay.add_overlay(&grid);
+        overlay.set_overlay_pass_through(&grid, true);
+
         let label = gtk::Label::new(Some("0"));
 
         // Our label should have the countdown-label style from the stylesheet
@@ -29,13 +89,82 @@ impl Overlay {
         // Add the label to our overlay
         overlay.add_overlay(&label);
 
+        // The status label is at the bottom center and styled via the stylesheet like the
+        // countdown label
+        let status_label = gtk::Label::new(None);
+        gtk::WidgetExt::set_name(&status_label, "status-label");
+        status_label.set_halign(gtk::Align::Center);
+        status_label.set_valign(gtk::Align::End);
+        status_label.set_no_show_all(true);
+        status_label.set_visible(false);
+        overlay.add_overlay(&status_label);
+
+        // The recording indicator and the elapsed recording time are at the top right, out of
+        // the way of the other labels. The indicator blinks via the stylesheet
+        let recording_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        recording_box.set_halign(gtk::Align::End);
+        recording_box.set_valign(gtk::Align::Start);
+
+        let recording_indicator = gtk::Label::new(Some("●"));
+        gtk::WidgetExt::set_name(&recording_indicator, "recording-indicator");
+        recording_indicator.set_no_show_all(true);
+        recording_indicator.set_visible(false);
+        recording_box.pack_start(&recording_indicator, false, false, 0);
+
+        let elapsed_label = gtk::Label::new(None);
+        gtk::WidgetExt::set_name(&elapsed_label, "elapsed-label");
+        elapsed_label.set_no_show_all(true);
+        elapsed_label.set_visible(false);
+        recording_box.pack_start(&elapsed_label, false, false, 0);
+
+        // The level bar goes from silence at 0 to the loudest possible audio at 1, see
+        // set_audio_level()
+        let audio_level = gtk::LevelBar::new_for_interval(0.0, 1.0);
+        gtk::WidgetExt::set_name(&audio_level, "audio-level");
+        audio_level.set_size_request(80, -1);
+        audio_level.set_valign(gtk::Align::Center);
+        audio_level.set_no_show_all(true);
+        audio_level.set_visible(false);
+        recording_box.pack_start(&audio_level, false, false, 0);
+
+        overlay.add_overlay(&recording_box);
+
+        // The statistics are at the top left, opposite of the recording indicator
+        let stats_label = gtk::Label::new(None);
+        gtk::WidgetExt::set_name(&stats_label, "stats-label");
+        stats_label.set_halign(gtk::Align::Start);
+        stats_label.set_valign(gtk::Align::Start);
+        stats_label.set_no_show_all(true);
+        stats_label.set_visible(false);
+        overlay.add_overlay(&stats_label);
+
         // Add the actual window content
         overlay.add(content);
 
         // Add ourselves to the container, i.e. our window
         container.add(&overlay);
 
-        Overlay { label }
+        Overlay {
+            overlay,
+            label,
+            status_label,
+            recording_indicator,
+            elapsed_label,
+            audio_level,
+            stats_label,
+            grid,
+            video_size,
+        }
+    }
+
+    // Replace the actual window content, e.g. after the pipeline was recreated
+    pub fn set_content<U: IsA<gtk::Widget>>(&self, content: &U) {
+        if let Some(child) = self.overlay.get_child() {
+            self.overlay.remove(&child);
+        }
+
+        self.overlay.add(content);
+        content.show_all();
     }
 
     pub fn set_label_visible(&self, visible: bool) {
@@ -45,4 +174,78 @@ impl Overlay {
     pub fn set_label_text(&self, text: &str) {
         self.label.set_text(text);
     }
+
+    // Use a high contrast color scheme and larger text for all labels, for better readability
+    pub fn set_high_contrast(&self, high_contrast: bool) {
+        for label in &[&self.label, &self.status_label, &self.elapsed_label] {
+            let style_context = label.get_style_context();
+            if high_contrast {
+                style_context.add_class("high-contrast");
+            } else {
+                style_context.remove_class("high-contrast");
+            }
+        }
+    }
+
+    // Show or hide the red dot in the corner that shows that a recording is running
+    pub fn set_recording_indicator_visible(&self, visible: bool) {
+        self.recording_indicator.set_visible(visible);
+    }
+
+    // Show the given elapsed recording time, or hide the elapsed label if None
+    pub fn set_elapsed_text(&self, text: Option<&str>) {
+        match text {
+            Some(text) => {
+                self.elapsed_label.set_text(text);
+                self.elapsed_label.set_visible(true);
+            }
+            None => self.elapsed_label.set_visible(false),
+        }
+    }
+
+    // Show the given statistics, or hide the statistics label if None
+    pub fn set_stats_text(&self, text: Option<&str>) {
+        match text {
+            Some(text) => {
+                self.stats_label.set_text(text);
+                self.stats_label.set_visible(true);
+            }
+            None => self.stats_label.set_visible(false),
+        }
+    }
+
+    pub fn set_grid_visible(&self, visible: bool) {
+        self.grid.set_visible(visible);
+    }
+
+    // Set the displayed size of the video, or None if unknown
+    pub fn set_video_size(&self, size: Option<(u32, u32)>) {
+        self.video_size.set(size);
+        self.grid.queue_draw();
+    }
+
+    // Show the given audio level in dB, or hide the level bar if None
+    //
+    // Everything below -60 dB is shown as silence
+    pub fn set_audio_level(&self, level: Option<f64>) {
+        match level {
+            Some(level) => {
+                self.audio_level
+                    .set_value(((level + 60.0) / 60.0).max(0.0).min(1.0));
+                self.audio_level.set_visible(true);
+            }
+            None => self.audio_level.set_visible(false),
+        }
+    }
+
+    // Show the given status text, or hide the status label if None
+    pub fn set_status_text(&self, text: Option<&str>) {
+        match text {
+            Some(text) => {
+                self.status_label.set_text(text);
+                self.status_label.set_visible(true);
+            }
+            None => self.status_label.set_visible(false),
+        }
+    }
 }
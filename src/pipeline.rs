@@ -1,19 +1,701 @@
+use gdk_pixbuf;
 use glib;
 use gst::{self, prelude::*};
 use gst_video;
 use gtk;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::error;
+use std::fmt;
+use std::fs::{self, File};
 use std::ops;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use fragile;
 
-use chrono::prelude::*;
+use serde::Serialize;
+use serde_any;
 
-use crate::settings::{RecordFormat, SnapshotFormat};
-use crate::utils;
+use zip;
+
+#[cfg(target_os = "linux")]
+use crate::capture_portal::Screencast;
+use crate::encryption;
+use crate::settings::{
+    AudioSource, Container, FileCollision, H264Profile, NamingMode, OverlayPosition, Settings,
+    SnapshotFormat, SnapshotSource, VideoCodec, VideoFlipMethod, MAX_ZOOM,
+};
+use crate::utils::{self, OverwriteChoice};
+
+// Maximum time in milliseconds to wait for the next frame for a snapshot, afterwards the last frame
+// is used instead
+const SNAPSHOT_FRAME_TIMEOUT_MS: u32 = 1000;
+
+// Distance of the watermark from the edges of the video, in pixels
+const WATERMARK_MARGIN: i32 = 16;
+
+// Frame rate of recordings with constant frame rate if no capture frame rate is configured
+const DEFAULT_RECORD_FRAMERATE: u32 = 30;
+
+// Error when there is no camera at all, as opposed to a camera that does not work
+#[derive(Debug)]
+pub struct NoCameraError;
+
+impl fmt::Display for NoCameraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No camera found")
+    }
+}
+
+impl error::Error for NoCameraError {}
+
+// Weight of the newest measurement for the smoothed frame rate
+//
+// With one measurement per second this averages over roughly the last four seconds
+const FPS_SMOOTHING_FACTOR: f64 = 0.25;
+
+// Frame rate statistics of the camera stream
+//
+// Frames are counted from the streaming thread and collected once per second from the main thread
+#[derive(Default)]
+struct FpsStats {
+    frames: Arc<AtomicUsize>,
+    // Number of frames during the last second
+    raw: u32,
+    // Exponential moving average of the raw values, None until the first frame arrived
+    smoothed: Option<f64>,
+}
+
+impl FpsStats {
+    // Collect the frames since the last call, must be called once per second
+    fn update(&mut self) {
+        self.raw = self.frames.swap(0, Ordering::Relaxed) as u32;
+
+        let raw = f64::from(self.raw);
+        self.smoothed = match self.smoothed {
+            None if self.raw == 0 => None,
+            None => Some(raw),
+            Some(smoothed) => Some(smoothed + FPS_SMOOTHING_FACTOR * (raw - smoothed)),
+        };
+    }
+
+    // Forget everything about the previous stream, e.g. after the camera was reconnected
+    fn reset(&mut self) {
+        self.frames.store(0, Ordering::Relaxed);
+        self.raw = 0;
+        self.smoothed = None;
+    }
+}
+
+// Events from the pipeline that are relevant for the UI
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineEvent {
+    // Buffering with the given percentage, 100 once buffering is done
+    Buffering(i32),
+    // Recording stopped on its own, e.g. because of an error
+    RecordingStopped,
+    // Recording was stopped and the file is completely written now
+    RecordingFinished {
+        filename: PathBuf,
+        dropped_frames: usize,
+    },
+    // A snapshot is being converted and written to disk
+    SnapshotSaving,
+    // Writing the snapshot is finished, with the file it was written to if successful. This is
+    // the archive if snapshots are collected in one
+    SnapshotSaved(Option<PathBuf>),
+    // The first frame was captured after starting
+    FirstFrame,
+    // The camera started a new stream, e.g. after it was reconnected or changed its format, with
+    // the size of its frames
+    StreamStarted {
+        width: u32,
+        height: u32,
+    },
+    // The camera failed, e.g. because it was unplugged. The pipeline is already shut down and has
+    // to be replaced
+    SourceError(String),
+    // Peak loudness of the configured audio source in dB, with 0 dB being the loudest possible.
+    // Posted regularly while the audio is monitored, see set_audio_monitor()
+    AudioLevel(f64),
+    // Measuring the audio level failed, e.g. because the microphone was unplugged, and was stopped
+    AudioMonitorFailed(String),
+    // The video has a new size, in square pixels as it is displayed
+    VideoSizeChanged {
+        width: u32,
+        height: u32,
+    },
+}
+
+// A running or stopping recording
+struct Recording {
+    bin: gst::Bin,
+    filename: PathBuf,
+    // Frames that entered and left the recording queue, the difference was dropped
+    frames_in: Arc<AtomicUsize>,
+    frames_out: Arc<AtomicUsize>,
+    // Bytes written by the muxer and when the recording was started, for the bitrate
+    bytes_written: Arc<AtomicU64>,
+    start_time: Instant,
+    // Bytes written until the last update of the current bitrate, and the bitrate during the
+    // second before that in bytes per second
+    last_bytes_written: Cell<u64>,
+    current_bitrate: Cell<Option<f64>>,
+    // Only if the file was pre-allocated
+    preallocation: Option<Preallocation>,
+    // Only if metadata should be written next to the recording
+    metadata: Option<CaptureMetadata>,
+    // Only if the recording is encrypted. The sink writes to it via its file descriptor
+    encrypted_file: Option<encryption::EncryptedFile>,
+    // Running time of the pipeline when the recording was started, chapter markers are relative
+    // to this
+    start_running_time: gst::ClockTime,
+    // Chapter markers with their position in the recording, in the order they were added
+    chapters: Vec<(gst::ClockTime, String)>,
+    // While set, all buffers are dropped before they reach the recording
+    paused: Arc<AtomicBool>,
+    // Running time of the pipeline when the recording was paused, if it is paused right now
+    paused_at: Option<gst::ClockTime>,
+    // Sum of all pauses so far, which is removed from the timestamps of the recording
+    paused_duration: gst::ClockTime,
+}
+
+impl Recording {
+    // Pads through which all data flows into the recording, i.e. the tee source pad and the audio
+    // source's source pad if audio is recorded
+    fn get_input_pads(&self) -> Vec<gst::Pad> {
+        let mut pads = Vec::new();
+
+        if let Some(peer) = self
+            .bin
+            .get_static_pad("sink")
+            .and_then(|sinkpad| sinkpad.get_peer())
+        {
+            pads.push(peer);
+        }
+        if let Some(srcpad) = self
+            .bin
+            .get_by_name("audiosrc")
+            .and_then(|audiosrc| audiosrc.get_static_pad("src"))
+        {
+            pads.push(srcpad);
+        }
+
+        pads
+    }
+}
+
+// Metadata about a snapshot or recording, written as JSON next to it
+#[derive(Serialize, Debug, Clone)]
+struct CaptureMetadata {
+    timestamp: String,
+    device_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: String,
+    settings: Settings,
+}
+
+impl CaptureMetadata {
+    // Serialize the metadata as JSON, e.g. for adding it to a snapshot archive
+    fn to_json(&self) -> Result<String, String> {
+        serde_any::to_string(self, serde_any::Format::Json)
+            .map_err(|err| format!("Failed to serialize metadata: {}", err))
+    }
+
+    // Write the metadata to the file name of the capture plus ".json"
+    //
+    // Failing to do so does not affect the capture itself
+    fn write(&self, filename: &Path) -> Result<(), String> {
+        let mut path = filename.as_os_str().to_owned();
+        path.push(".json");
+        let path = PathBuf::from(path);
+
+        serde_any::to_file(&path, self)
+            .map_err(|err| format!("Failed to write metadata file {}: {}", path.display(), err))
+    }
+}
+
+// Write the chapter markers as WebVTT chapters to the file name of the recording plus
+// ".chapters.vtt", which is understood by most video players and editors
+//
+// Every chapter lasts until the next one, the last one until the end of the recording
+fn write_chapters(
+    filename: &Path,
+    chapters: &[(gst::ClockTime, String)],
+    end: gst::ClockTime,
+) -> Result<(), String> {
+    fn format_time(time: gst::ClockTime) -> String {
+        let ms = time.mseconds().unwrap_or(0);
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            ms / 3_600_000,
+            (ms / 60_000) % 60,
+            (ms / 1000) % 60,
+            ms % 1000
+        )
+    }
+
+    let mut vtt = String::from("WEBVTT\n");
+    for (i, &(start, ref label)) in chapters.iter().enumerate() {
+        let next = chapters.get(i + 1).map(|&(next, _)| next).unwrap_or(end);
+        vtt.push_str(&format!(
+            "\n{}\n{} --> {}\n{}\n",
+            i + 1,
+            format_time(start),
+            format_time(std::cmp::max(start, next)),
+            label
+        ));
+    }
+
+    let mut path = filename.as_os_str().to_owned();
+    path.push(".chapters.vtt");
+    let path = PathBuf::from(path);
+
+    fs::write(&path, vtt)
+        .map_err(|err| format!("Failed to write chapters file {}: {}", path.display(), err))
+}
+
+// Get the file name for the next snapshot in the configured directory
+pub fn get_snapshot_filename(settings: &Settings) -> Result<PathBuf, String> {
+    utils::create_media_directory(&settings.snapshot_directory)?;
+
+    let extension = settings.snapshot_format.extension();
+    let name = match settings.snapshot_naming {
+        NamingMode::Timestamp => utils::get_template_file_name(
+            &settings.snapshot_directory,
+            &settings.snapshot_filename_template,
+            settings.filename_timezone,
+            extension,
+        )
+        .map_err(|err| format!("Invalid snapshot file name template: {}", err))?,
+        NamingMode::Sequential => {
+            utils::get_sequential_file_name(&settings.snapshot_directory, "IMG", extension)
+        }
+    };
+
+    Ok(settings.snapshot_directory.join(name))
+}
+
+// Keep the number of a sequentially named snapshot in the configured directory once it is written
+fn commit_snapshot_filename(settings: &Settings, filename: &Path) {
+    if settings.snapshot_naming == NamingMode::Sequential
+        && filename.parent() == Some(settings.snapshot_directory.as_path())
+    {
+        utils::commit_sequential_file_name(filename, "IMG");
+    }
+}
+
+// Get the file name for the next recording in the given container in the configured directory
+pub fn get_recording_filename(
+    settings: &Settings,
+    container: Container,
+) -> Result<PathBuf, String> {
+    utils::create_media_directory(&settings.record_directory)?;
+
+    let extension = container.extension();
+    let name = match settings.record_naming {
+        NamingMode::Timestamp => utils::get_template_file_name(
+            &settings.record_directory,
+            &settings.record_filename_template,
+            settings.filename_timezone,
+            extension,
+        )
+        .map_err(|err| format!("Invalid recording file name template: {}", err))?,
+        NamingMode::Sequential => {
+            utils::get_sequential_file_name(&settings.record_directory, "VID", extension)
+        }
+    };
+
+    Ok(settings.record_directory.join(name))
+}
+
+// Keep the number of a sequentially named recording in the configured directory once it is
+// finished
+fn commit_recording_filename(settings: &Settings, filename: &Path) {
+    if settings.record_naming == NamingMode::Sequential
+        && filename.parent() == Some(settings.record_directory.as_path())
+    {
+        utils::commit_sequential_file_name(filename, "VID");
+    }
+}
+
+// Location pattern for splitmuxsink, derived from the file name of the recording
+//
+// The fragments are numbered starting at zero before the extension, e.g. "Recording 2018-11-24
+// 12-00-00.mp4" is split into "Recording 2018-11-24 12-00-00_00000.mp4", "Recording 2018-11-24
+// 12-00-00_00001.mp4" and so on
+fn get_split_location(filename: &Path, index: Option<u32>) -> PathBuf {
+    let mut name = filename
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace('%', "%%"))
+        .unwrap_or_default();
+    match index {
+        Some(index) => name.push_str(&format!("_{:05}", index)),
+        None => name.push_str("_%05d"),
+    }
+    if let Some(extension) = filename.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+
+    filename.with_file_name(name)
+}
+
+// Convert a YUV value with BT.601 coefficients and limited range to RGB
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let y = 1.164 * (f64::from(y) - 16.0);
+    let u = f64::from(u) - 128.0;
+    let v = f64::from(v) - 128.0;
+    let clamp = |c: f64| c.round().max(0.0).min(255.0) as u8;
+
+    [
+        clamp(y + 1.596 * v),
+        clamp(y - 0.392 * u - 0.813 * v),
+        clamp(y + 2.017 * u),
+    ]
+}
+
+// Read the RGB value of a single pixel of a frame
+//
+// Only the formats that cameras and the preview commonly use are handled, for everything else
+// this gives None
+fn read_pixel(
+    frame: &gst_video::VideoFrame<gst_video::video_frame::Readable>,
+    x: u32,
+    y: u32,
+) -> Option<[u8; 3]> {
+    use gst_video::VideoFormat;
+
+    let (x, y) = (x as usize, y as usize);
+    let stride = |plane: u32| frame.plane_stride()[plane as usize] as usize;
+    let byte = |plane: u32, offset: usize| -> Option<u8> {
+        frame
+            .plane_data(plane)
+            .ok()
+            .and_then(|data| data.get(offset).cloned())
+    };
+
+    // Size of a pixel and the offsets of red, green and blue in it for packed RGB formats
+    let rgb = match frame.format() {
+        VideoFormat::Rgb => Some((3, [0, 1, 2])),
+        VideoFormat::Bgr => Some((3, [2, 1, 0])),
+        VideoFormat::Rgbx | VideoFormat::Rgba => Some((4, [0, 1, 2])),
+        VideoFormat::Bgrx | VideoFormat::Bgra => Some((4, [2, 1, 0])),
+        VideoFormat::Xrgb | VideoFormat::Argb => Some((4, [1, 2, 3])),
+        VideoFormat::Xbgr | VideoFormat::Abgr => Some((4, [3, 2, 1])),
+        _ => None,
+    };
+    if let Some((pixel_size, [r, g, b])) = rgb {
+        let offset = y * stride(0) + x * pixel_size;
+        return Some([
+            byte(0, offset + r)?,
+            byte(0, offset + g)?,
+            byte(0, offset + b)?,
+        ]);
+    }
+
+    // Chroma is subsampled in all of these, horizontally and for the planar ones also vertically
+    let (luma, u, v) = match frame.format() {
+        VideoFormat::I420 | VideoFormat::Yv12 => {
+            let (u_plane, v_plane) = if frame.format() == VideoFormat::I420 {
+                (1, 2)
+            } else {
+                (2, 1)
+            };
+            (
+                byte(0, y * stride(0) + x)?,
+                byte(u_plane, (y / 2) * stride(u_plane) + x / 2)?,
+                byte(v_plane, (y / 2) * stride(v_plane) + x / 2)?,
+            )
+        }
+        VideoFormat::Nv12 => {
+            let offset = (y / 2) * stride(1) + (x / 2) * 2;
+            (
+                byte(0, y * stride(0) + x)?,
+                byte(1, offset)?,
+                byte(1, offset + 1)?,
+            )
+        }
+        VideoFormat::Yuy2 => {
+            let offset = y * stride(0) + (x / 2) * 4;
+            (
+                byte(0, offset + (x % 2) * 2)?,
+                byte(0, offset + 1)?,
+                byte(0, offset + 3)?,
+            )
+        }
+        VideoFormat::Uyvy => {
+            let offset = y * stride(0) + (x / 2) * 4;
+            (
+                byte(0, offset + 1 + (x % 2) * 2)?,
+                byte(0, offset)?,
+                byte(0, offset + 2)?,
+            )
+        }
+        _ => return None,
+    };
+
+    Some(yuv_to_rgb(luma, u, v))
+}
+
+// Snapshots of a burst all get the file name of the first one, followed by their number
+struct SnapshotBurst {
+    // File name of the first snapshot without number, once it is known
+    filename: Option<PathBuf>,
+    // Number of the next snapshot, starting at 1
+    next: u32,
+}
+
+impl SnapshotBurst {
+    fn new() -> Self {
+        SnapshotBurst {
+            filename: None,
+            next: 1,
+        }
+    }
+
+    // Get the file name for the next snapshot, e.g. "Snapshot-001.jpg"
+    fn next_filename(&mut self, settings: &Settings) -> Result<PathBuf, String> {
+        if self.filename.is_none() {
+            self.filename = Some(get_snapshot_filename(settings)?);
+        }
+        let filename = self.filename.as_ref().expect("No burst file name");
+
+        let mut name = filename
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        name.push_str(&format!("-{:03}", self.next));
+        if let Some(extension) = filename.extension() {
+            name.push('.');
+            name.push_str(&extension.to_string_lossy());
+        }
+        self.next += 1;
+
+        Ok(filename.with_file_name(name))
+    }
+}
+
+// ZIP archive that collects all snapshots of a burst or interval session
+//
+// The snapshots are converted and written on other threads, so the archive is shared with them.
+// Once finished, nothing can be added anymore and snapshots arriving late are written as separate
+// files instead
+struct SnapshotArchive {
+    filename: PathBuf,
+    writer: zip::ZipWriter<File>,
+    // Names of all files in the archive so far
+    names: HashSet<PathBuf>,
+    finished: bool,
+}
+
+impl SnapshotArchive {
+    fn new(filename: PathBuf) -> Result<Self, String> {
+        let file = File::create(&filename).map_err(|err| {
+            format!(
+                "Failed to create snapshot archive {}: {}",
+                filename.display(),
+                err
+            )
+        })?;
+
+        Ok(SnapshotArchive {
+            filename,
+            writer: zip::ZipWriter::new(file),
+            names: HashSet::new(),
+            finished: false,
+        })
+    }
+
+    // Get a file name that is not used in the archive yet
+    //
+    // Timestamp based file names collide if multiple snapshots are taken within the same second
+    fn get_unique_name(&self, name: &str) -> String {
+        let names = &self.names;
+        utils::get_unique_path_with(PathBuf::from(name), |path| names.contains(path))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // Add a file with the given name to the archive
+    //
+    // The snapshots are already compressed, so they're stored as is
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        use std::io::Write;
+
+        if self.finished {
+            return Err(format!(
+                "Snapshot archive {} is already finished, writing {} separately",
+                self.filename.display(),
+                name
+            ));
+        }
+
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+        self.names.insert(PathBuf::from(name));
+
+        self.writer
+            .start_file(name, options)
+            .map_err(|err| err.to_string())
+            .and_then(|_| self.writer.write_all(data).map_err(|err| err.to_string()))
+            .map_err(|err| {
+                format!(
+                    "Failed to write {} to snapshot archive {}: {}",
+                    name,
+                    self.filename.display(),
+                    err
+                )
+            })
+    }
+
+    // Write the central directory, without which the archive can't be opened
+    fn finish(&mut self) -> Result<(), String> {
+        self.finished = true;
+
+        self.writer.finish().map(|_| ()).map_err(|err| {
+            format!(
+                "Failed to finish snapshot archive {}: {}",
+                self.filename.display(),
+                err
+            )
+        })
+    }
+}
+
+// A pre-allocated recording file
+//
+// The muxer overwrites the pre-allocated file from the beginning, and once the recording is done
+// (or failed) the unused tail is cut off again on Drop
+struct Preallocation {
+    file: File,
+    // End of the data written by the muxer so far, in bytes
+    written: Arc<AtomicU64>,
+}
+
+impl Preallocation {
+    // Create the file and reserve the given number of bytes for it
+    //
+    // This only allocates the blocks in the filesystem without writing anything, so it does not
+    // block the main thread even for large sizes
+    fn new(filename: &Path, size: u64) -> Result<Self, String> {
+        let err_msg = |err| format!("Failed to pre-allocate {}: {}", filename.display(), err);
+
+        let file = File::create(filename).map_err(err_msg)?;
+        Self::allocate(&file, size).map_err(err_msg)?;
+
+        Ok(Preallocation {
+            file,
+            written: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    // Reserve the blocks of the file
+    //
+    // If the filesystem does not support this, only the size of the file is set. That gives a
+    // sparse file, which does not reserve any space but at least does not hurt either
+    #[cfg(target_os = "linux")]
+    fn allocate(file: &File, size: u64) -> std::io::Result<()> {
+        use std::io;
+        use std::os::unix::io::AsRawFd;
+
+        let res = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, size as libc::off_t) };
+        if res == 0 {
+            return Ok(());
+        }
+
+        match io::Error::last_os_error() {
+            ref err if err.raw_os_error() == Some(libc::EOPNOTSUPP) => file.set_len(size),
+            err => Err(err),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn allocate(file: &File, size: u64) -> std::io::Result<()> {
+        file.set_len(size)
+    }
+
+    // Keep track of where the muxer writes to in the file
+    //
+    // The muxer might seek back, e.g. to rewrite headers, so we remember the furthest position
+    fn track_writes(&self, sink: &gst::Element) {
+        let written = self.written.clone();
+        let position = AtomicU64::new(0);
+        sink.get_static_pad("sink")
+            .expect("Sink has no sinkpad")
+            .add_probe(
+                gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM,
+                move |_pad, info| {
+                    match info.data {
+                        Some(gst::PadProbeData::Buffer(ref buffer)) => {
+                            // Buffers only ever arrive from the streaming thread, one at a time
+                            let end = position.load(Ordering::Relaxed) + buffer.get_size() as u64;
+                            position.store(end, Ordering::Relaxed);
+                            if end > written.load(Ordering::Relaxed) {
+                                written.store(end, Ordering::Relaxed);
+                            }
+                        }
+                        Some(gst::PadProbeData::Event(ref event)) => {
+                            if let gst::EventView::Segment(e) = event.view() {
+                                if let Some(segment) =
+                                    e.get_segment().downcast_ref::<gst::format::Bytes>()
+                                {
+                                    if let Some(start) = segment.get_start().0 {
+                                        position.store(start, Ordering::Relaxed);
+                                    }
+                                }
+                            }
+                        }
+                        _ => (),
+                    }
+
+                    gst::PadProbeReturn::Ok
+                },
+            );
+    }
+}
+
+impl Drop for Preallocation {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(self.written.load(Ordering::Relaxed));
+    }
+}
+
+impl Recording {
+    // Number of frames dropped in the recording queue because the encoder or storage could not
+    // keep up. Only accurate once the recording is finalized
+    fn get_dropped_frames(&self) -> usize {
+        self.frames_in
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.frames_out.load(Ordering::Relaxed))
+    }
+
+    // Average bitrate of the recording so far in bytes per second
+    fn get_bitrate(&self) -> Option<f64> {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return None;
+        }
+
+        Some(self.bytes_written.load(Ordering::Relaxed) as f64 / elapsed)
+    }
+
+    // Collect the bytes written since the last call, must be called once per second
+    fn update_current_bitrate(&self) {
+        let bytes_written = self.bytes_written.load(Ordering::Relaxed);
+        let last_bytes_written = self.last_bytes_written.replace(bytes_written);
+        self.current_bitrate
+            .set(Some((bytes_written - last_bytes_written) as f64));
+    }
+}
 
 // Our refcounted pipeline struct for containing all the media state we have to carry around.
 #[derive(Clone)]
@@ -30,9 +712,78 @@ impl ops::Deref for Pipeline {
 
 pub struct PipelineInner {
     pipeline: gst::Pipeline,
+    // Video source, usually the camera
+    source: gst::Element,
     tee: gst::Element,
     sink: gst::Element,
-    recording_bin: RefCell<Option<gst::Bin>>,
+    // Constrains the resolution and framerate of the video source
+    capture_caps: gst::Element,
+    // Caps that were configured for the video source, if any
+    configured_capture_caps: Option<gst::Caps>,
+    // Drops all frames before the preview sink while the preview is frozen
+    preview_valve: gst::Element,
+    // Whether the preview is frozen only to save resources while recording, in which case
+    // snapshots still have to show the current frame
+    preview_minimized: Cell<bool>,
+    // Adjusts brightness, contrast, saturation and hue of the preview
+    balance: gst::Element,
+    // Flips or rotates the video before the tee, i.e. for the preview, recordings and snapshots
+    flip: gst::Element,
+    video_flip: Cell<VideoFlipMethod>,
+    // Crops the borders for zooming into the center, and the caps that scale the result back to
+    // the size before cropping
+    crop: gst::Element,
+    zoom_caps: gst::Element,
+    zoom: Cell<f64>,
+    // Burns the date and time into the video before the tee, if enabled
+    clock: gst::Element,
+    // Overlays the watermark image before the tee, None if the plugin for that is not installed
+    watermark: Option<gst::Element>,
+    // Image path, position and opacity of the watermark as last set, even if it failed
+    watermark_settings: RefCell<Option<(Option<PathBuf>, OverlayPosition, f64)>>,
+    // Sink to take snapshots from, either the preview sink or a separate full resolution sink
+    snapshot_sink: gst::Element,
+    recording: RefCell<Option<Recording>>,
+    // Recording that was stopped but did not finish writing the file yet
+    stopping_recording: RefCell<Option<Recording>>,
+    http_preview_bin: RefCell<Option<gst::Bin>>,
+    // Audio source whose level is measured and the bin doing that, independent of recording
+    audio_monitor: RefCell<Option<(AudioSource, gst::Bin)>>,
+    // Keys derived from the passphrase for encrypting recordings, if enabled
+    recording_key: RefCell<Option<encryption::Key>>,
+    // File to write the following recordings to instead of the configured directory, if chosen
+    recording_location: RefCell<Option<PathBuf>>,
+    // File to write the next snapshot to instead of the configured directory, if chosen
+    snapshot_location: RefCell<Option<PathBuf>>,
+    // Archive to write snapshots to instead of separate files, if any
+    snapshot_archive: RefCell<Option<Arc<Mutex<SnapshotArchive>>>>,
+    // Numbering of the snapshots of the running burst, if any
+    snapshot_burst: RefCell<Option<Rc<RefCell<SnapshotBurst>>>>,
+    fps_stats: RefCell<FpsStats>,
+    // Copy of the application's current settings, which is updated whenever they change
+    settings: RefCell<Settings>,
+    preview_disabled: bool,
+    // Whether this only shows a placeholder instead of the camera
+    placeholder: bool,
+    // Whether this was created from the custom pipeline description instead of the camera
+    custom: bool,
+    // Camera that was configured when creating the pipeline, even if it was not found
+    video_device: Option<String>,
+    // Custom pipeline description that was configured when creating the pipeline, even if it was
+    // invalid
+    custom_pipeline: Option<String>,
+    // Whether deinterlacing was configured when creating the pipeline
+    deinterlace: bool,
+    // URL and latency of the network camera that was configured when creating the pipeline, even
+    // if it was not used
+    rtsp_source: Option<(String, u32)>,
+    // Live pipelines, e.g. with a camera, must not be paused for buffering
+    is_live: Cell<bool>,
+    // Whether we paused the pipeline for buffering
+    buffering: Cell<bool>,
+    event_handler: RefCell<Option<Box<dyn Fn(&PipelineEvent)>>>,
+    // Whether the elements needed for recording could be created, by factory name
+    available_elements: RefCell<HashMap<String, bool>>,
 }
 
 // Weak reference to our pipeline struct
@@ -48,27 +799,362 @@ impl PipelineWeak {
 }
 
 impl Pipeline {
-    pub fn new() -> Result<Self, Box<dyn error::Error>> {
-        // Create a new GStreamer pipeline that captures from the default video source, which is
-        // usually a camera, converts the output to RGB if needed and then passes it to a GTK video
-        // sink
-        let pipeline = gst::parse_launch(
-            "autovideosrc ! tee name=tee ! queue ! videoconvert ! gtksink name=sink",
+    // Create the preview pipeline
+    //
+    // This only brings up the camera and the preview and never touches any encoders or muxers, so
+    // that the application always starts and shows video even if the recording plugins are missing
+    // or broken. Everything needed for recording is only checked once a recording is started
+    //
+    // A custom pipeline from the settings is used instead of the camera if it is valid
+    pub fn new(settings: &Settings) -> Result<Self, Box<dyn error::Error>> {
+        let custom_error = match settings.custom_pipeline {
+            Some(ref description) => match Self::new_custom(description, settings) {
+                Ok(pipeline) => return Ok(pipeline),
+                Err(err) => Some(err),
+            },
+            None => None,
+        };
+
+        let pipeline =
+            Self::new_with_source(Self::create_camera_source(settings)?, false, settings)?;
+
+        // The bus watch shows this once it's installed
+        if let Some(err) = custom_error {
+            let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+            let _ = bus.post(&Self::create_application_warning_message(&format!(
+                "Invalid custom pipeline, using the camera instead: {}",
+                err
+            )));
+        }
+
+        Ok(pipeline)
+    }
+
+    // Create a pipeline from the user's own description, e.g. for an IP camera with rtspsrc
+    //
+    // The description has to contain a "tee name=tee" for snapshots and recordings and a
+    // "gtksink name=sink" for the preview. It can contain elements with the names of the default
+    // pipeline, e.g. "videobalance name=balance", and the settings for any of them that it does
+    // not contain have no effect
+    fn new_custom(description: &str, settings: &Settings) -> Result<Self, Box<dyn error::Error>> {
+        let pipeline = gst::parse_launch(description)?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| "It has to consist of more than a single element")?;
+
+        if pipeline.get_by_name("tee").is_none() {
+            return Err("It has no tee named tee".into());
+        }
+        let has_sink = pipeline
+            .get_by_name("sink")
+            .and_then(|sink| sink.get_factory())
+            .map(|factory| factory.get_name().as_str() == "gtksink")
+            .unwrap_or(false);
+        if !has_sink {
+            return Err("It has no gtksink named sink".into());
+        }
+
+        let source = pipeline
+            .iterate_sources()
+            .find(|element| {
+                element
+                    .get_factory()
+                    .and_then(|factory| factory.get_metadata("klass"))
+                    .map(|klass| klass.contains("Source"))
+                    .unwrap_or(false)
+            })
+            .ok_or("It has no source element")?;
+
+        Self::new_with_pipeline(pipeline, source, false, true, None, settings)
+    }
+
+    // Create a pipeline that shows a black image instead of the camera
+    //
+    // This is used if the camera pipeline can't be created, e.g. because there is no camera or a
+    // plugin is missing, so that the application can still start
+    pub fn new_placeholder(settings: &Settings) -> Result<Self, Box<dyn error::Error>> {
+        let source =
+            gst::parse_bin_from_description("videotestsrc is-live=true pattern=black", true)?;
+        Self::new_with_source(source.upcast(), true, settings)
+    }
+
+    // Create a pipeline that captures the given shared screen or window instead of the camera
+    #[cfg(target_os = "linux")]
+    pub fn new_screencast(
+        screencast: &Screencast,
+        settings: &Settings,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let source = gst::parse_bin_from_description(&screencast.get_source_description(), true)?;
+        Self::new_with_source(source.upcast(), false, settings)
+    }
+
+    // Ids and names of all cameras that can be selected in the settings
+    pub fn list_video_devices() -> Vec<(String, String)> {
+        let monitor = gst::DeviceMonitor::new();
+        monitor.add_filter(Some("Video/Source"), None);
+        if monitor.start().is_err() {
+            return Vec::new();
+        }
+
+        let devices = monitor
+            .get_devices()
+            .into_iter()
+            .map(|device| {
+                (
+                    Self::get_video_device_id(&device),
+                    device.get_display_name().to_string(),
+                )
+            })
+            .collect();
+        monitor.stop();
+
+        devices
+    }
+
+    // Id of the camera that is stored in the settings
+    //
+    // This is the device path if there is one, as two cameras of the same model have the same
+    // name. Otherwise the name is the best we have
+    fn get_video_device_id(device: &gst::Device) -> String {
+        device
+            .get_properties()
+            .and_then(|properties| {
+                properties
+                    .get::<&str>("device.path")
+                    .or_else(|| properties.get::<&str>("api.v4l2.path"))
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| device.get_display_name().to_string())
+    }
+
+    // Create the source element for the configured camera
+    //
+    // If no camera is configured or it is not connected anymore, the default camera is used
+    fn create_camera_source(settings: &Settings) -> Result<gst::Element, Box<dyn error::Error>> {
+        if let Some((url, latency_ms)) = settings.get_rtsp_source() {
+            return Self::create_rtsp_source(url, latency_ms);
+        }
+
+        // Older versions stored the name of the camera, so that is still accepted
+        if let Some(ref id) = settings.video_device {
+            let monitor = gst::DeviceMonitor::new();
+            monitor.add_filter(Some("Video/Source"), None);
+            let source = if monitor.start().is_ok() {
+                let source = monitor
+                    .get_devices()
+                    .into_iter()
+                    .find(|device| {
+                        Self::get_video_device_id(device) == *id
+                            || device.get_display_name().as_str() == id.as_str()
+                    })
+                    .and_then(|device| device.create_element(None));
+                monitor.stop();
+                source
+            } else {
+                None
+            };
+
+            match source {
+                Some(source) => return Ok(source),
+                None => println!("Camera {} not found, using the default camera", id),
+            }
+        }
+
+        // autovideosrc would only fail once the pipeline is started if there is no camera at all
+        let monitor = gst::DeviceMonitor::new();
+        monitor.add_filter(Some("Video/Source"), None);
+        if monitor.start().is_ok() {
+            let no_camera = monitor.get_devices().is_empty();
+            monitor.stop();
+
+            if no_camera {
+                return Err(NoCameraError.into());
+            }
+        }
+
+        Ok(gst::ElementFactory::make("autovideosrc", None)?)
+    }
+
+    // Create a source bin that receives and decodes the video of a network camera
+    //
+    // decodebin only knows the decoded streams once the camera answered, so the source pad of the
+    // bin is connected to the decoded video once decodebin adds it. Audio from the camera is not
+    // received at all
+    fn create_rtsp_source(
+        url: &str,
+        latency_ms: u32,
+    ) -> Result<gst::Element, Box<dyn error::Error>> {
+        if url.is_empty() {
+            return Err("No URL configured for the network camera".into());
+        }
+
+        let bin = gst::parse_bin_from_description(
+            &format!(
+                "rtspsrc location=\"{}\" latency={} ! application/x-rtp,media=video ! decodebin name=decodebin",
+                url.replace('"', "\\\""),
+                latency_ms
+            ),
+            false,
         )?;
 
+        let ghost_pad = gst::GhostPad::new_no_target(Some("src"), gst::PadDirection::Src)?;
+        bin.add_pad(&ghost_pad)?;
+
+        // Only a weak reference, the bin owns decodebin and the ghost pad
+        let ghost_pad_weak = ghost_pad.downgrade();
+        bin.get_by_name("decodebin")
+            .expect("No decodebin found")
+            .connect_pad_added(move |_decodebin, pad| {
+                let ghost_pad = match ghost_pad_weak.upgrade() {
+                    Some(ghost_pad) => ghost_pad,
+                    None => return,
+                };
+
+                let is_video = pad
+                    .get_current_caps()
+                    .and_then(|caps| {
+                        caps.get_structure(0)
+                            .map(|s| s.get_name().starts_with("video/"))
+                    })
+                    .unwrap_or(false);
+                if !is_video || ghost_pad.get_target().is_some() {
+                    return;
+                }
+
+                if let Err(err) = ghost_pad.set_target(Some(pad)) {
+                    println!("Failed to link network camera video: {}", err);
+                }
+            });
+
+        Ok(bin.upcast())
+    }
+
+    fn new_with_source(
+        source: gst::Element,
+        placeholder: bool,
+        settings: &Settings,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        // Without preview, e.g. for recording-only devices, we don't render anything but still
+        // keep the last frame around in a fakesink for snapshots
+        let sink_description = if settings.disable_preview {
+            "fakesink name=sink sync=false enable-last-sample=true"
+        } else {
+            "gtksink name=sink"
+        };
+
+        // Optionally keep the camera's native frames for snapshots in a separate branch. This
+        // costs an additional branch but the frames are not converted for display. The leaky
+        // queue makes sure that this branch never blocks the others
+        let snapshot_description = match settings.snapshot_source {
+            SnapshotSource::Preview => "",
+            SnapshotSource::FullResolution => {
+                " tee. ! queue leaky=downstream max-size-buffers=1 ! fakesink name=snapshot_sink sync=false async=false enable-last-sample=true"
+            }
+        };
+
+        // Create a new GStreamer pipeline that constrains the output of the video source to the
+        // configured resolution and framerate, flips or rotates it, adjusts the colors of the
+        // preview, converts it to RGB if needed and then passes it to a GTK video sink
+        //
+        // The watermark is optional and should not prevent the camera from working, so it is
+        // left out if its plugin is not installed
+        let watermark_description = if gst::ElementFactory::find("gdkpixbufoverlay").is_some() {
+            " ! gdkpixbufoverlay name=watermark"
+        } else {
+            ""
+        };
+
+        // Deinterlacing right after the source gives all following elements whole frames. With
+        // mode=auto it only deinterlaces if the caps say that the video is interlaced
+        let deinterlace_description = if settings.deinterlace {
+            " ! deinterlace mode=auto"
+        } else {
+            ""
+        };
+
+        // The flip, zoom, timestamp and watermark happen before the tee so that recordings and
+        // snapshots look exactly like the preview. videobalance and videoflip do not support all
+        // formats, hence the conversions around them. All of them do nothing with the default
+        // values
+        let pipeline = gst::parse_launch(&format!(
+            "capsfilter name=capture_caps{} ! videoconvert ! videoflip name=flip ! videocrop name=crop ! videoscale ! capsfilter name=zoom_caps ! videoconvert ! clockoverlay name=clock silent=true{} ! tee name=tee ! queue ! valve name=preview_valve ! videoconvert ! videobalance name=balance ! videoconvert ! {}{}",
+            deinterlace_description, watermark_description, sink_description, snapshot_description
+        ))?;
+
         // Upcast to a gst::Pipeline as the above function could've also returned an arbitrary
         // gst::Element if a different string was passed
         let pipeline = pipeline
             .downcast::<gst::Pipeline>()
             .expect("Couldn't downcast pipeline");
 
-        // Request that the pipeline forwards us all messages, even those that it would otherwise
-        // aggregate first
-        pipeline.set_property_message_forward(true);
+        // Then add the video source, which is usually the camera, in front of everything
+        let capture_caps = pipeline
+            .get_by_name("capture_caps")
+            .expect("No capture caps found");
+        pipeline.add(&source)?;
+        source.link(&capture_caps)?;
 
-        // Retrieve sink and tee elements from the pipeline for later use
-        let tee = pipeline.get_by_name("tee").expect("No tee found");
-        let sink = pipeline.get_by_name("sink").expect("No sink found");
+        // The placeholder can produce anything, so there's no need to constrain it
+        let configured_capture_caps = if placeholder {
+            None
+        } else {
+            Self::create_capture_caps(&settings)
+        };
+        if let Some(ref caps) = configured_capture_caps {
+            capture_caps
+                .set_property("caps", caps)
+                .expect("capsfilter had no caps property");
+        }
+
+        Self::new_with_pipeline(
+            pipeline,
+            source,
+            placeholder,
+            false,
+            configured_capture_caps,
+            settings,
+        )
+    }
+
+    // Get the element with the given name, or a new element from the given factory that is not
+    // part of the pipeline if a custom pipeline does not contain it
+    fn get_by_name_or_unlinked(
+        pipeline: &gst::Pipeline,
+        name: &str,
+        factory: &str,
+    ) -> Result<gst::Element, Box<dyn error::Error>> {
+        match pipeline.get_by_name(name) {
+            Some(element) => Ok(element),
+            None => Ok(gst::ElementFactory::make(factory, Some(name))?),
+        }
+    }
+
+    // Finish setting up the parsed pipeline with the source already linked in
+    fn new_with_pipeline(
+        pipeline: gst::Pipeline,
+        source: gst::Element,
+        placeholder: bool,
+        custom: bool,
+        configured_capture_caps: Option<gst::Caps>,
+        settings: &Settings,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        // Request that the pipeline forwards us all messages, even those that it would otherwise
+        // aggregate first
+        pipeline.set_property_message_forward(true);
+
+        // Retrieve sink and tee elements from the pipeline for later use
+        let tee = pipeline.get_by_name("tee").expect("No tee found");
+        let sink = pipeline.get_by_name("sink").expect("No sink found");
+        let capture_caps = Self::get_by_name_or_unlinked(&pipeline, "capture_caps", "capsfilter")?;
+        let preview_valve = Self::get_by_name_or_unlinked(&pipeline, "preview_valve", "valve")?;
+        let balance = Self::get_by_name_or_unlinked(&pipeline, "balance", "videobalance")?;
+        let flip = Self::get_by_name_or_unlinked(&pipeline, "flip", "videoflip")?;
+        let crop = Self::get_by_name_or_unlinked(&pipeline, "crop", "videocrop")?;
+        let zoom_caps = Self::get_by_name_or_unlinked(&pipeline, "zoom_caps", "capsfilter")?;
+        let clock = Self::get_by_name_or_unlinked(&pipeline, "clock", "clockoverlay")?;
+        let watermark = pipeline.get_by_name("watermark");
+        let snapshot_sink = pipeline
+            .get_by_name("snapshot_sink")
+            .unwrap_or_else(|| sink.clone());
 
         // XXX: Workaround for a bug on macOS
         //
@@ -90,216 +1176,2354 @@ impl Pipeline {
             });
         }
 
-        let pipeline = Pipeline(Rc::new(PipelineInner {
-            pipeline,
-            sink,
-            tee,
-            recording_bin: RefCell::new(None),
-        }));
+        let pipeline = Pipeline(Rc::new(PipelineInner {
+            pipeline,
+            source,
+            sink,
+            preview_valve,
+            preview_minimized: Cell::new(false),
+            balance,
+            flip,
+            video_flip: Cell::new(VideoFlipMethod::default()),
+            crop,
+            zoom_caps,
+            zoom: Cell::new(1.0),
+            clock,
+            watermark,
+            watermark_settings: RefCell::new(None),
+            snapshot_sink,
+            tee,
+            capture_caps,
+            configured_capture_caps,
+            recording: RefCell::new(None),
+            stopping_recording: RefCell::new(None),
+            http_preview_bin: RefCell::new(None),
+            audio_monitor: RefCell::new(None),
+            recording_key: RefCell::new(None),
+            recording_location: RefCell::new(None),
+            snapshot_location: RefCell::new(None),
+            snapshot_archive: RefCell::new(None),
+            snapshot_burst: RefCell::new(None),
+            fps_stats: RefCell::new(FpsStats::default()),
+            settings: RefCell::new(settings.clone()),
+            preview_disabled: settings.disable_preview,
+            placeholder,
+            custom,
+            video_device: settings.video_device.clone(),
+            custom_pipeline: settings.custom_pipeline.clone(),
+            deinterlace: settings.deinterlace,
+            rtsp_source: settings
+                .get_rtsp_source()
+                .map(|(url, latency_ms)| (String::from(url), latency_ms)),
+            is_live: Cell::new(false),
+            buffering: Cell::new(false),
+            event_handler: RefCell::new(None),
+            available_elements: RefCell::new(HashMap::new()),
+        }));
+
+        pipeline.set_brightness(settings.brightness);
+        pipeline.set_contrast(settings.contrast);
+        pipeline.set_saturation(settings.saturation);
+        pipeline.set_hue(settings.hue);
+        pipeline.set_video_flip(settings.video_flip);
+        pipeline.set_zoom(settings.zoom);
+        pipeline.set_timestamp(
+            settings.burn_timestamp,
+            settings.timestamp_position,
+            settings.timestamp_font_size,
+        );
+
+        // A broken watermark is only a warning, the bus watch below shows it once it's installed
+        if let Err(err) = pipeline.set_watermark(
+            settings.watermark_path.as_ref().map(PathBuf::as_path),
+            settings.watermark_position,
+            settings.watermark_opacity,
+        ) {
+            let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+            let _ = bus.post(&Self::create_application_warning_message(&err));
+        }
+
+        // Notice when the camera starts a new stream. Stream start messages also arrive for new
+        // branches, e.g. when recording is started, so this looks at the events of the camera
+        // stream instead. The main thread is only told once the caps of the new stream are known,
+        // as the caps event follows the stream start event
+        {
+            // Stream id of the current camera stream, and whether its caps are still pending
+            let stream_state = Mutex::new((None::<String>, false));
+            let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+            let sinkpad = pipeline
+                .tee
+                .get_static_pad("sink")
+                .expect("tee has no sinkpad");
+            sinkpad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+                let event = match info.data {
+                    Some(gst::PadProbeData::Event(ref event)) => event,
+                    _ => return gst::PadProbeReturn::Ok,
+                };
+
+                let mut stream_state = stream_state.lock().unwrap();
+                match event.view() {
+                    gst::EventView::StreamStart(e) => {
+                        let stream_id = e.get_stream_id();
+                        if stream_state.0.as_ref().map(String::as_str) != Some(stream_id) {
+                            *stream_state = (Some(stream_id.to_string()), true);
+                        }
+                    }
+                    gst::EventView::Caps(e) if stream_state.1 => {
+                        stream_state.1 = false;
+
+                        if let Some(info) = gst_video::VideoInfo::from_caps(&e.get_caps_owned()) {
+                            let _ = bus.post(
+                                &gst::Message::new_application(
+                                    gst::Structure::builder("stream-started")
+                                        .field("width", &info.width())
+                                        .field("height", &info.height())
+                                        .build(),
+                                )
+                                .build(),
+                            );
+                        }
+                    }
+                    _ => (),
+                }
+
+                gst::PadProbeReturn::Ok
+            });
+        }
+
+        // Count all frames that are captured and update the frame rate once per second
+        {
+            let frames = pipeline.fps_stats.borrow().frames.clone();
+            let sinkpad = pipeline
+                .tee
+                .get_static_pad("sink")
+                .expect("tee has no sinkpad");
+            sinkpad.add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                frames.fetch_add(1, Ordering::Relaxed);
+                gst::PadProbeReturn::Ok
+            });
+
+            let pipeline_weak = pipeline.downgrade();
+            gtk::timeout_add_seconds(1, move || {
+                let pipeline = upgrade_weak!(pipeline_weak, glib::Continue(false));
+                pipeline.fps_stats.borrow_mut().update();
+                if let Some(ref recording) = *pipeline.recording.borrow() {
+                    recording.update_current_bitrate();
+                }
+                glib::Continue(true)
+            });
+
+            // And let the main thread know once frames are actually flowing
+            let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+            sinkpad.add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                let _ = bus.post(
+                    &gst::Message::new_application(gst::Structure::new_empty("first-frame"))
+                        .build(),
+                );
+                gst::PadProbeReturn::Remove
+            });
+
+            // And whenever the size of the video changes, e.g. when rotating it
+            let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+            sinkpad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+                let caps = match info.data {
+                    Some(gst::PadProbeData::Event(ref event)) => match event.view() {
+                        gst::EventView::Caps(e) => e.get_caps_owned(),
+                        _ => return gst::PadProbeReturn::Ok,
+                    },
+                    _ => return gst::PadProbeReturn::Ok,
+                };
+
+                if let Some(info) = gst_video::VideoInfo::from_caps(&caps) {
+                    let par = info.par();
+                    let width = u64::from(info.width()) * *par.numer() as u64 / *par.denom() as u64;
+                    let _ = bus.post(
+                        &gst::Message::new_application(
+                            gst::Structure::builder("video-size")
+                                .field("width", &(width as u32))
+                                .field("height", &info.height())
+                                .build(),
+                        )
+                        .build(),
+                    );
+                }
+
+                gst::PadProbeReturn::Ok
+            });
+        }
+
+        // Install a message handler on the pipeline's bus to catch errors
+        let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+
+        // GStreamer is thread-safe and it is possible to attach bus watches from any thread, which
+        // are then nonetheless called from the main thread. As such we have to make use of
+        // fragile::Fragile() here to be able to pass our non-Send application struct into a
+        // closure that requires Send.
+        //
+        // As we are on the main thread and the closure will be called on the main thread, this
+        // will not cause a panic and is perfectly safe.
+        let pipeline_weak = fragile::Fragile::new(pipeline.downgrade());
+        bus.add_watch(move |_bus, msg| {
+            let pipeline_weak = pipeline_weak.get();
+            let pipeline = upgrade_weak!(pipeline_weak, glib::Continue(false));
+
+            pipeline.on_pipeline_message(msg);
+
+            glib::Continue(true)
+        });
+
+        Ok(pipeline)
+    }
+
+    // Downgrade to a weak reference
+    pub fn downgrade(&self) -> PipelineWeak {
+        PipelineWeak(Rc::downgrade(&self.0))
+    }
+
+    pub fn get_widget(&self) -> gtk::Widget {
+        // Without preview we only show a placeholder in the window
+        if self.preview_disabled {
+            let placeholder = gtk::Label::new(Some("Preview disabled"));
+            gtk::WidgetExt::set_name(&placeholder, "preview-placeholder");
+            return placeholder.upcast();
+        }
+
+        // Get the GTK video sink and retrieve the video display widget from it
+        let widget_value = self
+            .sink
+            .get_property("widget")
+            .expect("Sink had no widget property");
+
+        widget_value
+            .get::<gtk::Widget>()
+            .expect("Sink's widget propery was of the wrong type")
+    }
+
+    pub fn start(&self) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        // This has no effect if called multiple times
+        let res = self.pipeline.set_state(gst::State::Playing);
+
+        // Live sources can't preroll, which tells us that this is a live pipeline
+        if res == Ok(gst::StateChangeSuccess::NoPreroll) {
+            self.is_live.set(true);
+        }
+
+        res
+    }
+
+    pub fn stop(&self) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
+        // This has no effect if called multiple times
+        self.pipeline.set_state(gst::State::Null)
+    }
+
+    // Check if the pipeline was created without preview
+    pub fn is_preview_disabled(&self) -> bool {
+        self.preview_disabled
+    }
+
+    // Caps for constraining the video source to the resolution and framerate from the settings, or
+    // None if neither is configured
+    pub fn create_capture_caps(settings: &Settings) -> Option<gst::Caps> {
+        if (settings.capture_width == 0 || settings.capture_height == 0)
+            && settings.capture_framerate == 0
+        {
+            return None;
+        }
+
+        let mut caps = gst::Caps::new_simple("video/x-raw", &[]);
+        {
+            let caps = caps.get_mut().unwrap();
+            let s = caps.get_mut_structure(0).unwrap();
+            if settings.capture_width != 0 && settings.capture_height != 0 {
+                s.set("width", &(settings.capture_width as i32));
+                s.set("height", &(settings.capture_height as i32));
+            }
+            if settings.capture_framerate != 0 {
+                s.set(
+                    "framerate",
+                    &gst::Fraction::new(settings.capture_framerate as i32, 1),
+                );
+            }
+        }
+
+        Some(caps)
+    }
+
+    // Caps that were configured for the video source when creating the pipeline
+    pub fn get_configured_capture_caps(&self) -> Option<&gst::Caps> {
+        self.configured_capture_caps.as_ref()
+    }
+
+    // Camera that was configured when creating the pipeline, None for the default camera
+    pub fn get_video_device(&self) -> Option<&str> {
+        self.video_device.as_ref().map(String::as_str)
+    }
+
+    // Check if this only shows a placeholder because the camera could not be used
+    pub fn is_placeholder(&self) -> bool {
+        self.placeholder
+    }
+
+    // Check if this was created from the custom pipeline description
+    pub fn is_custom(&self) -> bool {
+        self.custom
+    }
+
+    // Custom pipeline description that was configured when creating the pipeline, if any
+    pub fn get_custom_pipeline(&self) -> Option<&str> {
+        self.custom_pipeline.as_ref().map(String::as_str)
+    }
+
+    // Check if deinterlacing was configured when creating the pipeline
+    pub fn is_deinterlacing(&self) -> bool {
+        self.deinterlace
+    }
+
+    // Network camera that was configured when creating the pipeline, if any
+    pub fn get_rtsp_source(&self) -> Option<(&str, u32)> {
+        self.rtsp_source
+            .as_ref()
+            .map(|&(ref url, latency_ms)| (url.as_str(), latency_ms))
+    }
+
+    // Set the function that is called for every pipeline event
+    //
+    // This is always called from the main application thread
+    pub fn connect_event<F: Fn(&PipelineEvent) + 'static>(&self, func: F) {
+        *self.event_handler.borrow_mut() = Some(Box::new(func));
+    }
+
+    fn emit_event(&self, event: PipelineEvent) {
+        if let Some(ref func) = *self.event_handler.borrow() {
+            func(&event);
+        }
+    }
+
+    // Smoothed frame rate of the camera stream, which is stable enough for displaying
+    pub fn current_fps(&self) -> Option<f64> {
+        self.fps_stats.borrow().smoothed
+    }
+
+    // Number of frames captured during the last second
+    pub fn current_raw_fps(&self) -> u32 {
+        self.fps_stats.borrow().raw
+    }
+
+    // Get the v4l2src element used for capturing, if any
+    //
+    // autovideosrc creates the actual source element as a child of itself once it is started, so
+    // we have to look at all elements recursively
+    fn get_v4l2_source(&self) -> Option<gst::Element> {
+        self.pipeline.iterate_recurse().find(|element| {
+            element
+                .get_factory()
+                .map(|factory| factory.get_name().as_str() == "v4l2src")
+                .unwrap_or(false)
+        })
+    }
+
+    // Check if the camera allows controlling the exposure
+    //
+    // This is only known after the pipeline was started
+    pub fn supports_exposure_lock(&self) -> bool {
+        self.get_v4l2_source().is_some()
+    }
+
+    // Lock exposure and gain of the camera to their current values or let the camera control
+    // them automatically again
+    pub fn set_exposure_locked(&self, locked: bool) -> Result<(), Box<dyn error::Error>> {
+        let src = self
+            .get_v4l2_source()
+            .ok_or("Camera does not support controlling the exposure")?;
+
+        // Switching to manual exposure keeps the camera's current exposure values. 1 is the manual
+        // mode and 3 the aperture priority mode, which is the default for most webcams
+        let controls = gst::Structure::builder("controls")
+            .field("exposure_auto", &(if locked { 1i32 } else { 3i32 }))
+            .field("gain_automatic", &(!locked as i32))
+            .build();
+
+        // v4l2src directly applies the controls if the device is already opened
+        src.set_property("extra-controls", &controls)
+            .map_err(|err| format!("Failed to set camera controls: {}", err))?;
+
+        Ok(())
+    }
+
+    // Update the settings that are used for the next snapshots and recordings
+    pub fn set_settings(&self, settings: &Settings) {
+        *self.settings.borrow_mut() = settings.clone();
+    }
+
+    // Current settings, as last given by the application
+    fn settings(&self) -> Settings {
+        self.settings.borrow().clone()
+    }
+
+    // Take a snapshot of the current image and write it to the configured location
+    pub fn take_snapshot(&self) -> Result<(), Box<dyn error::Error>> {
+        let settings = self.settings();
+
+        if settings.snapshot_wait_for_frame || self.is_snapshot_sink_stale() {
+            self.take_snapshot_from_next_frame();
+            return Ok(());
+        }
+
+        match self.get_last_sample() {
+            // We have no sample to store yet
+            None => Ok(()),
+            Some(sample) => self.save_snapshot(sample),
+        }
+    }
+
+    // Get the current image as pixbuf, e.g. for copying it to the clipboard
+    //
+    // Returns None if there is no image yet
+    pub fn get_snapshot_pixbuf(&self) -> Result<Option<gdk_pixbuf::Pixbuf>, Box<dyn error::Error>> {
+        let sample = match self.get_last_sample() {
+            // We have no sample to convert yet
+            None => return Ok(None),
+            Some(sample) => sample,
+        };
+
+        // Pixbufs can only hold packed RGB, so let GStreamer convert from whatever format we got
+        let caps = gst::Caps::new_simple("video/x-raw", &[("format", &"RGB")]);
+        let sample = gst_video::convert_sample(&sample, &caps, gst::SECOND)?;
+        let info = sample
+            .get_caps()
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps))
+            .ok_or("Converted frame has no valid caps")?;
+        let buffer = sample.get_buffer().ok_or("Converted frame has no buffer")?;
+        let map = buffer
+            .map_readable()
+            .ok_or("Failed to map converted frame")?;
+
+        Ok(Some(gdk_pixbuf::Pixbuf::new_from_mut_slice(
+            map.as_slice().to_vec(),
+            gdk_pixbuf::Colorspace::Rgb,
+            false,
+            8,
+            info.width() as i32,
+            info.height() as i32,
+            info.stride()[0],
+        )))
+    }
+
+    // Take a snapshot of exactly the frame that is shown in the preview, e.g. while it is frozen
+    pub fn take_snapshot_of_preview(&self) -> Result<(), Box<dyn error::Error>> {
+        let sample = self
+            .sink
+            .get_property("last-sample")
+            .expect("Sink had no last-sample property")
+            .get::<gst::Sample>();
+
+        match sample {
+            // We have no sample to store yet
+            None => Ok(()),
+            Some(sample) => self.save_snapshot(sample),
+        }
+    }
+
+    // Brightness of the preview, from -1 to 1 with 0 being unchanged
+    pub fn set_brightness(&self, brightness: f64) {
+        self.balance
+            .set_property("brightness", &brightness.max(-1.0).min(1.0))
+            .expect("videobalance had no brightness property");
+    }
+
+    // Contrast of the preview, from 0 to 2 with 1 being unchanged
+    pub fn set_contrast(&self, contrast: f64) {
+        self.balance
+            .set_property("contrast", &contrast.max(0.0).min(2.0))
+            .expect("videobalance had no contrast property");
+    }
+
+    // Saturation of the preview, from 0 to 2 with 1 being unchanged
+    pub fn set_saturation(&self, saturation: f64) {
+        self.balance
+            .set_property("saturation", &saturation.max(0.0).min(2.0))
+            .expect("videobalance had no saturation property");
+    }
+
+    // Hue of the preview, from -1 to 1 with 0 being unchanged
+    pub fn set_hue(&self, hue: f64) {
+        self.balance
+            .set_property("hue", &hue.max(-1.0).min(1.0))
+            .expect("videobalance had no hue property");
+    }
+
+    // Flip or rotate the video
+    //
+    // Rotating by 90 or 270 degrees swaps width and height, which a running recording can't
+    // handle, so this should only be changed while not recording
+    pub fn set_video_flip(&self, method: VideoFlipMethod) {
+        if self.video_flip.replace(method) == method {
+            return;
+        }
+
+        // The zoom keeps the previous size, so it is reset until the new size is known
+        self.reset_zoom();
+        self.flip.set_property_from_str("method", method.nick());
+    }
+
+    // Zoom into the center of the video by the given factor, 1.0 for no zoom
+    //
+    // The cropped video is scaled back to the previous size, so this can also be changed while
+    // recording
+    pub fn set_zoom(&self, zoom: f64) {
+        self.zoom.set(zoom.max(1.0).min(MAX_ZOOM));
+        self.apply_zoom();
+    }
+
+    // Crop the borders for the current zoom factor, based on the size of the video before
+    // cropping
+    //
+    // Does nothing while that size is not known yet, this is called again once it is
+    fn apply_zoom(&self) {
+        let info = match self
+            .crop
+            .get_static_pad("sink")
+            .and_then(|pad| pad.get_current_caps())
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps))
+        {
+            Some(info) => info,
+            None => return,
+        };
+
+        let caps = gst::Caps::builder("video/x-raw")
+            .field("width", &(info.width() as i32))
+            .field("height", &(info.height() as i32))
+            .field("pixel-aspect-ratio", &info.par())
+            .build();
+
+        // This is called again whenever the size after zooming is known, so only change what is
+        // different to not renegotiate over and over again
+        let current_caps = self
+            .zoom_caps
+            .get_property("caps")
+            .expect("capsfilter had no caps property")
+            .get::<gst::Caps>();
+        if current_caps.as_ref() != Some(&caps) {
+            self.zoom_caps
+                .set_property("caps", &caps)
+                .expect("capsfilter had no caps property");
+        }
+
+        // Subsampled formats can only be cropped by even numbers of pixels
+        let zoom = self.zoom.get();
+        let border = |size: u32| (f64::from(size) * (1.0 - 1.0 / zoom) / 2.0) as i32 & !1;
+        for &(property, size) in &[
+            ("left", info.width()),
+            ("right", info.width()),
+            ("top", info.height()),
+            ("bottom", info.height()),
+        ] {
+            let current = self
+                .crop
+                .get_property(property)
+                .expect("videocrop had no border property")
+                .get::<i32>();
+            if current != Some(border(size)) {
+                self.crop
+                    .set_property(property, &border(size))
+                    .expect("videocrop had no border property");
+            }
+        }
+    }
+
+    // Burn the date and time into the video at the given corner, or stop doing so
+    //
+    // This happens before the tee, so the timestamp is part of recordings and snapshots too
+    pub fn set_timestamp(&self, enabled: bool, position: OverlayPosition, font_size: u32) {
+        self.clock
+            .set_property("silent", &!enabled)
+            .expect("clockoverlay had no silent property");
+        self.clock
+            .set_property("time-format", &"%Y-%m-%d %H:%M:%S")
+            .expect("clockoverlay had no time-format property");
+        self.clock
+            .set_property("font-desc", &format!("Sans {}", font_size).as_str())
+            .expect("clockoverlay had no font-desc property");
+        self.clock
+            .set_property_from_str("halignment", position.halignment());
+        self.clock
+            .set_property_from_str("valignment", position.valignment());
+    }
+
+    // Overlay the image at the given corner with the given opacity, or remove the watermark
+    //
+    // If the image can't be loaded, there is no watermark and an error is returned. Nothing is
+    // done if nothing changed since the last call, so that the image is not loaded again and
+    // errors are only reported once
+    pub fn set_watermark(
+        &self,
+        path: Option<&Path>,
+        position: OverlayPosition,
+        opacity: f64,
+    ) -> Result<(), String> {
+        let watermark_settings = Some((path.map(Path::to_path_buf), position, opacity));
+        if *self.watermark_settings.borrow() == watermark_settings {
+            return Ok(());
+        }
+        *self.watermark_settings.borrow_mut() = watermark_settings;
+
+        let watermark = match self.watermark {
+            Some(ref watermark) => watermark,
+            None if path.is_none() => return Ok(()),
+            None => {
+                return Err(String::from(
+                    "Failed to show watermark: gdkpixbufoverlay is missing, it is part of gst-plugins-good",
+                ))
+            }
+        };
+
+        // The overlay can't be removed from the running pipeline, so it is made invisible instead
+        let pixbuf = match path {
+            None => None,
+            Some(path) => match gdk_pixbuf::Pixbuf::new_from_file(path) {
+                Ok(pixbuf) => Some(pixbuf),
+                Err(err) => {
+                    watermark
+                        .set_property("alpha", &0.0f64)
+                        .expect("gdkpixbufoverlay had no alpha property");
+                    return Err(format!(
+                        "Failed to load watermark {}: {}",
+                        path.display(),
+                        err
+                    ));
+                }
+            },
+        };
+
+        let alpha = match pixbuf {
+            Some(pixbuf) => {
+                watermark
+                    .set_property("pixbuf", &pixbuf)
+                    .expect("gdkpixbufoverlay had no pixbuf property");
+                opacity.max(0.0).min(1.0)
+            }
+            None => 0.0,
+        };
+        watermark
+            .set_property("alpha", &alpha)
+            .expect("gdkpixbufoverlay had no alpha property");
+
+        // Negative offsets are from the right and bottom edges
+        let (offset_x, offset_y) = match position {
+            OverlayPosition::TopLeft => (WATERMARK_MARGIN, WATERMARK_MARGIN),
+            OverlayPosition::TopRight => (-WATERMARK_MARGIN, WATERMARK_MARGIN),
+            OverlayPosition::BottomLeft => (WATERMARK_MARGIN, -WATERMARK_MARGIN),
+            OverlayPosition::BottomRight => (-WATERMARK_MARGIN, -WATERMARK_MARGIN),
+        };
+        watermark
+            .set_property("offset-x", &offset_x)
+            .expect("gdkpixbufoverlay had no offset-x property");
+        watermark
+            .set_property("offset-y", &offset_y)
+            .expect("gdkpixbufoverlay had no offset-y property");
+
+        Ok(())
+    }
+
+    // Let the video through unchanged and with any size
+    fn reset_zoom(&self) {
+        self.zoom_caps
+            .set_property("caps", &gst::Caps::new_any())
+            .expect("capsfilter had no caps property");
+        for property in &["left", "right", "top", "bottom"] {
+            self.crop
+                .set_property(property, &0i32)
+                .expect("videocrop had no border property");
+        }
+    }
+
+    // Freeze the preview at the currently shown frame, or continue showing the live frames
+    //
+    // Only the preview is affected, recording and everything else continues as before
+    pub fn freeze_preview(&self, frozen: bool) {
+        self.preview_valve
+            .set_property("drop", &frozen)
+            .expect("Valve had no drop property");
+    }
+
+    // Remember that the preview is frozen only to save resources while recording
+    //
+    // The preview itself has to be frozen with freeze_preview() separately
+    pub fn set_preview_minimized(&self, minimized: bool) {
+        self.preview_minimized.set(minimized);
+    }
+
+    // Check if the sink that snapshots are taken from only has an old frame because the preview
+    // is minimized
+    fn is_snapshot_sink_stale(&self) -> bool {
+        self.preview_minimized.get() && self.snapshot_sink == self.sink
+    }
+
+    // Show the next frame from the camera while the preview is frozen
+    //
+    // The camera is live, so there is no way to step backwards
+    pub fn step_frame(&self) {
+        // Close the valve again right after the first frame passed it
+        let valve = self.preview_valve.clone();
+        self.preview_valve
+            .get_static_pad("src")
+            .expect("Valve has no srcpad")
+            .add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                let _ = valve.set_property("drop", &true);
+                gst::PadProbeReturn::Remove
+            });
+
+        self.freeze_preview(false);
+    }
+
+    // Get the frame that was shown last, if any
+    fn get_last_sample(&self) -> Option<gst::Sample> {
+        self.snapshot_sink
+            .get_property("last-sample")
+            .expect("Sink had no last-sample property")
+            .get::<gst::Sample>()
+    }
+
+    // Take a snapshot from the next frame that arrives at the sink
+    //
+    // The frame currently shown might e.g. only be half updated with some sources. If no frame
+    // arrives in time, the last frame is taken after all
+    //
+    // While the preview is minimized no frames arrive at the preview sink, so the frame is taken
+    // from before the closed valve instead. This is without the color balance of the preview
+    fn take_snapshot_from_next_frame(&self) {
+        let taken = Arc::new(AtomicBool::new(false));
+
+        let pad = if self.is_snapshot_sink_stale() {
+            self.preview_valve
+                .get_static_pad("sink")
+                .expect("Valve has no sinkpad")
+        } else {
+            self.snapshot_sink
+                .get_static_pad("sink")
+                .expect("Sink has no sinkpad")
+        };
+
+        // Pass the frame to the main thread via the bus
+        let taken_clone = taken.clone();
+        let bus = self.pipeline.get_bus().expect("Pipeline has no bus");
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if taken_clone.swap(true, Ordering::SeqCst) {
+                return gst::PadProbeReturn::Remove;
+            }
+
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                let sample = gst::Sample::new(
+                    Some(buffer),
+                    pad.get_current_caps().as_ref(),
+                    None::<&gst::FormattedSegment<gst::ClockTime>>,
+                    None,
+                );
+                let _ = bus.post(
+                    &gst::Message::new_application(
+                        gst::Structure::builder("snapshot-frame")
+                            .field("sample", &sample)
+                            .build(),
+                    )
+                    .build(),
+                );
+            }
+
+            gst::PadProbeReturn::Remove
+        });
+
+        let pipeline_weak = self.downgrade();
+        gtk::timeout_add(SNAPSHOT_FRAME_TIMEOUT_MS, move || {
+            let pipeline = upgrade_weak!(pipeline_weak, glib::Continue(false));
+
+            if !taken.swap(true, Ordering::SeqCst) {
+                if let Some(sample) = pipeline.get_last_sample() {
+                    pipeline.on_snapshot_frame(sample);
+                }
+            }
+
+            glib::Continue(false)
+        });
+    }
+
+    // Called with the frame to use for the snapshot once it's available
+    fn on_snapshot_frame(&self, sample: gst::Sample) {
+        if let Err(err) = self.save_snapshot(sample) {
+            utils::show_error_dialog(false, format!("Failed to take snapshot: {}", err).as_str());
+        }
+    }
+
+    // Write the given frame as snapshot to the configured location
+    //
+    // If configured, an additional lossless PNG with the same name is written from the same frame
+    fn save_snapshot(&self, sample: gst::Sample) -> Result<(), Box<dyn error::Error>> {
+        let settings = self.settings();
+
+        // GL sinks keep their frames in GPU memory, which can't be converted directly
+        let is_gl_memory = sample
+            .get_caps()
+            .and_then(|caps| caps.get_features(0).map(|f| f.contains("memory:GLMemory")))
+            .unwrap_or(false);
+        let sample = if is_gl_memory {
+            Self::process_sample(&sample, "glcolorconvert ! gldownload")
+                .map_err(|err| format!("Failed to download frame from the GPU: {}", err))?
+        } else {
+            sample
+        };
+
+        // Only waiting for a clean frame also gives a deinterlaced frame
+        let is_interlaced = sample
+            .get_caps()
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps))
+            .map(|info| info.interlace_mode() != gst_video::VideoInterlaceMode::Progressive)
+            .unwrap_or(false);
+        let sample = if settings.snapshot_wait_for_frame && is_interlaced {
+            Self::process_sample(&sample, "deinterlace method=linear")
+                .map_err(|err| format!("Failed to deinterlace frame: {}", err))?
+        } else {
+            sample
+        };
+
+        // Create the filename and open the file writable. A location chosen by the user is
+        // never added to the archive
+        let location = self.snapshot_location.borrow_mut().take();
+        let archive = match location {
+            Some(_) => None,
+            None => self.snapshot_archive.borrow().clone(),
+        };
+        let chosen = location.is_some();
+        let burst = self.snapshot_burst.borrow().clone();
+        let filename = match (location, burst) {
+            (Some(location), _) => location,
+            (None, Some(burst)) => burst.borrow_mut().next_filename(&settings)?,
+            (None, None) => get_snapshot_filename(&settings)?,
+        };
+
+        // If configured, the user decides what happens with an existing file and the snapshot is
+        // only saved afterwards
+        if !chosen
+            && archive.is_none()
+            && settings.snapshot_file_collision == FileCollision::Ask
+            && filename.exists()
+        {
+            let pipeline_weak = self.downgrade();
+            let path = filename.clone();
+            utils::show_overwrite_dialog(&path, move |choice| {
+                let pipeline = upgrade_weak!(pipeline_weak);
+
+                let overwrite = match choice {
+                    OverwriteChoice::Overwrite => true,
+                    OverwriteChoice::KeepBoth => false,
+                    OverwriteChoice::Cancel => {
+                        pipeline.emit_event(PipelineEvent::SnapshotSaved(None));
+                        return;
+                    }
+                };
+
+                if let Err(err) = pipeline.write_snapshot_files(
+                    &settings,
+                    sample.clone(),
+                    filename.clone(),
+                    overwrite,
+                    None,
+                ) {
+                    utils::show_error_dialog(
+                        false,
+                        format!("Failed to take snapshot: {}", err).as_str(),
+                    );
+                }
+            });
+
+            return Ok(());
+        }
+
+        self.write_snapshot_files(&settings, sample, filename, chosen, archive)
+    }
+
+    // Write the snapshot in the configured format, and if configured also as PNG
+    fn write_snapshot_files(
+        &self,
+        settings: &Settings,
+        sample: gst::Sample,
+        filename: PathBuf,
+        overwrite: bool,
+        archive: Option<Arc<Mutex<SnapshotArchive>>>,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let filename = self
+            .write_snapshot(
+                settings,
+                sample.clone(),
+                settings.snapshot_format.clone(),
+                filename.clone(),
+                overwrite,
+                archive.clone(),
+            )?
+            .unwrap_or(filename);
+
+        // The PNG is also written if the main format is not available
+        if settings.snapshot_also_png && settings.snapshot_format != SnapshotFormat::PNG {
+            self.write_snapshot(
+                settings,
+                sample,
+                SnapshotFormat::PNG,
+                filename.with_extension(SnapshotFormat::PNG.extension()),
+                false,
+                archive,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Convert the frame to the given format and write it to the file or archive
+    //
+    // An existing file is only overwritten if allowed, otherwise a number is appended to the file
+    // name. Returns the file name that is actually used, or None if the format is not available
+    fn write_snapshot(
+        &self,
+        settings: &Settings,
+        sample: gst::Sample,
+        format: SnapshotFormat,
+        filename: PathBuf,
+        overwrite: bool,
+        archive: Option<Arc<Mutex<SnapshotArchive>>>,
+    ) -> Result<Option<PathBuf>, Box<dyn error::Error>> {
+        // Create the GStreamer caps for the output format
+        let caps = match format {
+            SnapshotFormat::JPEG => gst::Caps::new_simple("image/jpeg", &[]),
+            SnapshotFormat::PNG => gst::Caps::new_simple("image/png", &[]),
+            SnapshotFormat::WEBP => gst::Caps::new_simple("image/webp", &[]),
+        };
+
+        // Unlike JPEG and PNG, the WebP encoder is not part of the base plugins
+        if format == SnapshotFormat::WEBP {
+            if let Err(err) = self.check_elements_available(&["webpenc"]) {
+                let bus = self.pipeline.get_bus().expect("Pipeline has no bus");
+                let _ = bus.post(&Self::create_application_warning_message(&format!(
+                    "Failed to take WebP snapshot. {} (webpenc is part of gst-plugins-bad)",
+                    err
+                )));
+                return Ok(None);
+            }
+        }
+
+        // Without archive the file is created right away to fail early, and so that rapid
+        // snapshots with the same name never overwrite each other. The user already confirmed
+        // overwriting a chosen location
+        let (filename, file) = match archive {
+            Some(_) => (filename, None),
+            None if overwrite => {
+                let file = File::create(&filename)
+                    .map_err(|err| format!("Failed to create snapshot file: {}", err))?;
+                (filename, Some(file))
+            }
+            None => {
+                let (filename, file) = utils::create_unique_file(filename)
+                    .map_err(|err| format!("Failed to create snapshot file: {}", err))?;
+                (filename, Some(file))
+            }
+        };
+        let written_filename = filename.clone();
+
+        // Then convert it from whatever format we got to PNG or JPEG as requested and write it out
+        println!("Writing snapshot to {}", filename.display());
+        self.emit_event(PipelineEvent::SnapshotSaving);
+
+        let metadata = if settings.write_capture_metadata {
+            Some(self.create_capture_metadata(settings, &format!("{:?}", format)))
+        } else {
+            None
+        };
+
+        // Cameras that output e.g. MJPEG already give us a JPEG, which would only lose quality by
+        // decoding and encoding it again
+        let is_target_format = sample
+            .get_caps()
+            .and_then(|sample_caps| {
+                let s = sample_caps.get_structure(0)?;
+                Some(s.get_name() == caps.get_structure(0)?.get_name())
+            })
+            .unwrap_or(false);
+
+        // The conversion finishes on another thread, so let the main thread know about that via
+        // the bus
+        let bus = self.pipeline.get_bus().expect("Pipeline has no bus");
+        let write = move |res: Result<gst::Sample, String>| {
+            use std::io::Write;
+
+            // The snapshot's own file name is also needed if it was added to the archive, it
+            // might be numbered
+            let saved_msg = |saved_filename: Option<&Path>| {
+                let mut s = gst::Structure::new_empty("snapshot-saved");
+                if let Some(saved_filename) = saved_filename {
+                    s.set("filename", &saved_filename.to_string_lossy().into_owned());
+                    s.set(
+                        "snapshot-filename",
+                        &filename.to_string_lossy().into_owned(),
+                    );
+                }
+                gst::Message::new_application(s).build()
+            };
+
+            let sample = match res {
+                Err(err) => {
+                    let _ = bus.post(&Self::create_application_warning_message(
+                        format!("Failed to convert sample: {}", err).as_str(),
+                    ));
+                    let _ = bus.post(&saved_msg(None));
+                    return;
+                }
+                Ok(sample) => sample,
+            };
+
+            let buffer = sample.get_buffer().expect("Failed to get buffer");
+            let map = buffer
+                .map_readable()
+                .expect("Failed to map buffer readable");
+
+            // Add the snapshot to the archive. If that fails, it is written as separate file
+            // instead so that it's not lost
+            if let Some(ref archive) = archive {
+                let mut archive = archive.lock().unwrap();
+                let name = archive.get_unique_name(
+                    &filename
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                );
+
+                match archive.add(&name, &map) {
+                    Ok(_) => {
+                        if let Some(metadata) = metadata {
+                            if let Err(err) = metadata.to_json().and_then(|json| {
+                                archive.add(&format!("{}.json", name), json.as_bytes())
+                            }) {
+                                let _ = bus.post(&Self::create_application_warning_message(&err));
+                            }
+                        }
+
+                        let _ = bus.post(&saved_msg(Some(archive.filename.as_path())));
+                        return;
+                    }
+                    Err(err) => {
+                        let _ = bus.post(&Self::create_application_warning_message(&err));
+                    }
+                }
+            }
+
+            let res = match file {
+                Some(file) => Ok(file),
+                None => File::create(&filename),
+            }
+            .and_then(|mut file| file.write_all(&map));
+
+            if let Err(err) = res {
+                let _ = bus.post(&Self::create_application_warning_message(
+                    format!(
+                        "Failed to write snapshot file {}: {}",
+                        filename.display(),
+                        err
+                    )
+                    .as_str(),
+                ));
+                let _ = bus.post(&saved_msg(None));
+                return;
+            }
+
+            if let Some(metadata) = metadata {
+                if let Err(err) = metadata.write(&filename) {
+                    let _ = bus.post(&Self::create_application_warning_message(&err));
+                }
+            }
+
+            let _ = bus.post(&saved_msg(Some(filename.as_path())));
+        };
+
+        if is_target_format {
+            println!("Frame is already in the snapshot format, writing it as is");
+            write(Ok(sample));
+        } else if format == SnapshotFormat::JPEG {
+            // The conversion can't configure the JPEG quality, so encode ourselves on a separate
+            // thread instead
+            let description = format!(
+                "videoconvert ! jpegenc quality={}",
+                settings.snapshot_jpeg_quality
+            );
+            thread::spawn(move || write(Self::process_sample(&sample, &description)));
+        } else {
+            gst_video::convert_sample_async(&sample, &caps, 5 * gst::SECOND, move |res| {
+                write(res.map_err(|err| err.to_string()))
+            });
+        }
+
+        Ok(Some(written_filename))
+    }
+
+    // Start writing all following snapshots into a new archive instead of separate files
+    pub fn start_snapshot_archive(&self) -> Result<(), Box<dyn error::Error>> {
+        let settings = self.settings();
+
+        utils::create_media_directory(&settings.snapshot_directory)?;
+
+        let mut filename = settings.snapshot_directory.clone();
+        filename.push(format!(
+            "{}.zip",
+            settings
+                .filename_timezone
+                .format_now("Snapshots %Y-%m-%d %H-%M-%S")
+        ));
+        let filename = utils::get_unique_path(filename);
+
+        println!("Writing snapshots to archive {}", filename.display());
+
+        let archive = SnapshotArchive::new(filename)?;
+        *self.snapshot_archive.borrow_mut() = Some(Arc::new(Mutex::new(archive)));
+
+        Ok(())
+    }
+
+    // Number all following snapshots as part of a new burst
+    pub fn start_snapshot_burst(&self) {
+        *self.snapshot_burst.borrow_mut() = Some(Rc::new(RefCell::new(SnapshotBurst::new())));
+    }
+
+    // Stop numbering snapshots as part of the current burst, if any
+    //
+    // Snapshots that still wait for their frame are given the time for that
+    pub fn finish_snapshot_burst(&self) {
+        let burst = match *self.snapshot_burst.borrow() {
+            Some(ref burst) => burst.clone(),
+            None => return,
+        };
+
+        let pipeline_weak = self.downgrade();
+        gtk::timeout_add(SNAPSHOT_FRAME_TIMEOUT_MS, move || {
+            let pipeline = upgrade_weak!(pipeline_weak, glib::Continue(false));
+
+            // Unless a new burst was started in the meantime
+            let mut current_burst = pipeline.snapshot_burst.borrow_mut();
+            if current_burst
+                .as_ref()
+                .map(|current_burst| Rc::ptr_eq(current_burst, &burst))
+                .unwrap_or(false)
+            {
+                *current_burst = None;
+            }
+
+            glib::Continue(false)
+        });
+    }
+
+    // Stop writing snapshots into the current archive, if any
+    //
+    // Snapshots that still wait for their frame are given the time for that before the archive is
+    // finished
+    pub fn finish_snapshot_archive(&self) {
+        let archive = match *self.snapshot_archive.borrow() {
+            Some(ref archive) => archive.clone(),
+            None => return,
+        };
+
+        let pipeline_weak = self.downgrade();
+        gtk::timeout_add(SNAPSHOT_FRAME_TIMEOUT_MS, move || {
+            // Unless a new archive was started in the meantime. The archive is also finished if
+            // the pipeline is gone already
+            if let Some(pipeline) = pipeline_weak.upgrade() {
+                let mut current_archive = pipeline.snapshot_archive.borrow_mut();
+                if current_archive
+                    .as_ref()
+                    .map(|current_archive| Arc::ptr_eq(current_archive, &archive))
+                    .unwrap_or(false)
+                {
+                    *current_archive = None;
+                }
+            }
+
+            let mut archive = archive.lock().unwrap();
+            match archive.finish() {
+                Ok(_) => println!("Finished snapshot archive {}", archive.filename.display()),
+                Err(err) => utils::show_error_dialog(false, &err),
+            }
+
+            glib::Continue(false)
+        });
+    }
+
+    // Pass a single sample through the given elements, e.g. for downloading it from GL memory
+    //
+    // This runs a small pipeline just for this sample and waits at most a second for the result
+    fn process_sample(sample: &gst::Sample, description: &str) -> Result<gst::Sample, String> {
+        let pipeline = gst::parse_launch(&format!(
+            "appsrc name=src format=time ! {} ! appsink name=sink sync=false",
+            description
+        ))
+        .map_err(|err| format!("Failed to create processing pipeline: {}", err))?;
+        let pipeline = pipeline
+            .downcast::<gst::Bin>()
+            .expect("Processing pipeline is not a bin");
+
+        let src = pipeline
+            .get_by_name("src")
+            .expect("Processing pipeline has no appsrc");
+        let sink = pipeline
+            .get_by_name("sink")
+            .expect("Processing pipeline has no appsink");
+
+        src.set_property("caps", &sample.get_caps().map(|caps| caps.to_owned()))
+            .expect("appsrc had no caps property");
+
+        let res = (|| {
+            pipeline
+                .set_state(gst::State::Playing)
+                .map_err(|err| format!("Failed to start processing pipeline: {}", err))?;
+
+            src.emit("push-sample", &[sample])
+                .map_err(|err| format!("Failed to push sample: {}", err))?;
+            src.emit("end-of-stream", &[])
+                .map_err(|err| format!("Failed to push sample: {}", err))?;
+
+            // If anything fails in the pipeline we get no sample before the timeout
+            sink.emit("try-pull-sample", &[&gst::SECOND.nseconds().unwrap_or(0)])
+                .map_err(|err| format!("Failed to pull sample: {}", err))?
+                .and_then(|value| value.get::<gst::Sample>())
+                .ok_or_else(|| String::from("No sample was produced"))
+        })();
+
+        let _ = pipeline.set_state(gst::State::Null);
+
+        res
+    }
+
+    // Get the RGB value of the preview pixel at the given widget coordinates
+    //
+    // Returns None if there is no frame yet or the coordinates are outside the video
+    pub fn get_preview_pixel(&self, x: f64, y: f64) -> Option<(u32, u32, [u8; 3])> {
+        if self.preview_disabled {
+            return None;
+        }
+
+        let sample = self.get_last_sample()?;
+
+        let info = gst_video::VideoInfo::from_caps(&sample.get_caps()?)?;
+        let par = info.par();
+        let (frame_width, frame_height) = (f64::from(info.width()), f64::from(info.height()));
+        // Non-square pixels are stretched horizontally for displaying
+        let par = f64::from(*par.numer()) / f64::from(*par.denom());
+        let display_width = frame_width * par;
+
+        // The video is scaled to the widget size while keeping the aspect ratio and centered, so
+        // undo this here
+        let allocation = self.get_widget().get_allocation();
+        let (widget_width, widget_height) =
+            (f64::from(allocation.width), f64::from(allocation.height));
+        let scale = f64::min(widget_width / display_width, widget_height / frame_height);
+        let x = (x - (widget_width - display_width * scale) / 2.0) / scale / par;
+        let y = (y - (widget_height - frame_height * scale) / 2.0) / scale;
+
+        if x < 0.0 || y < 0.0 || x >= frame_width || y >= frame_height {
+            return None;
+        }
+        let (x, y) = (x as u32, y as u32);
+
+        // This is called for every click, so read directly from the frame instead of converting
+        // it. Frames in e.g. GL memory can't be mapped and give None
+        let buffer = sample.get_buffer()?.to_owned();
+        let frame = gst_video::VideoFrame::from_buffer_readable(buffer, &info).ok()?;
+
+        Some((x, y, read_pixel(&frame, x, y)?))
+    }
+
+    // Add a new branch after the tee, e.g. for recording
+    //
+    // The bin needs a ghost sink pad called "sink"
+    fn add_tee_branch(&self, bin: &gst::Bin) -> Result<(), Box<dyn error::Error>> {
+        // First try setting the bin to playing: if this fails we know this before it
+        // potentially interferred with the other part of the pipeline
+        bin.set_state(gst::State::Playing)
+            .map_err(|_err| "Failed to set bin to playing")?;
+
+        // Add the bin to the pipeline. This would only fail if there was already a bin with the
+        // same name, which we ensured can't happen
+        self.pipeline.add(bin).expect("Failed to add bin");
+
+        // Request a new source pad from our tee and then link that to our bin to actually start
+        // receiving data
+        let srcpad = self
+            .tee
+            .get_request_pad("src_%u")
+            .expect("Failed to request new pad from tee");
+        let sinkpad = bin
+            .get_static_pad("sink")
+            .expect("Failed to get sink pad from bin");
+
+        // If linking fails, we just undo what we did above
+        if let Err(err) = srcpad.link(&sinkpad) {
+            // This might fail but we don't care anymore: we're in an error path
+            let _ = self.pipeline.remove(bin);
+            let _ = bin.set_state(gst::State::Null);
+            self.tee.release_request_pad(&srcpad);
+
+            return Err(format!("Failed to link bin: {}", err).as_str().into());
+        }
+
+        Ok(())
+    }
+
+    // Start serving JPEG frames of the preview, e.g. over HTTP
+    //
+    // This is another branch after the tee that encodes at most one frame per second to keep the
+    // CPU usage low. If the encoder can't keep up, frames are dropped before they reach it
+    pub fn start_http_preview(&self) -> Result<(), Box<dyn error::Error>> {
+        if self.http_preview_bin.borrow().is_some() {
+            return Ok(());
+        }
+
+        let bin = gst::parse_bin_from_description(
+            "queue leaky=downstream max-size-buffers=1 ! videorate drop-only=true ! video/x-raw,framerate=1/1 ! videoconvert ! jpegenc ! fakesink name=sink sync=false async=false enable-last-sample=true",
+            true,
+        )
+        .map_err(|err| format!("Failed to create HTTP preview pipeline: {}", err))?;
+
+        self.add_tee_branch(&bin)
+            .map_err(|err| format!("Failed to start HTTP preview: {}", err))?;
+
+        *self.http_preview_bin.borrow_mut() = Some(bin);
+
+        Ok(())
+    }
+
+    // Stop serving JPEG frames of the preview
+    pub fn stop_http_preview(&self) {
+        let bin = match self.http_preview_bin.borrow_mut().take() {
+            None => return,
+            Some(bin) => bin,
+        };
+
+        self.remove_tee_branch(bin);
+    }
+
+    // Measure the level of the given audio source, so that it can already be checked before
+    // recording. Nothing is measured for AudioSource::None
+    //
+    // This runs separately from the recording, which captures the same source again
+    pub fn set_audio_monitor(&self, source: AudioSource) -> Result<(), Box<dyn error::Error>> {
+        if self.audio_monitor.borrow().as_ref().map(|(s, _)| *s) == Some(source) {
+            return Ok(());
+        }
+        self.stop_audio_monitor();
+
+        let source_description = match Self::get_audio_source_description(source)? {
+            Some(source_description) => source_description,
+            None => return Ok(()),
+        };
+
+        let bin = gst::parse_bin_from_description(
+            &format!(
+                "{} ! queue ! audioconvert ! level interval=100000000 ! fakesink sync=false async=false",
+                source_description
+            ),
+            false,
+        )
+        .map_err(|err| format!("Failed to create audio level pipeline: {}", err))?;
+
+        self.pipeline.add(&bin).expect("Failed to add bin");
+        if bin.sync_state_with_parent().is_err() {
+            let _ = bin.set_state(gst::State::Null);
+            let _ = self.pipeline.remove(&bin);
+            return Err("Failed to start measuring the audio level".into());
+        }
+
+        *self.audio_monitor.borrow_mut() = Some((source, bin));
+
+        Ok(())
+    }
+
+    pub fn is_audio_monitored(&self) -> bool {
+        self.audio_monitor.borrow().is_some()
+    }
+
+    // Check if the object is part of the audio level measurement
+    fn is_inside_audio_monitor(&self, obj: &gst::Object) -> bool {
+        self.audio_monitor
+            .borrow()
+            .as_ref()
+            .map(|(_, bin)| obj.has_as_ancestor(bin))
+            .unwrap_or(false)
+    }
+
+    // Check if the object is part of the HTTP preview branch
+    fn is_inside_http_preview(&self, obj: &gst::Object) -> bool {
+        self.http_preview_bin
+            .borrow()
+            .as_ref()
+            .map(|bin| obj.has_as_ancestor(bin))
+            .unwrap_or(false)
+    }
+
+    // Stop measuring the audio level
+    fn stop_audio_monitor(&self) {
+        if let Some((_, bin)) = self.audio_monitor.borrow_mut().take() {
+            // Only a source and a sink, not linked to anything else in the pipeline
+            let _ = bin.set_state(gst::State::Null);
+            let _ = self.pipeline.remove(&bin);
+        }
+    }
+
+    // Remove a branch after the tee again without finishing anything in it
+    fn remove_tee_branch(&self, bin: gst::Bin) {
+        let sinkpad = bin
+            .get_static_pad("sink")
+            .expect("Failed to get sink pad from bin");
+        let pipeline = self.pipeline.clone();
+
+        // Not linked anymore, so we can directly get rid of it
+        let srcpad = match sinkpad.get_peer() {
+            Some(peer) => peer,
+            None => {
+                call_async!(pipeline => |pipeline| {
+                    let _ = pipeline.remove(&bin);
+                    let _ = bin.set_state(gst::State::Null);
+                });
+                return;
+            }
+        };
+
+        // Unlike for stopping a recording we don't need to finish anything here, so once the tee
+        // source pad is idle we can directly unlink and remove the bin
+        srcpad.add_probe(gst::PadProbeType::IDLE, move |srcpad, _| {
+            let tee = srcpad
+                .get_parent()
+                .and_then(|parent| parent.downcast::<gst::Element>().ok())
+                .expect("Failed to get tee source pad parent");
+
+            let _ = srcpad.unlink(&sinkpad);
+            tee.release_request_pad(srcpad);
+
+            // Shutting down the bin might block for a while, so do this asynchronously as we might
+            // be called from the main UI thread here
+            let bin = bin.clone();
+            call_async!(pipeline => |pipeline| {
+                let _ = pipeline.remove(&bin);
+                let _ = bin.set_state(gst::State::Null);
+            });
+
+            gst::PadProbeReturn::Remove
+        });
+    }
+
+    // Get the latest JPEG encoded preview frame, if any
+    pub fn get_http_preview_frame(&self) -> Option<Vec<u8>> {
+        let bin = self.http_preview_bin.borrow();
+        let sink = bin
+            .as_ref()?
+            .get_by_name("sink")
+            .expect("HTTP preview bin has no sink element");
+
+        let last_sample = sink
+            .get_property("last-sample")
+            .expect("Sink had no last-sample property");
+        let sample = last_sample.get::<gst::Sample>()?;
+        let buffer = sample.get_buffer()?;
+        let map = buffer.map_readable()?;
+
+        Some(map.as_slice().to_vec())
+    }
+
+    // Start recording to the configured location
+    pub fn start_recording(&self) -> Result<(), Box<dyn error::Error>> {
+        let settings = self.settings();
+
+        // Queues and encoders can need quite some memory, and running out of memory in the middle
+        // of the recording would take down the whole application
+        if settings.record_min_free_memory > 0 {
+            if let Some(available) = utils::get_available_memory() {
+                let required = u64::from(settings.record_min_free_memory) * 1024 * 1024;
+                if available < required {
+                    return Err(format!(
+                        "Only {} MiB of memory available but at least {} MiB are required. \
+                         Try recording with a lower resolution or bitrate",
+                        available / 1024 / 1024,
+                        settings.record_min_free_memory
+                    )
+                    .into());
+                }
+            }
+        }
+
+        // Same for the disk space, muxers fail in the middle of the file once the disk is full
+        if settings.record_min_free_space > 0 {
+            if let Some(free_space) = utils::get_free_space(&settings.record_directory) {
+                let required = u64::from(settings.record_min_free_space) * 1024 * 1024;
+                if free_space < required {
+                    return Err(format!(
+                        "Only {} free in {} but at least {} MiB are required",
+                        utils::format_size(free_space),
+                        settings.record_directory.display(),
+                        settings.record_min_free_space
+                    )
+                    .into());
+                }
+            }
+        }
+
+        self.start_recording_with_format(
+            &settings,
+            settings.record_codec,
+            settings.record_container,
+        )
+    }
+
+    // Start recording to the configured location with the given codec and container instead of
+    // the configured ones
+    fn start_recording_with_format(
+        &self,
+        settings: &Settings,
+        codec: VideoCodec,
+        container: Container,
+    ) -> Result<(), Box<dyn error::Error>> {
+        let (encoder_factory, encoder_options) = match codec {
+            VideoCodec::H264 => (
+                "x264enc",
+                format!(
+                    "tune=zerolatency bitrate={} speed-preset={} ! capsfilter name=profile caps=video/x-h264,profile={}",
+                    settings.record_video_bitrate_kbps.max(1),
+                    settings.x264_preset.nick(),
+                    settings.h264_profile.nick()
+                ),
+            ),
+            // The encoder only outputs a byte-stream, which the MP4 muxer does not accept
+            VideoCodec::H265 => (
+                "x265enc",
+                format!(
+                    "tune=zerolatency bitrate={} ! h265parse",
+                    settings.record_video_bitrate_kbps.max(1)
+                ),
+            ),
+            VideoCodec::Vp8 => ("vp8enc", String::from("deadline=1")),
+            VideoCodec::Vp9 => ("vp9enc", String::from("deadline=1")),
+            VideoCodec::Av1 => ("av1enc", String::new()),
+        };
+        let encoder_description = format!("{} name=encoder {}", encoder_factory, encoder_options);
+
+        let (muxer_factory, audio_encoder_factory) = match container {
+            Container::Mp4 => ("mp4mux", "avenc_aac"),
+            Container::Matroska => ("matroskamux", "opusenc"),
+            Container::WebM => ("webmmux", "opusenc"),
+        };
+
+        // Long recordings can be split into multiple files by splitmuxsink, which opens the files
+        // itself. Encrypted and pre-allocated recordings are written via a file descriptor instead
+        // and can't be split
+        let split = if settings.record_max_duration_secs == 0 {
+            false
+        } else if settings.record_encrypt || (cfg!(unix) && settings.record_preallocate_size > 0) {
+            utils::show_error_dialog(
+                false,
+                "Encrypted or pre-allocated recordings can't be split, recording into a single file instead",
+            );
+            false
+        } else {
+            true
+        };
+        // The muxer is configured on the splitmuxsink later if splitting
+        let (muxer_description, audio_sink_pad) = if split {
+            (String::from("splitmuxsink name=sink"), "sink.audio_%u")
+        } else {
+            (format!("{} name=mux", muxer_factory), "mux.")
+        };
+
+        // If the audio source is not available, record without audio instead of failing
+        // completely
+        let audio_source_description =
+            match Self::get_audio_source_description(settings.record_audio_source) {
+                Ok(audio_source_description) => audio_source_description,
+                Err(err) => {
+                    utils::show_error_dialog(
+                        false,
+                        format!("{}, recording without audio instead", err).as_str(),
+                    );
+                    None
+                }
+            };
+
+        // Same if the audio encoder is missing, the video alone is still more useful than nothing
+        let audio_source_description = match audio_source_description {
+            Some(_)
+                if self
+                    .check_elements_available(&[audio_encoder_factory])
+                    .is_err() =>
+            {
+                utils::show_error_dialog(
+                    false,
+                    format!(
+                        "Missing element {}, recording without audio instead",
+                        audio_encoder_factory
+                    )
+                    .as_str(),
+                );
+                None
+            }
+            audio_source_description => audio_source_description,
+        };
+        let audio_description = match audio_source_description {
+            Some(ref source) => format!(
+                " {} ! queue ! audioconvert ! audioresample ! {} ! {}",
+                source, audio_encoder_factory, audio_sink_pad
+            ),
+            None => String::new(),
+        };
+
+        // Give a helpful error message instead of a generic parsing error if anything is missing
+        let mut required_elements = vec![encoder_factory, muxer_factory];
+        if codec == VideoCodec::H265 {
+            required_elements.push("h265parse");
+        }
+        if split {
+            required_elements.push("splitmuxsink");
+        }
+        self.check_elements_available(&required_elements)?;
+
+        // Video editors often don't handle variable frame rates well, so optionally duplicate or
+        // drop frames as needed to get a constant frame rate. The preview is not affected by this
+        //
+        // The configured capture frame rate is used so that no frames are duplicated or dropped
+        // if the camera delivers what was asked for
+        let framerate_description = if settings.record_constant_framerate {
+            let framerate = if settings.capture_framerate != 0 {
+                settings.capture_framerate
+            } else {
+                DEFAULT_RECORD_FRAMERATE
+            };
+            format!("videorate ! video/x-raw,framerate={}/1 ! ", framerate)
+        } else {
+            String::new()
+        };
+
+        // Really rotate the frames instead of only storing a rotation flag in the file: not all
+        // players handle the latter. The preview is not affected by this
+        let orientation_description = if settings.record_portrait {
+            "videoflip method=clockwise ! "
+        } else {
+            ""
+        };
+
+        // Encrypted recordings are only supported where we can pass the file descriptor to the
+        // sink, as the file starts with a header that has to be written first
+        let key = if settings.record_encrypt {
+            if !cfg!(unix) {
+                return Err("Encrypting recordings is only supported on UNIX".into());
+            }
+
+            Some(
+                self.recording_key
+                    .borrow()
+                    .clone()
+                    .ok_or("No passphrase for encrypting the recording")?,
+            )
+        } else {
+            None
+        };
+
+        // A pre-allocated file is written to via its file descriptor as the filesink would
+        // truncate it again when opening it. This is not combined with encryption
+        let preallocate = cfg!(unix) && settings.record_preallocate_size > 0 && key.is_none();
+        let output_description = if split {
+            muxer_description
+        } else if preallocate || key.is_some() {
+            format!("{} ! fdsink name=sink", muxer_description)
+        } else {
+            format!("{} ! filesink name=sink", muxer_description)
+        };
+
+        let bin_description = format!(
+            "queue name=queue ! videoconvert ! {}{}{} ! {}{}",
+            orientation_description,
+            framerate_description,
+            encoder_description,
+            output_description,
+            audio_description
+        );
+
+        let bin = gst::parse_bin_from_description(&bin_description, true)
+            .map_err(|err| format!("Failed to create recording pipeline: {}", err))?;
+
+        // Check if the encoder can handle what the camera produces before even trying to record
+        let encoder = bin
+            .get_by_name("encoder")
+            .expect("Recording bin has no encoder element");
+        self.check_encoder_compatibility(&encoder)?;
+
+        // The VP8 and VP9 encoders take the bitrate in bit/s, and their defaults give rather low
+        // quality
+        if codec == VideoCodec::Vp8 || codec == VideoCodec::Vp9 {
+            let bitrate = settings
+                .record_video_bitrate_kbps
+                .max(1)
+                .min(i32::MAX as u32 / 1000);
+            encoder
+                .set_property("target-bitrate", &((bitrate * 1000) as i32))
+                .expect("Encoder had no target-bitrate property");
+        }
+
+        // Not all encoders support all H.264 profiles but every encoder supports the baseline
+        // profile, so fall back to that
+        if let Some(profile) = bin.get_by_name("profile") {
+            let profile_caps = profile
+                .get_property("caps")
+                .expect("capsfilter had no caps property")
+                .get::<gst::Caps>()
+                .expect("capsfilter had no caps");
+            let encoder_caps = encoder
+                .get_static_pad("src")
+                .expect("Encoder has no srcpad")
+                .query_caps(None);
+
+            if !encoder_caps.can_intersect(&profile_caps) {
+                utils::show_error_dialog(
+                    false,
+                    format!(
+                        "The encoder does not support the H.264 {} profile, recording with the baseline profile instead",
+                        settings.h264_profile.nick()
+                    )
+                    .as_str(),
+                );
+
+                let baseline_caps = gst::Caps::builder("video/x-h264")
+                    .field("profile", &H264Profile::Baseline.nick())
+                    .build();
+                profile
+                    .set_property("caps", &baseline_caps)
+                    .expect("capsfilter had no caps property");
+            }
+        }
+
+        // Configure how the recording is written to disk. With a leaky queue frames are dropped
+        // if the encoder or storage can't keep up, instead of blocking the recording branch
+        let tuning = settings.get_record_output_tuning();
+        let queue = bin
+            .get_by_name("queue")
+            .expect("Recording bin has no queue element");
+        if tuning.leaky {
+            queue.set_property_from_str("leaky", "downstream");
+        }
+
+        // Count all frames going into and out of the queue to know how many were dropped
+        let frames_in = Arc::new(AtomicUsize::new(0));
+        let frames_out = Arc::new(AtomicUsize::new(0));
+        for (pad_name, frames) in vec![("sink", frames_in.clone()), ("src", frames_out.clone())] {
+            queue
+                .get_static_pad(pad_name)
+                .expect("Queue has no pad")
+                .add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                    frames.fetch_add(1, Ordering::Relaxed);
+                    gst::PadProbeReturn::Ok
+                });
+        }
+
+        // Get our file sink element by its name and set the location where to write the recording
+        let sink = bin
+            .get_by_name("sink")
+            .expect("Recording bin has no sink element");
+
+        // When splitting, the splitmuxsink writes all fragments with its own muxer and filesink.
+        // We create those ourselves to be able to configure them like in the unsplit case
+        let file_sink = if split {
+            let muxer = gst::ElementFactory::make(muxer_factory, None)?;
+            Self::configure_muxer(&muxer, container);
+            let file_sink = gst::ElementFactory::make("filesink", None)?;
+            sink.set_property("muxer", &muxer)
+                .expect("splitmuxsink had no muxer property");
+            sink.set_property("sink", &file_sink)
+                .expect("splitmuxsink had no sink property");
+            sink.set_property(
+                "max-size-time",
+                &(u64::from(settings.record_max_duration_secs) * gst::SECOND_VAL),
+            )
+            .expect("splitmuxsink had no max-size-time property");
+
+            file_sink
+        } else {
+            if let Some(muxer) = bin.get_by_name("mux") {
+                Self::configure_muxer(&muxer, container);
+            }
+            sink.clone()
+        };
+        // A location chosen by the user only gets the extension of the container, e.g. when
+        // falling back to another format
+        let location = self.recording_location.borrow().clone();
+        let filename = match location {
+            Some(ref location) => location.with_extension(container.extension()),
+            None => get_recording_filename(settings, container)?,
+        };
+        let filename = if key.is_some() {
+            let mut filename = filename.into_os_string();
+            filename.push(".enc");
+            PathBuf::from(filename)
+        } else {
+            filename
+        };
+        // The user already confirmed overwriting the chosen location, but no other file. When
+        // splitting, the file that is actually written first is the first fragment
+        let filename = if location.as_ref() == Some(&filename) {
+            filename
+        } else if split {
+            utils::get_unique_path_with(filename, |path| get_split_location(path, Some(0)).exists())
+        } else {
+            utils::get_unique_path(filename)
+        };
+
+        // The recording is identified by its first fragment if split, everything written next
+        // to it like the metadata or chapters covers all fragments
+        let (filename, location) = if split {
+            (
+                get_split_location(&filename, Some(0)),
+                get_split_location(&filename, None),
+            )
+        } else {
+            (filename.clone(), filename)
+        };
+
+        // Count all bytes that are written for knowing the bitrate
+        let bytes_written = Arc::new(AtomicU64::new(0));
+        {
+            let bytes_written = bytes_written.clone();
+            file_sink
+                .get_static_pad("sink")
+                .expect("Sink has no sinkpad")
+                .add_probe(gst::PadProbeType::BUFFER, move |_pad, info| {
+                    if let Some(gst::PadProbeData::Buffer(ref buffer)) = info.data {
+                        bytes_written.fetch_add(buffer.get_size() as u64, Ordering::Relaxed);
+                    }
+                    gst::PadProbeReturn::Ok
+                });
+        }
+
+        // Nothing about encrypted recordings is written next to them in plain text
+        let metadata = if settings.write_capture_metadata && key.is_none() {
+            Some(self.create_capture_metadata(settings, &format!("{:?}/{:?}", codec, container)))
+        } else {
+            None
+        };
+
+        // Everything is encrypted right before it is written to the file
+        let encrypted_file = match key {
+            Some(ref key) => {
+                let (file, cipher) = encryption::EncryptedFile::create(&filename, key)?;
+                encryption::encrypt_writes(&sink, cipher);
+                Self::set_sink_fd(&sink, file.as_file());
+
+                Some(file)
+            }
+            None => None,
+        };
+
+        let preallocation = if preallocate {
+            let preallocation = Preallocation::new(
+                &filename,
+                u64::from(settings.record_preallocate_size) * 1024 * 1024,
+            )?;
+            preallocation.track_writes(&sink);
+            Self::set_sink_fd(&sink, &preallocation.file);
+
+            Some(preallocation)
+        } else {
+            if encrypted_file.is_none() {
+                // All strings in GStreamer are UTF8, we need to convert the path to UTF8 which in
+                // theory can fail
+                sink.set_property("location", &(location.to_str().unwrap()))
+                    .expect("Filesink had no location property");
+                file_sink.set_property_from_str("buffer-mode", tuning.buffer_mode.nick());
+            }
+
+            None
+        };
+        file_sink
+            .set_property("sync", &tuning.sync)
+            .expect("Sink had no sync property");
+        file_sink
+            .set_property("async", &tuning.async_)
+            .expect("Sink had no async property");
+
+        // The audio source timestamps its buffers based on the clock, so it must use the same clock
+        // and base time as the already running pipeline to stay in sync with the video
+        if audio_source_description.is_some() {
+            if let Some(clock) = self.pipeline.get_clock() {
+                let _ = bin.set_clock(Some(&clock));
+            }
+            bin.set_base_time(self.pipeline.get_base_time());
+            bin.set_start_time(gst::CLOCK_TIME_NONE);
+        }
+
+        self.add_tee_branch(&bin)
+            .map_err(|err| format!("Failed to start recording: {}", err))?;
+
+        println!("Recording to {}", location.display());
+
+        // Drop everything while the recording is paused
+        let paused = Arc::new(AtomicBool::new(false));
+        {
+            let sinkpad = bin
+                .get_static_pad("sink")
+                .expect("Recording bin has no sinkpad");
+            let audio_srcpad = bin
+                .get_by_name("audiosrc")
+                .and_then(|audiosrc| audiosrc.get_static_pad("src"));
+            for pad in Some(sinkpad).iter().chain(audio_srcpad.iter()) {
+                let paused = paused.clone();
+                pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, _info| {
+                    if paused.load(Ordering::SeqCst) {
+                        gst::PadProbeReturn::Drop
+                    } else {
+                        gst::PadProbeReturn::Ok
+                    }
+                });
+            }
+        }
+
+        let start_running_time = self.get_running_time();
+        *self.recording.borrow_mut() = Some(Recording {
+            bin,
+            filename,
+            frames_in,
+            frames_out,
+            bytes_written,
+            start_time: Instant::now(),
+            last_bytes_written: Cell::new(0),
+            current_bitrate: Cell::new(None),
+            preallocation,
+            metadata,
+            encrypted_file,
+            start_running_time,
+            chapters: Vec::new(),
+            paused,
+            paused_at: None,
+            paused_duration: gst::ClockTime::from_nseconds(0),
+        });
+
+        Ok(())
+    }
+
+    // Description of the element that captures the given audio source, None for no audio
+    //
+    // System audio is captured from the monitor of an output device. An error is returned if
+    // there is no such device or no microphone is connected
+    fn get_audio_source_description(source: AudioSource) -> Result<Option<String>, &'static str> {
+        match source {
+            AudioSource::None => Ok(None),
+            AudioSource::Microphone if Self::has_microphone() => {
+                Ok(Some(String::from("autoaudiosrc name=audiosrc")))
+            }
+            AudioSource::Microphone => Err("No microphone found"),
+            AudioSource::SystemAudio => Self::find_monitor_source()
+                .map(|device| Some(format!("pulsesrc name=audiosrc device=\"{}\"", device)))
+                .ok_or("No system audio source found"),
+        }
+    }
+
+    // Check if there is any audio source apart from the monitors of audio output devices
+    fn has_microphone() -> bool {
+        let monitor = gst::DeviceMonitor::new();
+        monitor.add_filter(Some("Audio/Source"), None);
+        // If devices can't be listed at all, let autoaudiosrc try nonetheless
+        if monitor.start().is_err() {
+            return true;
+        }
+
+        let found = monitor.get_devices().into_iter().any(|device| {
+            device
+                .get_properties()
+                .and_then(|properties| {
+                    properties
+                        .get::<&str>("device.class")
+                        .map(|c| c != "monitor")
+                })
+                .unwrap_or(true)
+        });
+        monitor.stop();
+
+        found
+    }
+
+    // Find the name of a monitor source, i.e. the loopback of an audio output device
+    //
+    // This works with PulseAudio and PipeWire's PulseAudio compatibility
+    fn find_monitor_source() -> Option<String> {
+        let monitor = gst::DeviceMonitor::new();
+        monitor.add_filter(Some("Audio/Source"), None);
+        if monitor.start().is_err() {
+            return None;
+        }
+
+        let device = monitor.get_devices().into_iter().find(|device| {
+            device
+                .get_properties()
+                .and_then(|properties| {
+                    properties
+                        .get::<&str>("device.class")
+                        .map(|c| c == "monitor")
+                })
+                .unwrap_or(false)
+        });
+        monitor.stop();
+
+        device
+            .and_then(|device| device.get_property("internal-name").ok())
+            .and_then(|name| name.get::<String>())
+    }
+
+    // Name of the camera, if known
+    fn get_device_name(&self) -> Option<String> {
+        self.get_v4l2_source()
+            .and_then(|src| src.get_property("device-name").ok())
+            .and_then(|value| value.get::<String>())
+    }
+
+    // Format of the camera stream, once negotiated
+    fn get_video_info(&self) -> Option<gst_video::VideoInfo> {
+        self.tee
+            .get_static_pad("sink")
+            .expect("tee has no sinkpad")
+            .get_current_caps()
+            .and_then(|caps| gst_video::VideoInfo::from_caps(&caps))
+    }
+
+    // Short human readable description of the current camera stream, e.g. for showing it after
+    // the camera started a new stream
+    pub fn get_stream_description(&self, width: u32, height: u32) -> String {
+        match self.get_device_name() {
+            Some(device_name) => format!("{}, {}×{}", device_name, width, height),
+            None => format!("{}×{}", width, height),
+        }
+    }
+
+    // Collect the metadata of a capture that is just being started
+    fn create_capture_metadata(&self, settings: &Settings, format: &str) -> CaptureMetadata {
+        let device_name = self.get_device_name();
+        let info = self.get_video_info();
+
+        CaptureMetadata {
+            timestamp: settings.filename_timezone.format_now("%+"),
+            device_name,
+            width: info.as_ref().map(|info| info.width()),
+            height: info.as_ref().map(|info| info.height()),
+            format: String::from(format),
+            settings: settings.clone(),
+        }
+    }
+
+    // Let the fdsink write to the given file
+    #[cfg(unix)]
+    fn set_sink_fd(sink: &gst::Element, file: &File) {
+        use std::os::unix::io::AsRawFd;
+
+        sink.set_property("fd", &file.as_raw_fd())
+            .expect("Fdsink had no fd property");
+    }
+
+    #[cfg(not(unix))]
+    fn set_sink_fd(_sink: &gst::Element, _file: &File) {
+        unreachable!("Writing via file descriptors is only supported on UNIX");
+    }
+
+    // Check if the camera frames can be passed to the encoder, if necessary by converting them
+    //
+    // Gives an actionable error message instead of the generic negotiation error we would
+    // otherwise get once the recording is running
+    fn check_encoder_compatibility(&self, encoder: &gst::Element) -> Result<(), String> {
+        // If nothing was negotiated yet we can't know
+        let input_caps = match self
+            .tee
+            .get_static_pad("sink")
+            .expect("tee has no sinkpad")
+            .get_current_caps()
+        {
+            Some(caps) => caps,
+            None => return Ok(()),
+        };
+
+        let encoder_caps = encoder
+            .get_static_pad("sink")
+            .expect("Encoder has no sinkpad")
+            .query_caps(None);
+
+        // Directly compatible
+        if input_caps.can_intersect(&encoder_caps) {
+            return Ok(());
+        }
+
+        // The videoconvert in front of the encoder can convert to any raw format in system memory,
+        // but e.g. some hardware encoders only accept special memory types
+        if encoder_caps.can_intersect(&gst::Caps::new_simple("video/x-raw", &[])) {
+            return Ok(());
+        }
 
-        // Install a message handler on the pipeline's bus to catch errors
-        let bus = pipeline.pipeline.get_bus().expect("Pipeline had no bus");
+        let encoder_name = encoder
+            .get_factory()
+            .map(|factory| factory.get_name().to_string())
+            .unwrap_or_else(|| String::from("unknown"));
 
-        // GStreamer is thread-safe and it is possible to attach bus watches from any thread, which
-        // are then nonetheless called from the main thread. As such we have to make use of
-        // fragile::Fragile() here to be able to pass our non-Send application struct into a
-        // closure that requires Send.
-        //
-        // As we are on the main thread and the closure will be called on the main thread, this
-        // will not cause a panic and is perfectly safe.
-        let pipeline_weak = fragile::Fragile::new(pipeline.downgrade());
-        bus.add_watch(move |_bus, msg| {
-            let pipeline_weak = pipeline_weak.get();
-            let pipeline = upgrade_weak!(pipeline_weak, glib::Continue(false));
+        Err(format!(
+            "The encoder {} only accepts {} but the camera produces {}",
+            encoder_name, encoder_caps, input_caps
+        ))
+    }
 
-            pipeline.on_pipeline_message(msg);
+    // Check if elements of all the given factories can be created
+    //
+    // This is done lazily when starting a recording or taking a snapshot and not when creating the
+    // pipeline, and the results are cached as loading the plugins can take a while
+    fn check_elements_available(&self, factories: &[&str]) -> Result<(), String> {
+        let mut available_elements = self.available_elements.borrow_mut();
+        let missing = factories
+            .iter()
+            .filter(|factory| {
+                !*available_elements
+                    .entry(factory.to_string())
+                    .or_insert_with(|| gst::ElementFactory::make(factory, None).is_ok())
+            })
+            .cloned()
+            .collect::<Vec<_>>();
 
-            glib::Continue(true)
-        });
+        if missing.is_empty() {
+            return Ok(());
+        }
 
-        Ok(pipeline)
+        // Name the plugin for the elements where it is not obvious, especially for the ones from
+        // gst-plugins-bad/ugly as distributions often package them separately
+        let missing = missing
+            .iter()
+            .map(|factory| match Self::get_plugin_hint(factory) {
+                Some(hint) => format!("{} ({})", factory, hint),
+                None => factory.to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        Err(format!(
+            "Missing elements: {}. Please install the corresponding GStreamer plugins",
+            missing.join(", ")
+        ))
     }
 
-    // Downgrade to a weak reference
-    pub fn downgrade(&self) -> PipelineWeak {
-        PipelineWeak(Rc::downgrade(&self.0))
+    // Configure the muxer of a recording for the container
+    fn configure_muxer(muxer: &gst::Element, container: Container) {
+        // Matroska files stay playable if the application is killed while recording, but only up
+        // to the last complete cluster. Starting a new cluster every second keeps that loss small.
+        // The muxer is not made streamable, as that would leave out the index that is written at
+        // the end and that is needed for fast seeking in finished files
+        if container == Container::Matroska || container == Container::WebM {
+            if muxer.find_property("max-cluster-duration").is_some() {
+                muxer
+                    .set_property("max-cluster-duration", &(gst::SECOND_VAL as i64))
+                    .expect("Muxer had no max-cluster-duration property");
+            }
+        }
     }
 
-    pub fn get_widget(&self) -> gtk::Widget {
-        // Get the GTK video sink and retrieve the video display widget from it
-        let widget_value = self
-            .sink
-            .get_property("widget")
-            .expect("Sink had no widget property");
+    // Plugin and module that contain the given element, if it's one we use for recording
+    fn get_plugin_hint(factory: &str) -> Option<&'static str> {
+        match factory {
+            "x264enc" => Some("x264 plugin from gst-plugins-ugly"),
+            "x265enc" => Some("x265 plugin from gst-plugins-bad"),
+            "h265parse" => Some("videoparsersbad plugin from gst-plugins-bad"),
+            "vp8enc" | "vp9enc" => Some("vpx plugin from gst-plugins-good"),
+            "av1enc" => Some("aom plugin from gst-plugins-bad"),
+            "mp4mux" => Some("isomp4 plugin from gst-plugins-good"),
+            "matroskamux" | "webmmux" => Some("matroska plugin from gst-plugins-good"),
+            "splitmuxsink" => Some("multifile plugin from gst-plugins-good"),
+            _ => None,
+        }
+    }
 
-        widget_value
-            .get::<gtk::Widget>()
-            .expect("Sink's widget propery was of the wrong type")
+    // Change whether the running recording drops frames if the encoder or storage can't keep up
+    //
+    // Dropping frames keeps the recording going in real time but leaves gaps in it, while not
+    // dropping frames might make the preview stutter instead
+    pub fn set_recording_leaky(&self, leaky: bool) {
+        let recording = self.recording.borrow();
+        let recording = match *recording {
+            Some(ref recording) => recording,
+            None => return,
+        };
+
+        let queue = recording
+            .bin
+            .get_by_name("queue")
+            .expect("Recording bin has no queue element");
+        queue.set_property_from_str("leaky", if leaky { "downstream" } else { "no" });
     }
 
-    pub fn start(&self) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
-        // This has no effect if called multiple times
-        self.pipeline.set_state(gst::State::Playing)
+    // Check if a recording is currently running
+    pub fn is_recording(&self) -> bool {
+        self.recording.borrow().is_some()
     }
 
-    pub fn stop(&self) -> Result<gst::StateChangeSuccess, gst::StateChangeError> {
-        // This has no effect if called multiple times
-        self.pipeline.set_state(gst::State::Null)
+    // Length of the current recording so far, without the time it was paused
+    pub fn get_recording_duration(&self) -> Option<Duration> {
+        let position = self.get_recording_position()?;
+        Some(Duration::from_nanos(position.nanoseconds()?))
     }
 
-    // Take a snapshot of the current image and write it to the configured location
-    pub fn take_snapshot(&self) -> Result<(), Box<dyn error::Error>> {
-        use std::fs::File;
+    // Average bitrate of the current recording in bytes per second, if known yet
+    pub fn get_recording_bitrate(&self) -> Option<f64> {
+        self.recording.borrow().as_ref()?.get_bitrate()
+    }
 
-        let settings = utils::load_settings();
+    // Bitrate of the current recording during the last second in bytes per second, if known yet
+    pub fn get_recording_current_bitrate(&self) -> Option<f64> {
+        self.recording.borrow().as_ref()?.current_bitrate.get()
+    }
 
-        // Create the GStreamer caps for the output format
-        let (caps, extension) = match settings.snapshot_format {
-            SnapshotFormat::JPEG => (gst::Caps::new_simple("image/jpeg", &[]), "jpg"),
-            SnapshotFormat::PNG => (gst::Caps::new_simple("image/png", &[]), "png"),
+    // Pause the current recording without finishing the file
+    //
+    // Returns false if nothing is recorded right now
+    pub fn pause_recording(&self) -> bool {
+        let mut recording = self.recording.borrow_mut();
+        let recording = match recording.as_mut() {
+            Some(recording) => recording,
+            None => return false,
         };
 
-        let last_sample = self
-            .sink
-            .get_property("last-sample")
-            .expect("Sink had no last-sample property");
-        let last_sample = match last_sample.get::<gst::Sample>() {
-            None => {
-                // We have no sample to store yet
-                return Ok(());
-            }
-            Some(sample) => sample,
-        };
+        if recording.paused_at.is_none() {
+            println!("Pausing recording");
+            recording.paused_at = Some(self.get_running_time());
+            recording.paused.store(true, Ordering::SeqCst);
+        }
 
-        // Create the filename and open the file writable
-        let mut filename = settings.snapshot_directory.clone();
-        let now = Local::now();
-        filename.push(format!(
-            "{}.{}",
-            now.format("Snapshot %Y-%m-%d %H-%M-%S"),
-            extension
-        ));
+        true
+    }
 
-        let mut file = File::create(&filename).map_err(|err| {
-            format!(
-                "Failed to create snapshot file {}: {}",
-                filename.display(),
-                err
-            )
-        })?;
+    // Continue the paused recording in the same file
+    //
+    // The time while the recording was paused is removed from the timestamps via the pad
+    // offsets, so that there is no gap in the recording
+    pub fn resume_recording(&self) {
+        let mut recording = self.recording.borrow_mut();
+        let recording = match recording.as_mut() {
+            Some(recording) => recording,
+            None => return,
+        };
+        let paused_at = match recording.paused_at.take() {
+            Some(paused_at) => paused_at,
+            None => return,
+        };
 
-        // Then convert it from whatever format we got to PNG or JPEG as requested and write it out
-        println!("Writing snapshot to {}", filename.display());
-        let bus = self.pipeline.get_bus().expect("Pipeline has no bus");
-        gst_video::convert_sample_async(&last_sample, &caps, 5 * gst::SECOND, move |res| {
-            use std::io::Write;
+        let pause = self.get_running_time() - paused_at;
+        if pause.is_some() {
+            recording.paused_duration = recording.paused_duration + pause;
+        }
+        println!(
+            "Resuming recording after {}, paused for {} in total",
+            pause, recording.paused_duration
+        );
 
-            let sample = match res {
-                Err(err) => {
-                    let _ = bus.post(&Self::create_application_warning_message(
-                        format!("Failed to convert sample: {}", err).as_str(),
-                    ));
-                    return;
-                }
-                Ok(sample) => sample,
-            };
+        let offset = -(recording.paused_duration.nseconds().unwrap_or(0) as i64);
+        for pad in recording.get_input_pads() {
+            pad.set_offset(offset);
+        }
+        recording.paused.store(false, Ordering::SeqCst);
+    }
 
-            let buffer = sample.get_buffer().expect("Failed to get buffer");
-            let map = buffer
-                .map_readable()
-                .expect("Failed to map buffer readable");
+    // Check if the current recording is paused
+    pub fn is_recording_paused(&self) -> bool {
+        self.recording
+            .borrow()
+            .as_ref()
+            .map(|recording| recording.paused_at.is_some())
+            .unwrap_or(false)
+    }
 
-            if let Err(err) = file.write_all(&map) {
-                let _ = bus.post(&Self::create_application_warning_message(
-                    format!(
-                        "Failed to write snapshot file {}: {}",
-                        filename.display(),
-                        err
-                    )
-                    .as_str(),
-                ));
-            }
-        });
+    // Check if a recording was stopped but is not finalized yet
+    pub fn is_stopping_recording(&self) -> bool {
+        self.stopping_recording.borrow().is_some()
+    }
 
-        Ok(())
+    // Set the keys for encrypting the following recordings, if enabled
+    pub fn set_recording_key(&self, key: Option<encryption::Key>) {
+        *self.recording_key.borrow_mut() = key;
     }
 
-    // Start recording to the configured location
-    pub fn start_recording(&self) -> Result<(), Box<dyn error::Error>> {
-        let settings = utils::load_settings();
+    // Write the following recordings to the given file instead of the configured directory
+    pub fn set_recording_location(&self, location: Option<PathBuf>) {
+        *self.recording_location.borrow_mut() = location;
+    }
 
-        let (bin_description, extension) = match settings.record_format {
-            RecordFormat::H264Mp4 => ("queue ! videoconvert ! x264enc tune=zerolatency ! video/x-h264,profile=baseline ! mp4mux ! filesink name=sink", "mp4"),
-            RecordFormat::Vp8WebM => ("queue ! videoconvert ! vp8enc deadline=1 ! webmmux ! filesink name=sink", "webm"),
-        };
+    // Write the next snapshot to the given file instead of the configured directory
+    pub fn set_snapshot_location(&self, location: Option<PathBuf>) {
+        *self.snapshot_location.borrow_mut() = location;
+    }
 
-        let bin = gst::parse_bin_from_description(bin_description, true)
-            .map_err(|err| format!("Failed to create recording pipeline: {}", err))?;
+    // Give up on finalizing a stopped recording, e.g. because the muxer got stuck, and tear it
+    // down without waiting any longer
+    //
+    // The file is most likely incomplete. Returns its filename if there was such a recording
+    pub fn abort_stopping_recording(&self) -> Option<PathBuf> {
+        let recording = self.stopping_recording.borrow_mut().take()?;
 
-        // Get our file sink element by its name and set the location where to write the recording
-        let sink = bin
-            .get_by_name("sink")
-            .expect("Recording bin has no sink element");
-        let mut filename = settings.record_directory.clone();
-        let now = Local::now();
-        filename.push(format!(
-            "{}.{}",
-            now.format("Recording %Y-%m-%d %H-%M-%S"),
-            extension
-        ));
+        println!("Aborting recording that could not be finalized");
 
-        // All strings in GStreamer are UTF8, we need to convert the path to UTF8 which in theory
-        // can fail
-        sink.set_property("location", &(filename.to_str().unwrap()))
-            .expect("Filesink had no location property");
+        self.remove_tee_branch(recording.bin.clone());
 
-        // First try setting the recording bin to playing: if this fails we know this before it
-        // potentially interferred with the other part of the pipeline
-        bin.set_state(gst::State::Playing)
-            .map_err(|_err| "Failed to start recording")?;
+        Some(recording.filename.clone())
+    }
 
-        // Add the bin to the pipeline. This would only fail if there was already a bin with the
-        // same name, which we ensured can't happen
-        self.pipeline
-            .add(&bin)
-            .expect("Failed to add recording bin");
+    // Current running time of the pipeline, i.e. the time that passed since it was started
+    fn get_running_time(&self) -> gst::ClockTime {
+        match self.pipeline.get_clock() {
+            Some(clock) => clock.get_time() - self.pipeline.get_base_time(),
+            None => gst::CLOCK_TIME_NONE,
+        }
+    }
 
-        // Get our tee element by name, request a new source pad from it and then link that to our
-        // recording bin to actually start receiving data
-        let srcpad = self
-            .tee
-            .get_request_pad("src_%u")
-            .expect("Failed to request new pad from tee");
-        let sinkpad = bin
-            .get_static_pad("sink")
-            .expect("Failed to get sink pad from recording bin");
+    // Current position in the recording, excluding the time it was paused
+    //
+    // This is the position the muxer reports, so it matches the timestamps in the recording. Until
+    // the muxer knows its position it is calculated from the running time of the pipeline instead
+    pub fn get_recording_position(&self) -> Option<gst::ClockTime> {
+        let recording = self.recording.borrow();
+        let recording = recording.as_ref()?;
 
-        // If linking fails, we just undo what we did above
-        if let Err(err) = srcpad.link(&sinkpad) {
-            // This might fail but we don't care anymore: we're in an error path
-            let _ = self.pipeline.remove(&bin);
-            let _ = bin.set_state(gst::State::Null);
+        if let Some(position) = recording.bin.query_position::<gst::ClockTime>() {
+            if position.is_some() {
+                return Some(position);
+            }
+        }
 
-            return Err(format!("Failed to link recording bin: {}", err)
-                .as_str()
-                .into());
+        let now = recording
+            .paused_at
+            .unwrap_or_else(|| self.get_running_time());
+        let position = now - recording.start_running_time - recording.paused_duration;
+        if position.is_none() {
+            return None;
         }
 
-        *self.recording_bin.borrow_mut() = Some(bin);
+        Some(position)
+    }
+
+    // Add a chapter marker with the given label at the current position of the recording
+    //
+    // The markers are written next to the recording once it is stopped. Returns the position of
+    // the marker, or None if nothing is recorded right now
+    pub fn mark_chapter(&self, label: &str) -> Option<gst::ClockTime> {
+        let position = self.get_recording_position()?;
 
-        println!("Recording to {}", filename.display());
+        let mut recording = self.recording.borrow_mut();
+        let recording = recording.as_mut()?;
 
-        Ok(())
+        println!("Marking chapter {} at {}", label, position);
+        recording.chapters.push((position, String::from(label)));
+
+        Some(position)
     }
 
     // Stop recording if any recording was currently ongoing
     pub fn stop_recording(&self) {
         // Get our recording bin, if it does not exist then nothing has to be stopped actually.
         // This shouldn't really happen
-        let bin = match self.recording_bin.borrow_mut().take() {
+        let recording = match self.recording.borrow_mut().take() {
             None => return,
-            Some(bin) => bin,
+            Some(recording) => recording,
         };
+        let bin = recording.bin.clone();
 
         // Get the source pad of the tee that is connected to the recording bin
         let sinkpad = bin
@@ -312,6 +3536,18 @@ impl Pipeline {
 
         println!("Stopping recording");
 
+        // Like the metadata, chapters of encrypted recordings are not written in plain text
+        if !recording.chapters.is_empty() && recording.encrypted_file.is_none() {
+            let end =
+                self.get_running_time() - recording.start_running_time - recording.paused_duration;
+            if let Err(err) = write_chapters(&recording.filename, &recording.chapters, end) {
+                utils::show_error_dialog(false, &err);
+            }
+        }
+
+        // Remember the recording until it is finalized
+        *self.stopping_recording.borrow_mut() = Some(recording);
+
         // Once the tee source pad is idle and we wouldn't interfere with any data flow, unlink the
         // tee and the recording bin and finalize the recording bin by sending it an end-of-stream
         // event
@@ -338,9 +3574,15 @@ impl Pipeline {
 
             // Asynchronously send the end-of-stream event to the sinkpad as this might block for a
             // while and our closure here might've been called from the main UI thread
+            //
+            // The audio source, if any, has to be finished too or the muxer waits for more audio
             let sinkpad = sinkpad.clone();
+            let audiosrc = bin.get_by_name("audiosrc");
             call_async!(bin => |_| {
                 sinkpad.send_event(gst::Event::new_eos().build());
+                if let Some(ref audiosrc) = audiosrc {
+                    audiosrc.send_event(gst::Event::new_eos().build());
+                }
             });
 
             // Don't block the pad but remove the probe to let everything
@@ -357,8 +3599,82 @@ impl Pipeline {
         use gst::MessageView;
 
         // A message can contain various kinds of information but
-        // here we are only interested in errors so far
+        // here we are only interested in a few of them
         match msg.view() {
+            // If the video source can't produce the configured resolution or framerate we
+            // continue with whatever it can produce instead
+            MessageView::Error(err) if self.is_capture_caps_error(&err) => {
+                self.on_capture_caps_error();
+            }
+            // Errors from inside the recording only stop the recording but don't affect anything
+            // else
+            MessageView::Error(err)
+                if err
+                    .get_src()
+                    .map(|src| self.is_inside_recording(&src))
+                    .unwrap_or(false) =>
+            {
+                self.on_recording_error(
+                    &err.get_src().expect("Error without source"),
+                    &format!("{} ({:?})", err.get_error(), err.get_debug()),
+                );
+            }
+            // Errors from the audio level measurement or the HTTP preview only stop those, as they
+            // are optional extras that the camera does not depend on
+            MessageView::Error(err)
+                if err
+                    .get_src()
+                    .map(|src| self.is_inside_audio_monitor(&src))
+                    .unwrap_or(false) =>
+            {
+                self.stop_audio_monitor();
+                self.emit_event(PipelineEvent::AudioMonitorFailed(format!(
+                    "{} ({:?})",
+                    err.get_error(),
+                    err.get_debug()
+                )));
+            }
+            MessageView::Error(err)
+                if err
+                    .get_src()
+                    .map(|src| self.is_inside_http_preview(&src))
+                    .unwrap_or(false) =>
+            {
+                self.stop_http_preview();
+                utils::show_error_dialog(
+                    false,
+                    format!(
+                        "HTTP preview stopped: {} ({:?})",
+                        err.get_error(),
+                        err.get_debug()
+                    )
+                    .as_str(),
+                );
+            }
+            // Errors from the camera, e.g. because it was unplugged, shut down the pipeline and
+            // let the application try to reconnect. A running recording is lost at this point as
+            // no more data can reach the muxer for finishing the file
+            MessageView::Error(err)
+                if !self.placeholder
+                    && err
+                        .get_src()
+                        .map(|src| src.has_as_ancestor(&self.source))
+                        .unwrap_or(false) =>
+            {
+                let _ = self.pipeline.set_state(gst::State::Null);
+
+                let was_recording = self.recording.borrow_mut().take().is_some();
+                let _ = self.stopping_recording.borrow_mut().take();
+                if was_recording {
+                    self.emit_event(PipelineEvent::RecordingStopped);
+                }
+
+                self.emit_event(PipelineEvent::SourceError(format!(
+                    "{} ({:?})",
+                    err.get_error(),
+                    err.get_debug()
+                )));
+            }
             MessageView::Error(err) => {
                 utils::show_error_dialog(
                     true,
@@ -371,6 +3687,24 @@ impl Pipeline {
                     .as_str(),
                 );
             }
+            MessageView::Buffering(buffering) => {
+                let percent = buffering.get_percent();
+
+                // Non-live sources, e.g. network streams, have to be paused until enough data is
+                // buffered and can only continue playing afterwards. Live sources can't be paused
+                // and just continue after buffering
+                if !self.is_live.get() {
+                    if percent < 100 && !self.buffering.get() {
+                        self.buffering.set(true);
+                        let _ = self.pipeline.set_state(gst::State::Paused);
+                    } else if percent >= 100 && self.buffering.get() {
+                        self.buffering.set(false);
+                        let _ = self.pipeline.set_state(gst::State::Playing);
+                    }
+                }
+
+                self.emit_event(PipelineEvent::Buffering(percent));
+            }
             MessageView::Application(msg) => match msg.get_structure() {
                 // Here we can send ourselves messages from any thread and show them to the user in
                 // the UI in case something goes wrong
@@ -378,6 +3712,52 @@ impl Pipeline {
                     let text = s.get::<&str>("text").expect("Warning message without text");
                     utils::show_error_dialog(false, text);
                 }
+                Some(s) if s.get_name() == "snapshot-saved" => {
+                    if let Some(snapshot_filename) = s.get::<String>("snapshot-filename") {
+                        commit_snapshot_filename(&self.settings(), Path::new(&snapshot_filename));
+                    }
+                    let filename = s.get::<String>("filename").map(PathBuf::from);
+                    self.emit_event(PipelineEvent::SnapshotSaved(filename));
+                }
+                Some(s) if s.get_name() == "recording-finished" => {
+                    let filename = s
+                        .get::<String>("filename")
+                        .map(PathBuf::from)
+                        .expect("Recording finished message without filename");
+                    commit_recording_filename(&self.settings(), &filename);
+                    let dropped_frames = s.get::<u64>("dropped-frames").unwrap_or(0);
+                    self.emit_event(PipelineEvent::RecordingFinished {
+                        filename,
+                        dropped_frames: dropped_frames as usize,
+                    });
+                }
+                Some(s) if s.get_name() == "snapshot-frame" => {
+                    let sample = s
+                        .get::<gst::Sample>("sample")
+                        .expect("Snapshot frame message without sample");
+                    self.on_snapshot_frame(sample);
+                }
+                Some(s) if s.get_name() == "video-size" => {
+                    if let (Some(width), Some(height)) =
+                        (s.get::<u32>("width"), s.get::<u32>("height"))
+                    {
+                        // Zooming only works once the size is known
+                        self.apply_zoom();
+                        self.emit_event(PipelineEvent::VideoSizeChanged { width, height });
+                    }
+                }
+                // The camera started a new stream, see the probe on the tee
+                Some(s) if s.get_name() == "stream-started" => {
+                    if let (Some(width), Some(height)) =
+                        (s.get::<u32>("width"), s.get::<u32>("height"))
+                    {
+                        self.fps_stats.borrow_mut().reset();
+                        self.emit_event(PipelineEvent::StreamStarted { width, height });
+                    }
+                }
+                Some(s) if s.get_name() == "first-frame" => {
+                    self.emit_event(PipelineEvent::FirstFrame);
+                }
                 _ => (),
             },
             MessageView::Element(msg) => {
@@ -387,7 +3767,28 @@ impl Pipeline {
                 //
                 // The normal end-of-stream message would only be sent once *all* sinks had their
                 // end-of-stream message posted.
+                //
+                // When splitting recordings, splitmuxsink finalizes every fragment internally and
+                // only lets the end-of-stream of the last fragment through, so this also means
+                // that all fragments are finalized
                 match msg.get_structure() {
+                    // The levels are given per channel, only the loudest channel is shown
+                    Some(s) if s.get_name() == "level" => {
+                        let peak = s.get::<glib::ValueArray>("peak").map(|values| {
+                            (0..values.len() as u32)
+                                .filter_map(|i| values.get_nth(i)?.get::<f64>())
+                                .fold(std::f64::NEG_INFINITY, f64::max)
+                        });
+
+                        if let Some(peak) = peak {
+                            self.emit_event(PipelineEvent::AudioLevel(peak));
+                        }
+                    }
+                    Some(s) if s.get_name() == "splitmuxsink-fragment-opened" => {
+                        if let Some(location) = s.get::<&str>("location") {
+                            println!("Recording to {}", location);
+                        }
+                    }
                     Some(s) if s.get_name() == "GstBinForwarded" => {
                         // The forwarded, original message from the bin is stored in the message
                         // field of its structure
@@ -404,6 +3805,23 @@ impl Pipeline {
                                 None => return,
                             };
 
+                            // The recording file is finalized now
+                            let recording = {
+                                let mut stopping_recording = self.stopping_recording.borrow_mut();
+                                let is_stopping_recording = stopping_recording
+                                    .as_ref()
+                                    .map(|recording| {
+                                        recording.bin.upcast_ref::<gst::Element>() == &bin
+                                    })
+                                    .unwrap_or(false);
+
+                                if is_stopping_recording {
+                                    stopping_recording.take()
+                                } else {
+                                    None
+                                }
+                            };
+
                             // And then asynchronously remove it and set its state to Null
                             let pipeline = &self.pipeline;
                             call_async!(pipeline => |pipeline| {
@@ -416,6 +3834,65 @@ impl Pipeline {
                                     let _ = bus.post(&Self::create_application_warning_message(format!("Failed to stop recording: {}", err).as_str()));
                                 }
                             });
+
+                            if let Some(mut recording) = recording {
+                                let dropped_frames = recording.get_dropped_frames();
+                                println!("Recording finished, {} frames dropped", dropped_frames);
+
+                                // Helps with choosing a good pre-allocation size. The unused part
+                                // is cut off once the recording is dropped
+                                if let Some(ref preallocation) = recording.preallocation {
+                                    println!(
+                                        "Recording used {} MiB of the pre-allocated file",
+                                        preallocation.written.load(Ordering::Relaxed) / 1024 / 1024
+                                    );
+                                }
+
+                                if let Some(ref metadata) = recording.metadata {
+                                    if let Err(err) = metadata.write(&recording.filename) {
+                                        utils::show_error_dialog(false, &err);
+                                    }
+                                }
+
+                                // Encrypted recordings are only finished once the authentication
+                                // tag is appended, which reads the whole file again
+                                match recording.encrypted_file.take() {
+                                    Some(encrypted_file) => {
+                                        let bus =
+                                            self.pipeline.get_bus().expect("Pipeline has no bus");
+                                        let filename = recording.filename.clone();
+                                        thread::spawn(move || {
+                                            if let Err(err) = encrypted_file.finish() {
+                                                let _ = bus.post(
+                                                    &Self::create_application_warning_message(&err),
+                                                );
+                                            }
+
+                                            let _ = bus.post(
+                                                &gst::Message::new_application(
+                                                    gst::Structure::builder("recording-finished")
+                                                        .field(
+                                                            "filename",
+                                                            &filename
+                                                                .to_string_lossy()
+                                                                .into_owned(),
+                                                        )
+                                                        .field(
+                                                            "dropped-frames",
+                                                            &(dropped_frames as u64),
+                                                        )
+                                                        .build(),
+                                                )
+                                                .build(),
+                                            );
+                                        });
+                                    }
+                                    None => self.emit_event(PipelineEvent::RecordingFinished {
+                                        filename: recording.filename.clone(),
+                                        dropped_frames,
+                                    }),
+                                }
+                            }
                         }
                     }
                     _ => (),
@@ -425,6 +3902,132 @@ impl Pipeline {
         };
     }
 
+    // Check if the object is part of the running or stopping recording
+    fn is_inside_recording(&self, obj: &gst::Object) -> bool {
+        let recording = self.recording.borrow();
+        let stopping_recording = self.stopping_recording.borrow();
+
+        recording
+            .iter()
+            .chain(stopping_recording.iter())
+            .any(|recording| obj.has_as_ancestor(&recording.bin))
+    }
+
+    // Some encoder/muxer combinations only fail once data is flowing, in which case we get the
+    // error from the bus instead of when starting the recording
+    //
+    // The broken recording is removed without trying to finish it. If configured, recording is
+    // started again with a format that is more likely to work.
+    //
+    // Only the recording that contains the source of the error is removed, the other one of a
+    // running and a stopping recording continues normally
+    fn on_recording_error(&self, src: &gst::Object, error: &str) {
+        let settings = self.settings();
+
+        // Errors while finishing a recording only need the bin to be removed
+        let stopping_failed = self
+            .stopping_recording
+            .borrow()
+            .as_ref()
+            .map(|recording| src.has_as_ancestor(&recording.bin))
+            .unwrap_or(false);
+        if stopping_failed {
+            let recording = self
+                .stopping_recording
+                .borrow_mut()
+                .take()
+                .expect("No stopping recording");
+            self.remove_tee_branch(recording.bin);
+            utils::show_error_dialog(
+                false,
+                format!("Failed to finish recording: {}", error).as_str(),
+            );
+            return;
+        }
+
+        let bin = match self.recording.borrow_mut().take() {
+            Some(recording) => recording.bin,
+            None => return,
+        };
+        self.remove_tee_branch(bin);
+
+        let (fallback_codec, fallback_container) = (VideoCodec::Vp8, Container::WebM);
+        if settings.record_format_fallback
+            && (settings.record_codec, settings.record_container)
+                != (fallback_codec, fallback_container)
+        {
+            if self
+                .start_recording_with_format(&settings, fallback_codec, fallback_container)
+                .is_ok()
+            {
+                utils::show_error_dialog(
+                    false,
+                    format!(
+                        "Recording failed: {}\n\nContinuing the recording as VP8/WebM",
+                        error
+                    )
+                    .as_str(),
+                );
+                return;
+            }
+        }
+
+        utils::show_error_dialog(false, format!("Recording failed: {}", error).as_str());
+        self.emit_event(PipelineEvent::RecordingStopped);
+    }
+
+    // Check if an error was caused by the video source not supporting the configured caps
+    //
+    // Sources report this either directly or as a generic flow error with the not-negotiated
+    // reason in the debug message
+    fn is_capture_caps_error(&self, err: &gst::message::Error) -> bool {
+        if self.configured_capture_caps.is_none() || self.is_capture_caps_unconstrained() {
+            return false;
+        }
+
+        err.get_error().kind::<gst::StreamError>() == Some(gst::StreamError::NotNegotiated)
+            || err
+                .get_debug()
+                .map(|debug| debug.contains("not-negotiated"))
+                .unwrap_or(false)
+    }
+
+    fn is_capture_caps_unconstrained(&self) -> bool {
+        self.capture_caps
+            .get_property("caps")
+            .expect("capsfilter had no caps property")
+            .get::<gst::Caps>()
+            .map(|caps| caps.is_any())
+            .unwrap_or(true)
+    }
+
+    // Drop the resolution and framerate constraints and restart the pipeline
+    fn on_capture_caps_error(&self) {
+        let caps = self
+            .configured_capture_caps
+            .as_ref()
+            .map(|caps| caps.to_string())
+            .unwrap_or_default();
+
+        let _ = self.pipeline.set_state(gst::State::Null);
+        self.capture_caps
+            .set_property("caps", &gst::Caps::new_any())
+            .expect("capsfilter had no caps property");
+        if let Err(err) = self.start() {
+            utils::show_error_dialog(
+                true,
+                format!("Failed to restart pipeline: {}", err).as_str(),
+            );
+            return;
+        }
+
+        let bus = self.pipeline.get_bus().expect("Pipeline had no bus");
+        let _ = bus.post(&Self::create_application_warning_message(&format!(
+            "The camera does not support {}, using its default resolution and framerate instead",
+            caps
+        )));
+    }
+
     fn create_application_warning_message(text: &str) -> gst::Message {
         gst::Message::new_application(
             gst::Structure::builder("warning")
@@ -0,0 +1,164 @@
+use glib::{self, translate::*};
+use gst::{self, prelude::*};
+use gtk::{self, prelude::*};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use fragile;
+
+use crate::settings::{DecoderPreference, Settings};
+use crate::utils;
+
+thread_local! {
+    // Original ranks of all decoders whose rank we changed, for going back to automatic selection
+    static ORIGINAL_RANKS: RefCell<HashMap<String, gst::Rank>> = RefCell::new(HashMap::new());
+}
+
+// Let decodebin prefer or avoid hardware decoders by changing their rank
+//
+// Hardware decoders are marked as such in their class. This only affects pipelines that autoplug
+// decoders, the camera pipeline is not affected
+fn apply_decoder_preference(preference: DecoderPreference) {
+    let decoders = gst::ElementFactory::list_get_elements(
+        gst::ElementFactoryListType::DECODER | gst::ElementFactoryListType::MEDIA_VIDEO,
+        gst::Rank::None,
+    );
+
+    ORIGINAL_RANKS.with(|original_ranks| {
+        let mut original_ranks = original_ranks.borrow_mut();
+
+        for factory in decoders {
+            let is_hardware = factory
+                .get_metadata("klass")
+                .map(|klass| klass.contains("Hardware"))
+                .unwrap_or(false);
+            if !is_hardware {
+                continue;
+            }
+
+            let name = factory.get_name().to_string();
+            let original_rank = *original_ranks
+                .entry(name)
+                .or_insert_with(|| factory.get_rank());
+
+            let rank = match preference {
+                DecoderPreference::Automatic => original_rank,
+                DecoderPreference::Software => gst::Rank::None,
+                // Above all software decoders, which are at most primary
+                DecoderPreference::Hardware => from_glib(gst::Rank::Primary.to_glib() + 1),
+            };
+            factory.set_rank(rank);
+        }
+    });
+}
+
+// Play a recording in a separate window
+//
+// If hardware decoding is preferred but fails, playback is restarted once with software decoding
+pub fn show_playback_window(application: &gtk::Application, filename: &Path, settings: &Settings) {
+    let uri = match glib::filename_to_uri(filename, None) {
+        Ok(uri) => uri,
+        Err(err) => {
+            utils::show_error_dialog(
+                false,
+                format!("Failed to play {}: {}", filename.display(), err).as_str(),
+            );
+            return;
+        }
+    };
+
+    let (playbin, sink) = match (
+        gst::ElementFactory::make("playbin", None),
+        gst::ElementFactory::make("gtksink", None),
+    ) {
+        (Ok(playbin), Ok(sink)) => (playbin, sink),
+        _ => {
+            utils::show_error_dialog(false, "Failed to create playback pipeline");
+            return;
+        }
+    };
+
+    playbin
+        .set_property("uri", &uri.as_str())
+        .expect("playbin had no uri property");
+    playbin
+        .set_property("video-sink", &sink)
+        .expect("playbin had no video-sink property");
+
+    let widget = sink
+        .get_property("widget")
+        .expect("Sink had no widget property")
+        .get::<gtk::Widget>()
+        .expect("Sink's widget propery was of the wrong type");
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(
+        &filename
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    );
+    window.set_transient_for(application.get_active_window().as_ref());
+    window.set_default_size(640, 360);
+    window.add(&widget);
+
+    // Restart with software decoding if the hardware decoder fails, but only once
+    //
+    // The bus watch is called on the main thread, so the window can be passed via
+    // fragile::Fragile() to the closure that requires Send
+    let mut preference = settings.playback_decoder;
+    let bus = playbin.get_bus().expect("playbin had no bus");
+    let playbin_clone = playbin.clone();
+    let window_clone = fragile::Fragile::new(window.clone());
+    bus.add_watch(move |_bus, msg| {
+        use gst::MessageView;
+
+        match msg.view() {
+            // Stay at the last frame at the end
+            MessageView::Eos(..) => {
+                let _ = playbin_clone.set_state(gst::State::Paused);
+            }
+            MessageView::Error(err) if preference == DecoderPreference::Hardware => {
+                println!(
+                    "Hardware decoding failed, falling back to software decoding: {}",
+                    err.get_error()
+                );
+
+                preference = DecoderPreference::Software;
+                let _ = playbin_clone.set_state(gst::State::Null);
+                apply_decoder_preference(preference);
+                let _ = playbin_clone.set_state(gst::State::Playing);
+            }
+            MessageView::Error(err) => {
+                utils::show_error_dialog(
+                    false,
+                    format!("Failed to play recording: {}", err.get_error()).as_str(),
+                );
+                window_clone.get().destroy();
+                return glib::Continue(false);
+            }
+            _ => (),
+        }
+
+        glib::Continue(true)
+    });
+
+    // Shut down the playback once the window is closed. Removing the bus watch also drops the
+    // last references to the playbin and the window
+    let playbin_clone = playbin.clone();
+    window.connect_destroy(move |_| {
+        let _ = playbin_clone.set_state(gst::State::Null);
+        let _ = bus.remove_watch();
+    });
+
+    apply_decoder_preference(settings.playback_decoder);
+
+    window.show_all();
+
+    if let Err(err) = playbin.set_state(gst::State::Playing) {
+        utils::show_error_dialog(false, format!("Failed to play recording: {}", err).as_str());
+        window.destroy();
+    }
+}
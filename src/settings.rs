@@ -1,33 +1,99 @@
 use glib;
 use gtk::{self, prelude::*};
 
+use crate::app::{App, AppWeak};
+use crate::pipeline::Pipeline;
+use crate::sounds;
 use crate::utils;
 
 use std::cell::RefCell;
-use std::fs::create_dir_all;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::fs::{self, create_dir_all};
 use std::ops;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_any;
+
+use chrono::prelude::*;
+
+// Error of the conversions from the strings in the comboboxes below
+//
+// Unknown values can only come from a newer version or a bug, see get_combo_value()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedValue {
+    what: &'static str,
+    value: String,
+}
+
+impl UnsupportedValue {
+    fn new(what: &'static str, value: &str) -> Self {
+        UnsupportedValue {
+            what,
+            value: String::from(value),
+        }
+    }
+}
+
+impl fmt::Display for UnsupportedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unsupported {} '{}'", self.what, self.value)
+    }
+}
+
+impl error::Error for UnsupportedValue {}
+
+// Get the value selected in a combobox
+//
+// Instead of crashing on unknown values the user is told about it and the default is used
+fn get_combo_value<T>(combo: &gtk::ComboBoxText) -> T
+where
+    T: TryFrom<Option<glib::GString>, Error = UnsupportedValue> + Default,
+{
+    T::try_from(combo.get_active_text()).unwrap_or_else(|err| {
+        utils::show_error_dialog(
+            false,
+            format!("{}, using the default instead", err).as_str(),
+        );
+        T::default()
+    })
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
 pub enum SnapshotFormat {
     JPEG,
     PNG,
+    WEBP,
 }
 
 // Convenience for converting from the strings in the combobox
-impl From<Option<glib::GString>> for SnapshotFormat {
-    fn from(s: Option<glib::GString>) -> Self {
+impl TryFrom<Option<glib::GString>> for SnapshotFormat {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
         if let Some(s) = s {
             match s.to_lowercase().as_str() {
-                "jpeg" => SnapshotFormat::JPEG,
-                "png" => SnapshotFormat::PNG,
-                _ => panic!("unsupported output format"),
+                "jpeg" => Ok(SnapshotFormat::JPEG),
+                "png" => Ok(SnapshotFormat::PNG),
+                "webp" => Ok(SnapshotFormat::WEBP),
+                _ => Err(UnsupportedValue::new("output format", &s)),
             }
         } else {
-            SnapshotFormat::default()
+            Ok(SnapshotFormat::default())
+        }
+    }
+}
+
+impl SnapshotFormat {
+    // File extension of snapshots in this format
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            SnapshotFormat::JPEG => "jpg",
+            SnapshotFormat::PNG => "png",
+            SnapshotFormat::WEBP => "webp",
         }
     }
 }
@@ -38,316 +104,3639 @@ impl Default for SnapshotFormat {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
-pub enum RecordFormat {
-    H264Mp4,
-    Vp8WebM,
+// Where the video comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum SourceType {
+    // A camera that is connected to this machine
+    Device,
+    // A network camera that streams via RTSP
+    Rtsp,
 }
 
-impl<'a> From<&'a str> for RecordFormat {
-    fn from(s: &'a str) -> Self {
-        match s.to_lowercase().as_str() {
-            "h264/mp4" => RecordFormat::H264Mp4,
-            "vp8/webm" => RecordFormat::Vp8WebM,
-            _ => panic!("unsupported output format"),
+impl TryFrom<Option<glib::GString>> for SourceType {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "local camera" => Ok(SourceType::Device),
+                "network camera (rtsp)" => Ok(SourceType::Rtsp),
+                _ => Err(UnsupportedValue::new("video source", &s)),
+            }
+        } else {
+            Ok(SourceType::default())
         }
     }
 }
 
-impl From<Option<glib::GString>> for RecordFormat {
-    fn from(s: Option<glib::GString>) -> Self {
+impl Default for SourceType {
+    fn default() -> Self {
+        SourceType::Device
+    }
+}
+
+// Where snapshots are taken from
+//
+// The preview frames were already converted for display, while the full resolution branch keeps
+// the camera's native frames at the cost of an additional branch in the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum SnapshotSource {
+    Preview,
+    FullResolution,
+}
+
+impl TryFrom<Option<glib::GString>> for SnapshotSource {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
         if let Some(s) = s {
             match s.to_lowercase().as_str() {
-                "h264/mp4" => RecordFormat::H264Mp4,
-                "vp8/webm" => RecordFormat::Vp8WebM,
-                _ => panic!("unsupported output format"),
+                "preview" => Ok(SnapshotSource::Preview),
+                "full resolution" => Ok(SnapshotSource::FullResolution),
+                _ => Err(UnsupportedValue::new("snapshot source", &s)),
             }
         } else {
-            RecordFormat::default()
+            Ok(SnapshotSource::default())
         }
     }
 }
 
-impl Default for RecordFormat {
+impl Default for SnapshotSource {
     fn default() -> Self {
-        RecordFormat::H264Mp4
+        SnapshotSource::Preview
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct Settings {
-    // By default, the user's picture directory.
-    pub snapshot_directory: PathBuf,
-    // Format in which to save the snapshot.
-    pub snapshot_format: SnapshotFormat,
-    // Timer length in seconds.
-    pub timer_length: u32,
+// Audio to record together with the video
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum AudioSource {
+    None,
+    Microphone,
+    // Loopback of what is played on the desktop, e.g. for screencasts
+    SystemAudio,
+}
 
-    // By default, the user's video directory.
-    pub record_directory: PathBuf,
-    // Format to use for recording videos.
-    pub record_format: RecordFormat,
+impl TryFrom<Option<glib::GString>> for AudioSource {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "none" => Ok(AudioSource::None),
+                "microphone" => Ok(AudioSource::Microphone),
+                "system audio" => Ok(AudioSource::SystemAudio),
+                _ => Err(UnsupportedValue::new("audio source", &s)),
+            }
+        } else {
+            Ok(AudioSource::default())
+        }
+    }
 }
 
-impl Default for Settings {
-    fn default() -> Settings {
-        Settings {
-            snapshot_directory: glib::get_user_special_dir(glib::UserDirectory::Pictures)
-                .unwrap_or_else(|| PathBuf::from(".")),
-            snapshot_format: SnapshotFormat::default(),
-            timer_length: 3,
-            record_directory: glib::get_user_special_dir(glib::UserDirectory::Videos)
-                .unwrap_or_else(|| PathBuf::from(".")),
-            record_format: RecordFormat::default(),
+impl Default for AudioSource {
+    fn default() -> Self {
+        AudioSource::None
+    }
+}
+
+// Combined codec and container of older versions, only used for migrating old settings files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum RecordFormat {
+    H264Mp4,
+    H264Mkv,
+    Vp8WebM,
+}
+
+impl RecordFormat {
+    pub fn split(self) -> (VideoCodec, Container) {
+        match self {
+            RecordFormat::H264Mp4 => (VideoCodec::H264, Container::Mp4),
+            RecordFormat::H264Mkv => (VideoCodec::H264, Container::Matroska),
+            RecordFormat::Vp8WebM => (VideoCodec::Vp8, Container::WebM),
         }
     }
 }
 
-// Our refcounted settings struct for containing all the widgets we have to carry around.
-//
-// This represents our settings dialog.
-#[derive(Clone)]
-struct SettingsDialog(Rc<SettingsDialogInner>);
+// Video codec to use for recordings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum VideoCodec {
+    H264,
+    // Smaller files than H.264 at the same quality, but needs more CPU for encoding
+    H265,
+    Vp8,
+    Vp9,
+    Av1,
+}
 
-// Deref into the contained struct to make usage a bit more ergonomic
-impl ops::Deref for SettingsDialog {
-    type Target = SettingsDialogInner;
+impl TryFrom<Option<glib::GString>> for VideoCodec {
+    type Error = UnsupportedValue;
 
-    fn deref(&self) -> &SettingsDialogInner {
-        &*self.0
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "h.264" => Ok(VideoCodec::H264),
+                "h.265" => Ok(VideoCodec::H265),
+                "vp8" => Ok(VideoCodec::Vp8),
+                "vp9" => Ok(VideoCodec::Vp9),
+                "av1" => Ok(VideoCodec::Av1),
+                _ => Err(UnsupportedValue::new("video codec", &s)),
+            }
+        } else {
+            Ok(VideoCodec::default())
+        }
     }
 }
 
-// Weak reference to our settings dialog struct
-//
-// Weak references are important to prevent reference cycles. Reference cycles are cases where
-// struct A references directly or indirectly struct B, and struct B references struct A again
-// while both are using reference counting.
-struct SettingsDialogWeak(Weak<SettingsDialogInner>);
+impl VideoCodec {
+    // Whether the configured bitrate is used for this codec, otherwise the encoder's default
+    // quality is used
+    pub fn has_bitrate(self) -> bool {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 | VideoCodec::Vp8 | VideoCodec::Vp9 => true,
+            VideoCodec::Av1 => false,
+        }
+    }
+}
 
-impl SettingsDialogWeak {
-    // Upgrade to a strong reference if it still exists
-    pub fn upgrade(&self) -> Option<SettingsDialog> {
-        self.0.upgrade().map(SettingsDialog)
+impl Default for VideoCodec {
+    fn default() -> Self {
+        VideoCodec::H264
     }
 }
 
-struct SettingsDialogInner {
-    snapshot_directory_chooser: gtk::FileChooserButton,
-    snapshot_format: gtk::ComboBoxText,
-    timer_entry: gtk::SpinButton,
-    record_directory_chooser: gtk::FileChooserButton,
-    record_format: gtk::ComboBoxText,
+// Container format to use for recordings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum Container {
+    Mp4,
+    Matroska,
+    WebM,
 }
 
-impl SettingsDialog {
-    // Downgrade to a weak reference
-    fn downgrade(&self) -> SettingsDialogWeak {
-        SettingsDialogWeak(Rc::downgrade(&self.0))
+impl Container {
+    // All containers in the order in which they are shown to the user
+    pub fn all() -> &'static [Container] {
+        &[Container::Mp4, Container::Matroska, Container::WebM]
     }
 
-    // Take current settings value from all our widgets and store into the configuration file
-    fn save_settings(&self) {
-        let settings = Settings {
-            snapshot_directory: self
-                .snapshot_directory_chooser
-                .get_filename()
-                .unwrap_or_else(|| {
-                    glib::get_user_special_dir(glib::UserDirectory::Pictures)
-                        .unwrap_or_else(|| PathBuf::from("."))
-                }),
-            snapshot_format: SnapshotFormat::from(self.snapshot_format.get_active_text()),
-            timer_length: self.timer_entry.get_value_as_int() as _,
-            record_directory: self
-                .record_directory_chooser
-                .get_filename()
-                .unwrap_or_else(|| {
-                    glib::get_user_special_dir(glib::UserDirectory::Videos)
-                        .unwrap_or_else(|| PathBuf::from("."))
-                }),
-            record_format: RecordFormat::from(self.record_format.get_active_text()),
-        };
+    // Name of the container as shown to the user
+    pub fn name(self) -> &'static str {
+        match self {
+            Container::Mp4 => "MP4",
+            Container::Matroska => "Matroska",
+            Container::WebM => "WebM",
+        }
+    }
+
+    // Whether the codec can be stored in this container
+    //
+    // VP9 and AV1 in MP4 are only supported by newer versions of mp4mux, so they are not offered
+    pub fn supports(self, codec: VideoCodec) -> bool {
+        match self {
+            Container::Mp4 => codec == VideoCodec::H264 || codec == VideoCodec::H265,
+            Container::Matroska => true,
+            Container::WebM => codec != VideoCodec::H264 && codec != VideoCodec::H265,
+        }
+    }
 
-        utils::save_settings(&settings);
+    // File extension of recordings in this container
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Matroska => "mkv",
+            Container::WebM => "webm",
+        }
     }
 }
 
-// Construct the settings dialog and ensure that the settings file exists and is loaded
-pub fn show_settings_dialog(application: &gtk::Application) {
-    let s = utils::get_settings_file_path();
+impl TryFrom<Option<glib::GString>> for Container {
+    type Error = UnsupportedValue;
 
-    if !s.exists() {
-        if let Some(parent_dir) = s.parent() {
-            if !parent_dir.exists() {
-                if let Err(e) = create_dir_all(parent_dir) {
-                    utils::show_error_dialog(
-                        false,
-                        format!(
-                            "Error while trying to build settings snapshot_directory '{}': {}",
-                            parent_dir.display(),
-                            e
-                        )
-                        .as_str(),
-                    );
-                }
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "mp4" => Ok(Container::Mp4),
+                "matroska" => Ok(Container::Matroska),
+                "webm" => Ok(Container::WebM),
+                _ => Err(UnsupportedValue::new("container format", &s)),
             }
+        } else {
+            Ok(Container::default())
         }
     }
+}
 
-    let settings = utils::load_settings();
-
-    // Create an empty dialog with close button
-    let dialog = gtk::Dialog::new_with_buttons(
-        Some("WebCam Viewer settings"),
-        application.get_active_window().as_ref(),
-        gtk::DialogFlags::MODAL,
-        &[("Close", gtk::ResponseType::Close)],
-    );
+impl Default for Container {
+    fn default() -> Self {
+        Container::Mp4
+    }
+}
 
-    // All the UI widgets are going to be stored in a grid
-    let grid = gtk::Grid::new();
-    grid.set_column_spacing(4);
-    grid.set_row_spacing(4);
-    grid.set_margin_bottom(12);
+// Profile to use for H.264 recordings
+//
+// Higher profiles compress better but are not supported by all players
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum H264Profile {
+    Baseline,
+    Main,
+    High,
+}
 
-    // File chooser for selecting the snapshot directory plus the label
-    // next to it
-    let snapshot_directory_label = gtk::Label::new(Some("Snapshot directory"));
-    let snapshot_directory_chooser = gtk::FileChooserButton::new(
-        "Pick a directory to save snapshots",
-        gtk::FileChooserAction::SelectFolder,
-    );
+impl H264Profile {
+    // Name of the profile in the GStreamer caps
+    pub fn nick(self) -> &'static str {
+        match self {
+            H264Profile::Baseline => "baseline",
+            H264Profile::Main => "main",
+            H264Profile::High => "high",
+        }
+    }
+}
 
-    snapshot_directory_label.set_halign(gtk::Align::Start);
-    snapshot_directory_chooser.set_filename(settings.snapshot_directory);
+impl TryFrom<Option<glib::GString>> for H264Profile {
+    type Error = UnsupportedValue;
 
-    grid.attach(&snapshot_directory_label, 0, 0, 1, 1);
-    grid.attach(&snapshot_directory_chooser, 1, 0, 3, 1);
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "baseline" => Ok(H264Profile::Baseline),
+                "main" => Ok(H264Profile::Main),
+                "high" => Ok(H264Profile::High),
+                _ => Err(UnsupportedValue::new("H.264 profile", &s)),
+            }
+        } else {
+            Ok(H264Profile::default())
+        }
+    }
+}
 
-    // Snapshot format combobox plus the label next to it
-    let format_label = gtk::Label::new(Some("Snapshot format"));
-    let snapshot_format = gtk::ComboBoxText::new();
+impl Default for H264Profile {
+    fn default() -> Self {
+        H264Profile::Baseline
+    }
+}
 
-    format_label.set_halign(gtk::Align::Start);
+// Speed preset of the H.264 encoder
+//
+// Slower presets compress better but need more CPU, which can lead to dropped frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum X264Preset {
+    Ultrafast,
+    Superfast,
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+}
 
-    // We'll add our 2 support snapshot formats as text here and select
-    // the configured one
-    snapshot_format.append_text("JPEG");
-    snapshot_format.append_text("PNG");
-    snapshot_format.set_active(match settings.snapshot_format {
-        SnapshotFormat::JPEG => Some(0),
-        SnapshotFormat::PNG => Some(1),
-    });
-    snapshot_format.set_hexpand(true);
+impl X264Preset {
+    // Nick of the value in the GStreamer enum of x264enc
+    pub fn nick(self) -> &'static str {
+        match self {
+            X264Preset::Ultrafast => "ultrafast",
+            X264Preset::Superfast => "superfast",
+            X264Preset::Veryfast => "veryfast",
+            X264Preset::Faster => "faster",
+            X264Preset::Fast => "fast",
+            X264Preset::Medium => "medium",
+            X264Preset::Slow => "slow",
+            X264Preset::Slower => "slower",
+            X264Preset::Veryslow => "veryslow",
+        }
+    }
+}
 
-    grid.attach(&format_label, 0, 1, 1, 1);
-    grid.attach(&snapshot_format, 1, 1, 3, 1);
+impl TryFrom<Option<glib::GString>> for X264Preset {
+    type Error = UnsupportedValue;
 
-    // Snapshot timer length spin button plus the label next to it
-    let timer_label = gtk::Label::new(Some("Timer length (in seconds)"));
-    // We allow 0 to 15 seconds, in 1 second steps
-    let timer_entry = gtk::SpinButton::new_with_range(0., 15., 1.);
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "ultrafast" => Ok(X264Preset::Ultrafast),
+                "superfast" => Ok(X264Preset::Superfast),
+                "veryfast" => Ok(X264Preset::Veryfast),
+                "faster" => Ok(X264Preset::Faster),
+                "fast" => Ok(X264Preset::Fast),
+                "medium" => Ok(X264Preset::Medium),
+                "slow" => Ok(X264Preset::Slow),
+                "slower" => Ok(X264Preset::Slower),
+                "veryslow" => Ok(X264Preset::Veryslow),
+                _ => Err(UnsupportedValue::new("H.264 preset", &s)),
+            }
+        } else {
+            Ok(X264Preset::default())
+        }
+    }
+}
 
-    timer_label.set_halign(gtk::Align::Start);
-    timer_label.set_hexpand(true);
+// Same as the encoder's default
+impl Default for X264Preset {
+    fn default() -> Self {
+        X264Preset::Medium
+    }
+}
 
-    timer_entry.set_value(settings.timer_length as f64);
+// Whether the camera exposure should be locked, and when
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum ExposureLock {
+    Off,
+    WhileRecording,
+    Always,
+}
 
-    grid.attach(&timer_label, 0, 2, 1, 1);
-    grid.attach(&timer_entry, 1, 2, 3, 1);
+impl TryFrom<Option<glib::GString>> for ExposureLock {
+    type Error = UnsupportedValue;
 
-    // File chooser for selecting the record directory plus the label
-    // next to it
-    let record_directory_label = gtk::Label::new(Some("Record directory"));
-    let record_directory_chooser = gtk::FileChooserButton::new(
-        "Pick a directory to save records",
-        gtk::FileChooserAction::SelectFolder,
-    );
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "off" => Ok(ExposureLock::Off),
+                "while recording" => Ok(ExposureLock::WhileRecording),
+                "always" => Ok(ExposureLock::Always),
+                _ => Err(UnsupportedValue::new("exposure lock mode", &s)),
+            }
+        } else {
+            Ok(ExposureLock::default())
+        }
+    }
+}
 
-    record_directory_label.set_halign(gtk::Align::Start);
-    record_directory_chooser.set_filename(settings.record_directory);
+impl Default for ExposureLock {
+    fn default() -> Self {
+        ExposureLock::Off
+    }
+}
 
-    grid.attach(&record_directory_label, 0, 3, 1, 1);
-    grid.attach(&record_directory_chooser, 1, 3, 3, 1);
+// Timezone to use for the timestamps in filenames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum Timezone {
+    Local,
+    Utc,
+}
 
-    // Record format combobox plus the label next to it
-    let format_label = gtk::Label::new(Some("Record format"));
-    let record_format = gtk::ComboBoxText::new();
+impl Timezone {
+    // Format the current time in this timezone
+    pub fn format_now(self, format: &str) -> String {
+        match self {
+            Timezone::Local => Local::now().format(format).to_string(),
+            Timezone::Utc => Utc::now().format(format).to_string(),
+        }
+    }
+}
 
-    format_label.set_halign(gtk::Align::Start);
+impl TryFrom<Option<glib::GString>> for Timezone {
+    type Error = UnsupportedValue;
 
-    record_format.append_text("H264/MP4");
-    record_format.append_text("VP8/WebM");
-    record_format.set_active(match settings.record_format {
-        RecordFormat::H264Mp4 => Some(0),
-        RecordFormat::Vp8WebM => Some(1),
-    });
-    record_format.set_hexpand(true);
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "local" => Ok(Timezone::Local),
+                "utc" => Ok(Timezone::Utc),
+                _ => Err(UnsupportedValue::new("timezone", &s)),
+            }
+        } else {
+            Ok(Timezone::default())
+        }
+    }
+}
 
-    grid.attach(&format_label, 0, 4, 1, 1);
-    grid.attach(&record_format, 1, 4, 3, 1);
+impl Default for Timezone {
+    fn default() -> Self {
+        Timezone::Local
+    }
+}
 
-    // Put the grid into the dialog's content area
-    let content_area = dialog.get_content_area();
-    content_area.pack_start(&grid, true, true, 0);
-    content_area.set_border_width(10);
+// Presets for how the recording is written to disk
+//
+// Reliable never drops frames but blocks the recording if the storage is too slow, while
+// low-latency drops frames instead to keep up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum RecordOutputPreset {
+    Reliable,
+    LowLatency,
+    Advanced,
+}
 
-    let settings_dialog = SettingsDialog(Rc::new(SettingsDialogInner {
-        snapshot_directory_chooser,
-        snapshot_format,
-        timer_entry,
-        record_directory_chooser,
-        record_format,
-    }));
+impl TryFrom<Option<glib::GString>> for RecordOutputPreset {
+    type Error = UnsupportedValue;
 
-    // Finally connect to all kinds of change notification signals for the different UI widgets.
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "reliable" => Ok(RecordOutputPreset::Reliable),
+                "low-latency" => Ok(RecordOutputPreset::LowLatency),
+                "advanced" => Ok(RecordOutputPreset::Advanced),
+                _ => Err(UnsupportedValue::new("record output preset", &s)),
+            }
+        } else {
+            Ok(RecordOutputPreset::default())
+        }
+    }
+}
+
+impl Default for RecordOutputPreset {
+    fn default() -> Self {
+        RecordOutputPreset::Reliable
+    }
+}
+
+// Values of the filesink buffer-mode property
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum FileSinkBufferMode {
+    Default,
+    Full,
+    Line,
+    Unbuffered,
+}
+
+impl FileSinkBufferMode {
+    // Nick of the value in the GStreamer enum
+    pub fn nick(self) -> &'static str {
+        match self {
+            FileSinkBufferMode::Default => "default",
+            FileSinkBufferMode::Full => "full",
+            FileSinkBufferMode::Line => "line",
+            FileSinkBufferMode::Unbuffered => "unbuffered",
+        }
+    }
+}
+
+impl TryFrom<Option<glib::GString>> for FileSinkBufferMode {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "default" => Ok(FileSinkBufferMode::Default),
+                "full" => Ok(FileSinkBufferMode::Full),
+                "line" => Ok(FileSinkBufferMode::Line),
+                "unbuffered" => Ok(FileSinkBufferMode::Unbuffered),
+                _ => Err(UnsupportedValue::new("buffer mode", &s)),
+            }
+        } else {
+            Ok(FileSinkBufferMode::default())
+        }
+    }
+}
+
+impl Default for FileSinkBufferMode {
+    fn default() -> Self {
+        FileSinkBufferMode::Default
+    }
+}
+
+// Properties of the recording queue and filesink
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RecordOutputTuning {
+    // Synchronize writing to the clock instead of writing as fast as possible.
+    pub sync: bool,
+    // Let the filesink do asynchronous state changes.
+    #[serde(rename = "async")]
+    pub async_: bool,
+    // Buffering of the file writes.
+    pub buffer_mode: FileSinkBufferMode,
+    // Drop frames in the recording queue if the storage can't keep up.
+    pub leaky: bool,
+}
+
+impl Default for RecordOutputTuning {
+    // Defaults of the GStreamer elements
+    fn default() -> Self {
+        RecordOutputTuning {
+            sync: false,
+            async_: true,
+            buffer_mode: FileSinkBufferMode::default(),
+            leaky: false,
+        }
+    }
+}
+
+// Sound to play when taking a snapshot
+//
+// Apart from a custom file, these are our own sounds from data/sounds.
+//
+// This is stored as a plain string as TOML can't represent enum variants with a value: either the
+// name of one of the sounds or the path of the custom file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum CaptureSound {
+    None,
+    Shutter,
+    Bell,
+    Click,
+    Custom(PathBuf),
+}
+
+impl Default for CaptureSound {
+    fn default() -> Self {
+        CaptureSound::Shutter
+    }
+}
+
+impl From<String> for CaptureSound {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "None" => CaptureSound::None,
+            "Shutter" => CaptureSound::Shutter,
+            "Bell" => CaptureSound::Bell,
+            "Click" => CaptureSound::Click,
+            _ => CaptureSound::Custom(PathBuf::from(s)),
+        }
+    }
+}
+
+impl From<CaptureSound> for String {
+    fn from(sound: CaptureSound) -> Self {
+        match sound {
+            CaptureSound::None => String::from("None"),
+            CaptureSound::Shutter => String::from("Shutter"),
+            CaptureSound::Bell => String::from("Bell"),
+            CaptureSound::Click => String::from("Click"),
+            CaptureSound::Custom(path) => path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+// How snapshots and recordings are named
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum NamingMode {
+    // E.g. "Snapshot 2018-11-24 12-00-00.jpg"
+    Timestamp,
+    // E.g. "IMG_0001.jpg", continuing after the highest number used so far
+    Sequential,
+}
+
+impl TryFrom<Option<glib::GString>> for NamingMode {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "timestamp" => Ok(NamingMode::Timestamp),
+                "sequential" => Ok(NamingMode::Sequential),
+                _ => Err(UnsupportedValue::new("naming mode", &s)),
+            }
+        } else {
+            Ok(NamingMode::default())
+        }
+    }
+}
+
+impl Default for NamingMode {
+    fn default() -> Self {
+        NamingMode::Timestamp
+    }
+}
+
+// Which video decoders are used for playing back recordings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum DecoderPreference {
+    // Whatever GStreamer selects by default
+    Automatic,
+    Software,
+    // Falls back to software decoding if the hardware decoder fails
+    Hardware,
+}
+
+impl TryFrom<Option<glib::GString>> for DecoderPreference {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "automatic" => Ok(DecoderPreference::Automatic),
+                "software" => Ok(DecoderPreference::Software),
+                "hardware" => Ok(DecoderPreference::Hardware),
+                _ => Err(UnsupportedValue::new("decoder preference", &s)),
+            }
+        } else {
+            Ok(DecoderPreference::default())
+        }
+    }
+}
+
+impl Default for DecoderPreference {
+    fn default() -> Self {
+        DecoderPreference::Automatic
+    }
+}
+
+// How the video is flipped or rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum VideoFlipMethod {
+    None,
+    // Mirrored, like for selfies
+    Horizontal,
+    Vertical,
+    // Clockwise rotations
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl VideoFlipMethod {
+    // Nick of the value in the GStreamer enum of videoflip
+    pub fn nick(self) -> &'static str {
+        match self {
+            VideoFlipMethod::None => "none",
+            VideoFlipMethod::Horizontal => "horizontal-flip",
+            VideoFlipMethod::Vertical => "vertical-flip",
+            VideoFlipMethod::Rotate90 => "clockwise",
+            VideoFlipMethod::Rotate180 => "rotate-180",
+            VideoFlipMethod::Rotate270 => "counterclockwise",
+        }
+    }
+}
+
+impl TryFrom<Option<glib::GString>> for VideoFlipMethod {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "none" => Ok(VideoFlipMethod::None),
+                "mirror horizontally" => Ok(VideoFlipMethod::Horizontal),
+                "mirror vertically" => Ok(VideoFlipMethod::Vertical),
+                "rotate 90°" => Ok(VideoFlipMethod::Rotate90),
+                "rotate 180°" => Ok(VideoFlipMethod::Rotate180),
+                "rotate 270°" => Ok(VideoFlipMethod::Rotate270),
+                _ => Err(UnsupportedValue::new("video flip", &s)),
+            }
+        } else {
+            Ok(VideoFlipMethod::default())
+        }
+    }
+}
+
+impl Default for VideoFlipMethod {
+    fn default() -> Self {
+        VideoFlipMethod::None
+    }
+}
+
+// What happens if a snapshot file with the same name already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum FileCollision {
+    // Append "-1", "-2", etc. to the new file name
+    AppendSuffix,
+    // Ask whether to overwrite the existing file or to keep both
+    Ask,
+}
+
+impl TryFrom<Option<glib::GString>> for FileCollision {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "append a number" => Ok(FileCollision::AppendSuffix),
+                "ask" => Ok(FileCollision::Ask),
+                _ => Err(UnsupportedValue::new("file collision", &s)),
+            }
+        } else {
+            Ok(FileCollision::default())
+        }
+    }
+}
+
+impl Default for FileCollision {
+    fn default() -> Self {
+        FileCollision::AppendSuffix
+    }
+}
+
+// What happens when the window is closed
+//
+// The application can always be quit via the menu or ctrl+q
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum CloseAction {
+    Quit,
+    // Minimize the window, everything keeps running
+    Minimize,
+    // Hide the window while a recording is running and quit once it is finished. Without a
+    // recording this quits directly. The window is shown again when the application is started
+    // another time
+    Background,
+}
+
+impl TryFrom<Option<glib::GString>> for CloseAction {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "quit" => Ok(CloseAction::Quit),
+                "minimize" => Ok(CloseAction::Minimize),
+                "keep recording in background" => Ok(CloseAction::Background),
+                _ => Err(UnsupportedValue::new("close action", &s)),
+            }
+        } else {
+            Ok(CloseAction::default())
+        }
+    }
+}
+
+impl Default for CloseAction {
+    fn default() -> Self {
+        CloseAction::Quit
+    }
+}
+
+// Corner of the video where an overlay like the burned-in timestamp or the watermark is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl OverlayPosition {
+    // Nick of the horizontal alignment in the GStreamer enum of the text overlays
+    pub fn halignment(self) -> &'static str {
+        match self {
+            OverlayPosition::TopLeft | OverlayPosition::BottomLeft => "left",
+            OverlayPosition::TopRight | OverlayPosition::BottomRight => "right",
+        }
+    }
+
+    // Nick of the vertical alignment in the GStreamer enum of the text overlays
+    pub fn valignment(self) -> &'static str {
+        match self {
+            OverlayPosition::TopLeft | OverlayPosition::TopRight => "top",
+            OverlayPosition::BottomLeft | OverlayPosition::BottomRight => "bottom",
+        }
+    }
+}
+
+impl TryFrom<Option<glib::GString>> for OverlayPosition {
+    type Error = UnsupportedValue;
+
+    fn try_from(s: Option<glib::GString>) -> Result<Self, UnsupportedValue> {
+        if let Some(s) = s {
+            match s.to_lowercase().as_str() {
+                "top left" => Ok(OverlayPosition::TopLeft),
+                "top right" => Ok(OverlayPosition::TopRight),
+                "bottom left" => Ok(OverlayPosition::BottomLeft),
+                "bottom right" => Ok(OverlayPosition::BottomRight),
+                _ => Err(UnsupportedValue::new("timestamp position", &s)),
+            }
+        } else {
+            Ok(OverlayPosition::default())
+        }
+    }
+}
+
+impl Default for OverlayPosition {
+    fn default() -> Self {
+        OverlayPosition::BottomRight
+    }
+}
+
+// Missing fields, e.g. from settings files written by older versions, are taken from the defaults
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    // By default, the user's picture directory.
+    pub snapshot_directory: PathBuf,
+    // Format in which to save the snapshot.
+    pub snapshot_format: SnapshotFormat,
+    // Quality of JPEG snapshots from 0 to 100, out of range values are clamped when loading.
+    #[serde(deserialize_with = "deserialize_percentage")]
+    pub snapshot_jpeg_quality: u8,
+    // Timer length in seconds.
+    pub timer_length: u32,
+    // Number of snapshots to take at once after the timer, 1 for a single snapshot.
+    pub burst_count: u32,
+    // Time between the snapshots of a burst in milliseconds.
+    pub burst_interval_ms: u32,
+    // Device path of the camera to use, or its name if it has no path, or None for the default
+    // camera.
+    //
+    // Older versions always stored the name, which is still accepted.
+    pub video_device: Option<String>,
+    // Whether a local camera or a network camera is used, and the URL of the network camera plus
+    // how long to buffer its stream in milliseconds for smoothing out network jitter.
+    pub source_type: SourceType,
+    pub rtsp_url: String,
+    pub rtsp_latency_ms: u32,
+    // Deinterlace interlaced video, e.g. from capture cards, before it is shown or recorded.
+    // Progressive video is passed through unchanged.
+    pub deinterlace: bool,
+    // Description of a whole pipeline to use instead of the camera, e.g. for an IP camera. It
+    // needs a "tee name=tee" and a "gtksink name=sink", otherwise the camera is used.
+    pub custom_pipeline: Option<String>,
+    // Capture resolution, or 0 for the camera's default.
+    pub capture_width: u32,
+    pub capture_height: u32,
+    // Capture framerate in frames per second, or 0 for the camera's default.
+    pub capture_framerate: u32,
+    // Whether snapshots are taken from the preview or a separate full resolution branch.
+    pub snapshot_source: SnapshotSource,
+
+    // By default, the user's video directory.
+    pub record_directory: PathBuf,
+    // Codec and container to use for recording videos. The codec has to be supported by the
+    // container.
+    pub record_codec: VideoCodec,
+    pub record_container: Container,
+    // Combined codec and container of older versions, only read for migrating to the above.
+    #[serde(skip_serializing)]
+    pub record_format: Option<RecordFormat>,
+    // Audio to record together with the video.
+    pub record_audio_source: AudioSource,
+    // Timezone of the timestamps in the snapshot and recording filenames.
+    pub filename_timezone: Timezone,
+    // Profile to use for H.264 recordings.
+    pub h264_profile: H264Profile,
+    // Bitrate of H.264, H.265, VP8 and VP9 recordings in kbit/s, never 0.
+    pub record_video_bitrate_kbps: u32,
+    // Speed preset of the H.264 encoder.
+    pub x264_preset: X264Preset,
+    // Lock exposure and gain of the camera to prevent flickering, e.g. for timelapses.
+    pub exposure_lock: ExposureLock,
+
+    // Serve a low frame rate JPEG preview over HTTP for remote monitoring.
+    pub http_preview_enabled: bool,
+    // Port on which the HTTP preview is served.
+    pub http_preview_port: u16,
+    // Accept connections to the HTTP preview from other machines instead of only from localhost.
+    pub http_preview_remote: bool,
+
+    // How recordings are written to disk. The raw properties for the advanced preset are at the
+    // end.
+    pub record_output_preset: RecordOutputPreset,
+    // Record with a constant instead of the camera's possibly variable frame rate.
+    pub record_constant_framerate: bool,
+    // Continue with VP8/WebM if the recording fails with the configured format.
+    pub record_format_fallback: bool,
+    // Don't start recording if less memory than this is available, in MiB. 0 disables the check.
+    pub record_min_free_memory: u32,
+    // Don't start recording if less disk space than this is free, in MiB. 0 disables the check.
+    pub record_min_free_space: u32,
+    // Size of the recording file to allocate before recording, in MiB. 0 disables this.
+    pub record_preallocate_size: u32,
+    // Remind every this many minutes that a recording is running. 0 disables the reminder.
+    pub record_reminder_interval: u32,
+    // Start a new file every this many seconds, named like the recording plus "_00000",
+    // "_00001" and so on. 0 records into a single file.
+    pub record_max_duration_secs: u32,
+
+    // Sound to play when taking a snapshot.
+    pub capture_sound: CaptureSound,
+
+    // Ask before quitting while a recording is running.
+    pub confirm_quit_while_recording: bool,
+
+    // Don't render the camera preview, e.g. for recording-only devices.
+    pub disable_preview: bool,
+
+    // Take a snapshot as poster frame whenever a recording is started.
+    pub snapshot_on_record_start: bool,
+
+    // Only save changes in the settings dialog when applying them instead of immediately.
+    pub settings_apply_on_close: bool,
+
+    // Rotate recordings by 90 degrees for portrait output, e.g. for vertically mounted cameras.
+    pub record_portrait: bool,
+
+    // Start recording as soon as the camera delivers frames after launching.
+    pub auto_record_on_start: bool,
+
+    // Write a JSON file with metadata next to every snapshot and recording.
+    pub write_capture_metadata: bool,
+
+    // Take snapshots from the next complete and deinterlaced frame instead of the last shown one.
+    pub snapshot_wait_for_frame: bool,
+
+    // Collect the snapshots of a burst or interval session in a single ZIP archive.
+    pub snapshot_archive: bool,
+
+    // What happens if a snapshot file with the same name already exists.
+    pub snapshot_file_collision: FileCollision,
+
+    // Encrypt recordings with a passphrase that is asked for when starting to record.
+    pub record_encrypt: bool,
+
+    // Hide the preview while recording to save power, the recording is not affected.
+    pub minimize_preview_while_recording: bool,
+
+    // What closing the window does.
+    pub close_action: CloseAction,
+
+    // Show the countdown and status messages with high contrast and larger text.
+    pub high_contrast_overlay: bool,
+
+    // Show a desktop notification whenever a snapshot or recording is saved.
+    pub notifications_enabled: bool,
+
+    // Additionally write every snapshot as lossless PNG, with the same name apart from the
+    // extension.
+    pub snapshot_also_png: bool,
+
+    // Ask for the location of each snapshot and recording instead of saving them in the
+    // configured directories.
+    pub prompt_for_location: bool,
+
+    // How snapshots and recordings are named.
+    pub snapshot_naming: NamingMode,
+    pub record_naming: NamingMode,
+    // Templates for the timestamp names, with strftime-style fields and {n} for a counter.
+    pub snapshot_filename_template: String,
+    pub record_filename_template: String,
+
+    // Whether recordings are played back with software or hardware decoders.
+    pub playback_decoder: DecoderPreference,
+
+    // Color adjustments of the preview. Brightness and hue go from -1 to 1, contrast and
+    // saturation from 0 to 2.
+    pub brightness: f64,
+    pub contrast: f64,
+    pub saturation: f64,
+    pub hue: f64,
+
+    // Flip or rotation of the video, for the preview as well as recordings and snapshots
+    pub video_flip: VideoFlipMethod,
+
+    // Digital zoom into the center of the video, from 1 to MAX_ZOOM. Also applies to recordings
+    // and snapshots
+    pub zoom: f64,
+
+    // Burn the current date and time into the video, so that it is part of recordings and
+    // snapshots. The font size is in points.
+    pub burn_timestamp: bool,
+    pub timestamp_position: OverlayPosition,
+    pub timestamp_font_size: u32,
+
+    // Image that is overlaid on the video, e.g. a logo, with the corner where it is shown and its
+    // opacity from 0 to 1. Like the timestamp it is part of recordings and snapshots.
+    pub watermark_path: Option<PathBuf>,
+    pub watermark_position: OverlayPosition,
+    pub watermark_opacity: f64,
+
+    // Size, position and maximized state of the main window when it was last closed. The size
+    // is the one of the unmaximized window.
+    pub window_width: i32,
+    pub window_height: i32,
+    pub window_position: Option<(i32, i32)>,
+    pub window_maximized: bool,
+
+    // Show a rule-of-thirds grid over the preview. This is toggled from the header bar.
+    pub show_grid: bool,
+
+    // Show the frame rate and the recording bitrate over the preview. This is toggled from the
+    // menu.
+    pub show_stats: bool,
+
+    // Raw queue/filesink properties, only used with the advanced preset.
+    //
+    // This is written as a TOML table, and TOML can't have any plain values after tables, so this
+    // has to stay the last field.
+    pub record_output_tuning: RecordOutputTuning,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            snapshot_directory: utils::get_default_snapshot_directory(),
+            snapshot_format: SnapshotFormat::default(),
+            snapshot_jpeg_quality: 85,
+            timer_length: 3,
+            burst_count: 1,
+            burst_interval_ms: 200,
+            video_device: None,
+            source_type: SourceType::default(),
+            rtsp_url: String::new(),
+            rtsp_latency_ms: 200,
+            deinterlace: false,
+            custom_pipeline: None,
+            capture_width: 0,
+            capture_height: 0,
+            capture_framerate: 0,
+            snapshot_source: SnapshotSource::default(),
+            record_directory: utils::get_default_record_directory(),
+            record_codec: VideoCodec::default(),
+            record_container: Container::default(),
+            record_format: None,
+            record_audio_source: AudioSource::default(),
+            filename_timezone: Timezone::default(),
+            h264_profile: H264Profile::default(),
+            record_video_bitrate_kbps: DEFAULT_RECORD_VIDEO_BITRATE_KBPS,
+            x264_preset: X264Preset::default(),
+            exposure_lock: ExposureLock::default(),
+            http_preview_enabled: false,
+            http_preview_port: 8080,
+            http_preview_remote: false,
+            record_output_preset: RecordOutputPreset::default(),
+            record_output_tuning: RecordOutputTuning::default(),
+            record_constant_framerate: false,
+            record_format_fallback: true,
+            record_min_free_memory: 128,
+            record_min_free_space: 200,
+            record_preallocate_size: 0,
+            record_max_duration_secs: 0,
+            record_reminder_interval: 0,
+            capture_sound: CaptureSound::default(),
+            confirm_quit_while_recording: true,
+            disable_preview: false,
+            snapshot_on_record_start: false,
+            settings_apply_on_close: false,
+            record_portrait: false,
+            auto_record_on_start: false,
+            write_capture_metadata: false,
+            snapshot_wait_for_frame: false,
+            snapshot_archive: false,
+            snapshot_file_collision: FileCollision::default(),
+            record_encrypt: false,
+            minimize_preview_while_recording: false,
+            close_action: CloseAction::default(),
+            high_contrast_overlay: false,
+            notifications_enabled: true,
+            snapshot_also_png: false,
+            prompt_for_location: false,
+            snapshot_naming: NamingMode::default(),
+            record_naming: NamingMode::default(),
+            snapshot_filename_template: String::from("Snapshot %Y-%m-%d %H-%M-%S"),
+            record_filename_template: String::from("Recording %Y-%m-%d %H-%M-%S"),
+            playback_decoder: DecoderPreference::default(),
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            hue: 0.0,
+            video_flip: VideoFlipMethod::default(),
+            zoom: 1.0,
+            burn_timestamp: false,
+            timestamp_position: OverlayPosition::default(),
+            timestamp_font_size: DEFAULT_TIMESTAMP_FONT_SIZE,
+            watermark_path: None,
+            watermark_position: OverlayPosition::TopRight,
+            watermark_opacity: 1.0,
+            window_width: 840,
+            window_height: 480,
+            window_position: None,
+            window_maximized: false,
+            show_grid: false,
+            show_stats: false,
+        }
+    }
+}
+
+// Font size of the burned-in timestamp in points, readable at common camera resolutions
+const DEFAULT_TIMESTAMP_FONT_SIZE: u32 = 18;
+
+// Highest digital zoom factor, more than that only shows big blurry pixels
+pub const MAX_ZOOM: f64 = 4.0;
+
+// Allowed (minimum, maximum) values of the numeric settings. These are what the settings dialog
+// offers, and anything else is rejected when validating e.g. imported settings
+const TIMER_LENGTH_RANGE: (f64, f64) = (0.0, 60.0);
+const BURST_COUNT_RANGE: (f64, f64) = (1.0, 100.0);
+const BURST_INTERVAL_MS_RANGE: (f64, f64) = (50.0, 10000.0);
+const HTTP_PREVIEW_PORT_RANGE: (f64, f64) = (1024.0, 65535.0);
+const VIDEO_BITRATE_KBPS_RANGE: (f64, f64) = (1.0, 100_000.0);
+const BRIGHTNESS_RANGE: (f64, f64) = (-1.0, 1.0);
+const CONTRAST_RANGE: (f64, f64) = (0.0, 2.0);
+const SATURATION_RANGE: (f64, f64) = (0.0, 2.0);
+const HUE_RANGE: (f64, f64) = (-1.0, 1.0);
+const ZOOM_RANGE: (f64, f64) = (1.0, MAX_ZOOM);
+const TIMESTAMP_FONT_SIZE_RANGE: (f64, f64) = (6.0, 96.0);
+const WATERMARK_OPACITY_RANGE: (f64, f64) = (0.0, 1.0);
+
+// Check if the value is within one of the ranges above
+fn in_range(value: f64, (min, max): (f64, f64)) -> bool {
+    value >= min && value <= max
+}
+
+// Height of the settings dialog's pages, anything taller scrolls
+const SETTINGS_PAGE_MIN_HEIGHT: i32 = 400;
+
+// Delay after the last slider movement until the settings are saved, so that dragging a slider
+// doesn't write the configuration file for every single step
+const SLIDER_SAVE_DELAY_MS: u32 = 500;
+
+// Same as the H.264 encoder's default
+const DEFAULT_RECORD_VIDEO_BITRATE_KBPS: u32 = 2048;
+
+// Deserialize a value from 0 to 100, clamping hand-edited values that are out of range instead of
+// failing to load the whole settings file
+fn deserialize_percentage<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let value = i64::deserialize(deserializer)?;
+    Ok(value.max(0).min(100) as u8)
+}
+
+impl Settings {
+    // Parse settings in the given format, e.g. TOML for the default settings file
+    //
+    // Missing fields, e.g. from older versions, get their default values and unknown fields, e.g.
+    // from newer versions, are ignored
+    pub fn from_format(s: &str, format: serde_any::Format) -> Result<Self, serde_any::Error> {
+        let mut settings = serde_any::from_str::<Settings>(s, format)?;
+        settings.migrate();
+        Ok(settings)
+    }
+
+    // Serialize the settings in the given format
+    pub fn to_format(&self, format: serde_any::Format) -> Result<String, serde_any::Error> {
+        serde_any::to_string_pretty(self, format)
+    }
+
+    // Write the settings to a file for importing them elsewhere
+    //
+    // The format is chosen by the extension, e.g. JSON for ".json", and is TOML like the settings
+    // file for anything unknown
+    pub fn export_to_file(&self, path: &Path) -> Result<(), String> {
+        let format = serde_any::guess_format(path).unwrap_or(serde_any::Format::Toml);
+        self.to_format(format)
+            .map_err(|err| err.to_string())
+            .and_then(|s| fs::write(path, s).map_err(|err| err.to_string()))
+            .map_err(|err| format!("Failed to export settings to {}: {}", path.display(), err))
+    }
+
+    // Read settings that were exported before, possibly on another machine
+    //
+    // Unlike the settings file, invalid values are not silently fixed but fail the import, so that
+    // the current settings are only replaced by something that works here
+    pub fn import_from_file(path: &Path) -> Result<Self, String> {
+        let format = serde_any::guess_format(path).unwrap_or(serde_any::Format::Toml);
+        let mut settings = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|s| serde_any::from_str::<Settings>(&s, format).map_err(|e| e.to_string()))
+            .map_err(|err| format!("Failed to import settings from {}: {}", path.display(), err))?;
+
+        settings
+            .validate()
+            .map_err(|err| format!("Invalid settings in {}: {}", path.display(), err))?;
+        settings.migrate();
+
+        Ok(settings)
+    }
+
+    // URL and latency of the network camera to use instead of a local camera, if configured
+    //
+    // Until a URL is entered the local camera stays in use
+    pub fn get_rtsp_source(&self) -> Option<(&str, u32)> {
+        match self.source_type {
+            SourceType::Device => None,
+            SourceType::Rtsp if self.rtsp_url.is_empty() => None,
+            SourceType::Rtsp => Some((self.rtsp_url.as_str(), self.rtsp_latency_ms)),
+        }
+    }
+
+    // Check that the directories exist and all values are in the ranges the dialog allows
+    fn validate(&self) -> Result<(), String> {
+        for &(name, directory) in &[
+            ("Snapshot", &self.snapshot_directory),
+            ("Recording", &self.record_directory),
+        ] {
+            if !directory.is_dir() {
+                return Err(format!(
+                    "{} directory {} does not exist",
+                    name,
+                    directory.display()
+                ));
+            }
+        }
+
+        if let Some(ref watermark_path) = self.watermark_path {
+            if !watermark_path.is_file() {
+                return Err(format!(
+                    "Watermark image {} does not exist",
+                    watermark_path.display()
+                ));
+            }
+        }
+
+        for &(name, template) in &[
+            ("snapshot", &self.snapshot_filename_template),
+            ("recording", &self.record_filename_template),
+        ] {
+            if let Err(err) = utils::expand_filename_template(template, self.filename_timezone, 1) {
+                return Err(format!("Invalid {} file name template: {}", name, err));
+            }
+        }
+
+        let ranges = [
+            (
+                "timer length",
+                in_range(f64::from(self.timer_length), TIMER_LENGTH_RANGE),
+            ),
+            (
+                "burst count",
+                in_range(f64::from(self.burst_count), BURST_COUNT_RANGE),
+            ),
+            (
+                "burst interval",
+                in_range(f64::from(self.burst_interval_ms), BURST_INTERVAL_MS_RANGE),
+            ),
+            (
+                "HTTP preview port",
+                in_range(f64::from(self.http_preview_port), HTTP_PREVIEW_PORT_RANGE),
+            ),
+            (
+                "video bitrate",
+                in_range(
+                    f64::from(self.record_video_bitrate_kbps),
+                    VIDEO_BITRATE_KBPS_RANGE,
+                ),
+            ),
+            ("brightness", in_range(self.brightness, BRIGHTNESS_RANGE)),
+            ("contrast", in_range(self.contrast, CONTRAST_RANGE)),
+            ("saturation", in_range(self.saturation, SATURATION_RANGE)),
+            ("hue", in_range(self.hue, HUE_RANGE)),
+            ("zoom", in_range(self.zoom, ZOOM_RANGE)),
+            (
+                "timestamp font size",
+                in_range(
+                    f64::from(self.timestamp_font_size),
+                    TIMESTAMP_FONT_SIZE_RANGE,
+                ),
+            ),
+            (
+                "watermark opacity",
+                in_range(self.watermark_opacity, WATERMARK_OPACITY_RANGE),
+            ),
+        ];
+        if let Some(&(name, _)) = ranges.iter().find(|&&(_, valid)| !valid) {
+            return Err(format!("The {} is out of range", name));
+        }
+
+        Ok(())
+    }
+
+    // Convert settings from older versions to the current ones
+    pub fn migrate(&mut self) {
+        if let Some(record_format) = self.record_format.take() {
+            let (codec, container) = record_format.split();
+            self.record_codec = codec;
+            self.record_container = container;
+        }
+
+        // Never use a combination that does not work
+        if !self.record_container.supports(self.record_codec) {
+            self.record_container = Container::Matroska;
+        }
+
+        // A bitrate of 0 can only come from hand-editing and would make the encoder fail
+        if self.record_video_bitrate_kbps == 0 {
+            self.record_video_bitrate_kbps = DEFAULT_RECORD_VIDEO_BITRATE_KBPS;
+        }
+
+        if !in_range(self.zoom, ZOOM_RANGE) {
+            self.zoom = 1.0;
+        }
+
+        if self.timestamp_font_size == 0 {
+            self.timestamp_font_size = DEFAULT_TIMESTAMP_FONT_SIZE;
+        }
+
+        if !in_range(self.watermark_opacity, WATERMARK_OPACITY_RANGE) {
+            self.watermark_opacity = 1.0;
+        }
+
+        if self
+            .custom_pipeline
+            .as_ref()
+            .map(|description| description.trim().is_empty())
+            .unwrap_or(false)
+        {
+            self.custom_pipeline = None;
+        }
+    }
+
+    // Queue/filesink properties to use for recording based on the selected preset
+    pub fn get_record_output_tuning(&self) -> RecordOutputTuning {
+        match self.record_output_preset {
+            RecordOutputPreset::Reliable => RecordOutputTuning::default(),
+            RecordOutputPreset::LowLatency => RecordOutputTuning {
+                sync: false,
+                async_: false,
+                buffer_mode: FileSinkBufferMode::Full,
+                leaky: true,
+            },
+            RecordOutputPreset::Advanced => self.record_output_tuning.clone(),
+        }
+    }
+}
+
+// Our refcounted settings struct for containing all the widgets we have to carry around.
+//
+// This represents our settings dialog.
+#[derive(Clone)]
+struct SettingsDialog(Rc<SettingsDialogInner>);
+
+// Deref into the contained struct to make usage a bit more ergonomic
+impl ops::Deref for SettingsDialog {
+    type Target = SettingsDialogInner;
+
+    fn deref(&self) -> &SettingsDialogInner {
+        &*self.0
+    }
+}
+
+// Weak reference to our settings dialog struct
+//
+// Weak references are important to prevent reference cycles. Reference cycles are cases where
+// struct A references directly or indirectly struct B, and struct B references struct A again
+// while both are using reference counting.
+struct SettingsDialogWeak(Weak<SettingsDialogInner>);
+
+impl SettingsDialogWeak {
+    // Upgrade to a strong reference if it still exists
+    pub fn upgrade(&self) -> Option<SettingsDialog> {
+        self.0.upgrade().map(SettingsDialog)
+    }
+}
+
+struct SettingsDialogInner {
+    snapshot_directory_chooser: gtk::FileChooserButton,
+    snapshot_format: gtk::ComboBoxText,
+    snapshot_jpeg_quality_box: gtk::Box,
+    snapshot_jpeg_quality: gtk::SpinButton,
+    timer_entry: gtk::SpinButton,
+    burst_count: gtk::SpinButton,
+    burst_interval_ms: gtk::SpinButton,
+    video_device: gtk::ComboBoxText,
+    capture_resolution: gtk::ComboBoxText,
+    capture_framerate: gtk::SpinButton,
+    snapshot_source: gtk::ComboBoxText,
+    record_directory_chooser: gtk::FileChooserButton,
+    record_codec: gtk::ComboBoxText,
+    record_container: gtk::ComboBoxText,
+    record_audio_source: gtk::ComboBoxText,
+    h264_profile: gtk::ComboBoxText,
+    encoder_options_box: gtk::Box,
+    x264_preset_box: gtk::Box,
+    record_video_bitrate_kbps: gtk::SpinButton,
+    x264_preset: gtk::ComboBoxText,
+    filename_timezone: gtk::ComboBoxText,
+    exposure_lock: gtk::ComboBoxText,
+    http_preview_enabled: gtk::CheckButton,
+    http_preview_port: gtk::SpinButton,
+    record_output_preset: gtk::ComboBoxText,
+    record_output_advanced: gtk::Box,
+    record_output_sync: gtk::CheckButton,
+    record_output_async: gtk::CheckButton,
+    record_output_leaky: gtk::CheckButton,
+    record_output_buffer_mode: gtk::ComboBoxText,
+    capture_sound: gtk::ComboBoxText,
+    capture_sound_chooser: gtk::FileChooserButton,
+    confirm_quit_while_recording: gtk::CheckButton,
+    record_constant_framerate: gtk::CheckButton,
+    record_format_fallback: gtk::CheckButton,
+    record_min_free_memory: gtk::SpinButton,
+    record_min_free_space: gtk::SpinButton,
+    record_preallocate_size: gtk::SpinButton,
+    record_max_duration_secs: gtk::SpinButton,
+    record_reminder_interval: gtk::SpinButton,
+    disable_preview: gtk::CheckButton,
+    snapshot_on_record_start: gtk::CheckButton,
+    settings_apply_on_close: gtk::CheckButton,
+    record_portrait: gtk::CheckButton,
+    auto_record_on_start: gtk::CheckButton,
+    write_capture_metadata: gtk::CheckButton,
+    snapshot_wait_for_frame: gtk::CheckButton,
+    snapshot_archive: gtk::CheckButton,
+    snapshot_file_collision: gtk::ComboBoxText,
+    record_encrypt: gtk::CheckButton,
+    minimize_preview_while_recording: gtk::CheckButton,
+    close_action: gtk::ComboBoxText,
+    high_contrast_overlay: gtk::CheckButton,
+    notifications_enabled: gtk::CheckButton,
+    prompt_for_location: gtk::CheckButton,
+    snapshot_naming: gtk::ComboBoxText,
+    record_naming: gtk::ComboBoxText,
+    snapshot_filename_template: gtk::Entry,
+    snapshot_filename_preview: gtk::Label,
+    record_filename_template: gtk::Entry,
+    record_filename_preview: gtk::Label,
+    playback_decoder: gtk::ComboBoxText,
+    brightness: gtk::Scale,
+    contrast: gtk::Scale,
+    saturation: gtk::Scale,
+    hue: gtk::Scale,
+    video_flip: gtk::ComboBoxText,
+    zoom: gtk::Scale,
+    burn_timestamp: gtk::CheckButton,
+    timestamp_position: gtk::ComboBoxText,
+    timestamp_font_size: gtk::SpinButton,
+    watermark_enabled: gtk::CheckButton,
+    watermark_chooser: gtk::FileChooserButton,
+    watermark_position: gtk::ComboBoxText,
+    watermark_opacity: gtk::Scale,
+    snapshot_also_png: gtk::CheckButton,
+    custom_pipeline: gtk::Entry,
+    source_type: gtk::ComboBoxText,
+    rtsp_url: gtk::Entry,
+    rtsp_latency_ms: gtk::SpinButton,
+    deinterlace: gtk::CheckButton,
+    http_preview_remote: gtk::CheckButton,
+
+    // Whether changes are only saved when applying them. This is fixed while the dialog is open
+    apply_on_close: bool,
+    // The application that keeps the current settings
+    app: AppWeak,
+    // Working copy of the settings as currently shown in the dialog
+    settings: RefCell<Settings>,
+    // Pending save of the working copy after a slider was moved
+    save_timeout: RefCell<Option<glib::SourceId>>,
+}
+
+impl SettingsDialog {
+    // Downgrade to a weak reference
+    fn downgrade(&self) -> SettingsDialogWeak {
+        SettingsDialogWeak(Rc::downgrade(&self.0))
+    }
+
+    // Called whenever something is changed in the dialog
+    //
+    // Unless the changes should only be applied once the dialog is closed, this directly saves
+    // the configuration file with the new values
+    fn on_changed(&self) {
+        self.update_filename_previews();
+
+        let settings = self.get_settings();
+
+        if !self.apply_on_close {
+            // This saves everything, including whatever a pending slider save would've saved
+            self.cancel_pending_save();
+            self.store_settings(settings.clone());
+        }
+
+        *self.settings.borrow_mut() = settings;
+    }
+
+    // Called whenever a slider is moved
+    //
+    // Sliders emit lots of changes while being dragged, so the working copy is updated right away
+    // but saving the configuration file is delayed until the slider stayed put for a moment
+    fn on_slider_changed(&self) {
+        *self.settings.borrow_mut() = self.get_settings();
+
+        if self.apply_on_close {
+            return;
+        }
+
+        self.cancel_pending_save();
+
+        let settings_dialog_weak = self.downgrade();
+        let timeout_id = gtk::timeout_add(SLIDER_SAVE_DELAY_MS, move || {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak, glib::Continue(false));
+
+            let _ = settings_dialog.save_timeout.borrow_mut().take();
+            settings_dialog.save_settings();
+
+            glib::Continue(false)
+        });
+        *self.save_timeout.borrow_mut() = Some(timeout_id);
+    }
+
+    // Remove the pending slider save, if any, and return whether there was one
+    fn cancel_pending_save(&self) -> bool {
+        match self.save_timeout.borrow_mut().take() {
+            Some(timeout_id) => {
+                glib::source::source_remove(timeout_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Callback for the change notification signals of widgets that only need the settings to be
+    // updated
+    fn on_changed_callback<T: 'static>(&self) -> impl Fn(&T) + 'static {
+        let settings_dialog_weak = self.downgrade();
+        move |_| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog.on_changed();
+        }
+    }
+
+    // Get the template from the entry if it is valid, otherwise the given previous template
+    fn get_filename_template(&self, entry: &gtk::Entry, previous: &str) -> String {
+        let timezone = get_combo_value::<Timezone>(&self.filename_timezone);
+        match entry.get_text() {
+            Some(template) if utils::expand_filename_template(&template, timezone, 1).is_ok() => {
+                template.to_string()
+            }
+            _ => String::from(previous),
+        }
+    }
+
+    // Show what the file names from the templates look like right now, or why they're invalid
+    fn update_filename_previews(&self) {
+        let timezone = get_combo_value::<Timezone>(&self.filename_timezone);
+        for &(entry, label) in &[
+            (
+                &self.snapshot_filename_template,
+                &self.snapshot_filename_preview,
+            ),
+            (
+                &self.record_filename_template,
+                &self.record_filename_preview,
+            ),
+        ] {
+            let template = entry.get_text().map(|t| t.to_string()).unwrap_or_default();
+            let text = match utils::expand_filename_template(&template, timezone, 1) {
+                Ok(name) => format!("e.g. {}", name),
+                Err(err) => format!("Not saved: {}", err),
+            };
+            label.set_text(&text);
+        }
+    }
+
+    // Store the working copy of the settings into the configuration file
+    fn save_settings(&self) {
+        self.store_settings(self.settings.borrow().clone());
+    }
+
+    // Save the settings via the application, so that it knows about them right away
+    fn store_settings(&self, settings: Settings) {
+        match self.app.upgrade() {
+            Some(app) => app.save_settings(settings),
+            None => {
+                utils::save_settings(&settings);
+            }
+        }
+    }
+
+    // Take current settings value from all our widgets
+    fn get_settings(&self) -> Settings {
+        // The resolution is shown as WIDTHxHEIGHT, and the first entry is the camera's default
+        let capture_resolution = self
+            .capture_resolution
+            .get_active_text()
+            .and_then(|text| {
+                let mut parts = text.split('x');
+                match (parts.next()?.parse(), parts.next()?.parse()) {
+                    (Ok(width), Ok(height)) => Some((width, height)),
+                    _ => None,
+                }
+            })
+            .unwrap_or((0, 0));
+
+        // Settings that are changed by the application while the dialog is open, e.g. the grid
+        // from the header bar, are taken from the current settings instead of the ones from when
+        // the dialog was opened
+        let current = self
+            .app
+            .upgrade()
+            .map(|app| app.settings())
+            .unwrap_or_else(utils::load_settings);
+
+        Settings {
+            snapshot_directory: self
+                .snapshot_directory_chooser
+                .get_filename()
+                .unwrap_or_else(utils::get_default_snapshot_directory),
+            snapshot_format: get_combo_value(&self.snapshot_format),
+            snapshot_jpeg_quality: self.snapshot_jpeg_quality.get_value_as_int() as _,
+            timer_length: self.timer_entry.get_value_as_int() as _,
+            burst_count: self.burst_count.get_value_as_int() as _,
+            burst_interval_ms: self.burst_interval_ms.get_value_as_int() as _,
+            // The first entry is the default camera, all others have the camera's id as id
+            video_device: match self.video_device.get_active() {
+                Some(0) | None => None,
+                Some(_) => self.video_device.get_active_id().map(String::from),
+            },
+            capture_width: capture_resolution.0,
+            capture_height: capture_resolution.1,
+            capture_framerate: self.capture_framerate.get_value_as_int() as _,
+            snapshot_source: get_combo_value(&self.snapshot_source),
+            record_directory: self
+                .record_directory_chooser
+                .get_filename()
+                .unwrap_or_else(utils::get_default_record_directory),
+            record_codec: get_combo_value(&self.record_codec),
+            record_container: get_combo_value(&self.record_container),
+            record_format: None,
+            record_audio_source: get_combo_value(&self.record_audio_source),
+            h264_profile: get_combo_value(&self.h264_profile),
+            record_video_bitrate_kbps: self.record_video_bitrate_kbps.get_value_as_int() as u32,
+            x264_preset: get_combo_value(&self.x264_preset),
+            filename_timezone: get_combo_value(&self.filename_timezone),
+            exposure_lock: get_combo_value(&self.exposure_lock),
+            http_preview_enabled: self.http_preview_enabled.get_active(),
+            http_preview_port: self.http_preview_port.get_value_as_int() as _,
+            http_preview_remote: self.http_preview_remote.get_active(),
+            record_output_preset: get_combo_value(&self.record_output_preset),
+            record_output_tuning: RecordOutputTuning {
+                sync: self.record_output_sync.get_active(),
+                async_: self.record_output_async.get_active(),
+                buffer_mode: get_combo_value(&self.record_output_buffer_mode),
+                leaky: self.record_output_leaky.get_active(),
+            },
+            capture_sound: match self.capture_sound.get_active() {
+                Some(0) => CaptureSound::None,
+                Some(1) => CaptureSound::Shutter,
+                Some(2) => CaptureSound::Bell,
+                Some(3) => CaptureSound::Click,
+                // Without a file selected yet we stay with the default sound
+                Some(4) => self
+                    .capture_sound_chooser
+                    .get_filename()
+                    .map(CaptureSound::Custom)
+                    .unwrap_or_default(),
+                _ => CaptureSound::default(),
+            },
+            confirm_quit_while_recording: self.confirm_quit_while_recording.get_active(),
+            record_constant_framerate: self.record_constant_framerate.get_active(),
+            record_format_fallback: self.record_format_fallback.get_active(),
+            record_min_free_memory: self.record_min_free_memory.get_value_as_int() as _,
+            record_min_free_space: self.record_min_free_space.get_value_as_int() as _,
+            record_preallocate_size: self.record_preallocate_size.get_value_as_int() as _,
+            record_max_duration_secs: self.record_max_duration_secs.get_value_as_int() as _,
+            record_reminder_interval: self.record_reminder_interval.get_value_as_int() as _,
+            disable_preview: self.disable_preview.get_active(),
+            snapshot_on_record_start: self.snapshot_on_record_start.get_active(),
+            settings_apply_on_close: self.settings_apply_on_close.get_active(),
+            record_portrait: self.record_portrait.get_active(),
+            auto_record_on_start: self.auto_record_on_start.get_active(),
+            write_capture_metadata: self.write_capture_metadata.get_active(),
+            snapshot_wait_for_frame: self.snapshot_wait_for_frame.get_active(),
+            snapshot_archive: self.snapshot_archive.get_active(),
+            snapshot_file_collision: get_combo_value(&self.snapshot_file_collision),
+            record_encrypt: self.record_encrypt.get_active(),
+            minimize_preview_while_recording: self.minimize_preview_while_recording.get_active(),
+            close_action: get_combo_value(&self.close_action),
+            high_contrast_overlay: self.high_contrast_overlay.get_active(),
+            notifications_enabled: self.notifications_enabled.get_active(),
+            prompt_for_location: self.prompt_for_location.get_active(),
+            snapshot_naming: get_combo_value(&self.snapshot_naming),
+            record_naming: get_combo_value(&self.record_naming),
+            // Invalid templates are not stored, the last valid one is kept instead
+            snapshot_filename_template: self.get_filename_template(
+                &self.snapshot_filename_template,
+                &self.settings.borrow().snapshot_filename_template,
+            ),
+            record_filename_template: self.get_filename_template(
+                &self.record_filename_template,
+                &self.settings.borrow().record_filename_template,
+            ),
+            playback_decoder: get_combo_value(&self.playback_decoder),
+            brightness: self.brightness.get_value(),
+            contrast: self.contrast.get_value(),
+            saturation: self.saturation.get_value(),
+            hue: self.hue.get_value(),
+            video_flip: get_combo_value(&self.video_flip),
+            zoom: self.zoom.get_value(),
+            burn_timestamp: self.burn_timestamp.get_active(),
+            timestamp_position: get_combo_value(&self.timestamp_position),
+            timestamp_font_size: self.timestamp_font_size.get_value_as_int() as u32,
+            // Without an image selected yet there is no watermark
+            watermark_path: if self.watermark_enabled.get_active() {
+                self.watermark_chooser.get_filename()
+            } else {
+                None
+            },
+            watermark_position: get_combo_value(&self.watermark_position),
+            watermark_opacity: self.watermark_opacity.get_value(),
+            snapshot_also_png: self.snapshot_also_png.get_active(),
+            custom_pipeline: self
+                .custom_pipeline
+                .get_text()
+                .map(|description| description.trim().to_string())
+                .filter(|description| !description.is_empty()),
+            source_type: get_combo_value(&self.source_type),
+            rtsp_url: self
+                .rtsp_url
+                .get_text()
+                .map(|url| url.trim().to_string())
+                .unwrap_or_default(),
+            rtsp_latency_ms: self.rtsp_latency_ms.get_value_as_int() as u32,
+            deinterlace: self.deinterlace.get_active(),
+            // The window geometry is not shown in the dialog and only stored by the application
+            window_width: current.window_width,
+            window_height: current.window_height,
+            window_position: current.window_position,
+            window_maximized: current.window_maximized,
+            show_grid: current.show_grid,
+            show_stats: current.show_stats,
+        }
+    }
+}
+
+// Construct the settings dialog and ensure that the settings file exists and is loaded
+//
+// The app is used for hiding settings that are not supported by the current camera
+pub fn show_settings_dialog(application: &gtk::Application, app: &App) {
+    let s = utils::get_settings_file_path();
+
+    if !s.exists() {
+        if let Some(parent_dir) = s.parent() {
+            if !parent_dir.exists() {
+                if let Err(e) = create_dir_all(parent_dir) {
+                    utils::show_error_dialog(
+                        false,
+                        format!(
+                            "Error while trying to build settings snapshot_directory '{}': {}",
+                            parent_dir.display(),
+                            e
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+        }
+    }
+
+    let settings = app.settings();
+
+    // Create an empty dialog with close button, or with cancel and apply buttons if changes are
+    // only saved once applied
+    let apply_on_close = settings.settings_apply_on_close;
+    let dialog = if apply_on_close {
+        gtk::Dialog::new_with_buttons(
+            Some("WebCam Viewer settings"),
+            application.get_active_window().as_ref(),
+            gtk::DialogFlags::MODAL,
+            &[
+                ("Cancel", gtk::ResponseType::Cancel),
+                ("Apply", gtk::ResponseType::Apply),
+            ],
+        )
+    } else {
+        gtk::Dialog::new_with_buttons(
+            Some("WebCam Viewer settings"),
+            application.get_active_window().as_ref(),
+            gtk::DialogFlags::MODAL,
+            &[("Close", gtk::ResponseType::Close)],
+        )
+    };
+
+    // All the UI widgets are going to be stored in grids on notebook pages, grouped by topic
+    let notebook = gtk::Notebook::new();
+    let camera_page = add_settings_page(&notebook, "Camera");
+    let image_page = add_settings_page(&notebook, "Image");
+    let snapshots_page = add_settings_page(&notebook, "Snapshots");
+    let recording_page = add_settings_page(&notebook, "Recording");
+    let files_page = add_settings_page(&notebook, "Files");
+    let general_page = add_settings_page(&notebook, "General");
+
+    // File chooser for selecting the snapshot directory plus the label
+    // next to it
+    let snapshot_directory_label = gtk::Label::new(Some("Snapshot directory"));
+    let snapshot_directory_chooser = gtk::FileChooserButton::new(
+        "Pick a directory to save snapshots",
+        gtk::FileChooserAction::SelectFolder,
+    );
+
+    snapshot_directory_label.set_halign(gtk::Align::Start);
+    snapshot_directory_chooser.set_filename(settings.snapshot_directory);
+
+    files_page.attach(&snapshot_directory_label, 0, 0, 1, 1);
+    files_page.attach(&snapshot_directory_chooser, 1, 0, 3, 1);
+
+    // Snapshot format combobox plus the label next to it
+    let format_label = gtk::Label::new(Some("Snapshot format"));
+    let snapshot_format = gtk::ComboBoxText::new();
+
+    format_label.set_halign(gtk::Align::Start);
+
+    // We'll add our 3 support snapshot formats as text here and select
+    // the configured one
+    snapshot_format.append_text("JPEG");
+    snapshot_format.append_text("PNG");
+    snapshot_format.append_text("WebP");
+    snapshot_format.set_active(match settings.snapshot_format {
+        SnapshotFormat::JPEG => Some(0),
+        SnapshotFormat::PNG => Some(1),
+        SnapshotFormat::WEBP => Some(2),
+    });
+    snapshot_format.set_hexpand(true);
+
+    // JPEG quality spin button plus the label next to it, only shown for JPEG
+    let snapshot_jpeg_quality_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let snapshot_jpeg_quality_label = gtk::Label::new(Some("Quality"));
+    // We allow 0 to 100, in steps of 1
+    let snapshot_jpeg_quality = gtk::SpinButton::new_with_range(0., 100., 1.);
+
+    snapshot_jpeg_quality.set_value(f64::from(settings.snapshot_jpeg_quality));
+
+    snapshot_jpeg_quality_box.pack_start(&snapshot_jpeg_quality_label, false, false, 0);
+    snapshot_jpeg_quality_box.pack_start(&snapshot_jpeg_quality, true, true, 0);
+    snapshot_jpeg_quality_box.show_all();
+    snapshot_jpeg_quality_box.set_no_show_all(true);
+    snapshot_jpeg_quality_box.set_visible(settings.snapshot_format == SnapshotFormat::JPEG);
+
+    snapshots_page.attach(&format_label, 0, 0, 1, 1);
+    snapshots_page.attach(&snapshot_format, 1, 0, 1, 1);
+    snapshots_page.attach(&snapshot_jpeg_quality_box, 2, 0, 2, 1);
+
+    // Snapshot timer length spin button plus the label next to it
+    let timer_label = gtk::Label::new(Some("Timer length (in seconds)"));
+    // We allow 0 seconds up to the maximum, in 1 second steps. The countdown label has room for
+    // up to 3 digits
+    let timer_entry =
+        gtk::SpinButton::new_with_range(TIMER_LENGTH_RANGE.0, TIMER_LENGTH_RANGE.1, 1.);
+
+    timer_label.set_halign(gtk::Align::Start);
+    timer_label.set_hexpand(true);
+
+    timer_entry.set_value(settings.timer_length as f64);
+
+    snapshots_page.attach(&timer_label, 0, 4, 1, 1);
+    snapshots_page.attach(&timer_entry, 1, 4, 3, 1);
+
+    // File chooser for selecting the record directory plus the label
+    // next to it
+    let record_directory_label = gtk::Label::new(Some("Record directory"));
+    let record_directory_chooser = gtk::FileChooserButton::new(
+        "Pick a directory to save records",
+        gtk::FileChooserAction::SelectFolder,
+    );
+
+    record_directory_label.set_halign(gtk::Align::Start);
+    record_directory_chooser.set_filename(settings.record_directory);
+
+    files_page.attach(&record_directory_label, 0, 1, 1, 1);
+    files_page.attach(&record_directory_chooser, 1, 1, 3, 1);
+
+    // Record codec and container comboboxes plus the label next to them. Only the containers
+    // that support the selected codec are offered
+    let format_label = gtk::Label::new(Some("Record format"));
+    let record_codec = gtk::ComboBoxText::new();
+    let record_container = gtk::ComboBoxText::new();
+
+    format_label.set_halign(gtk::Align::Start);
+
+    record_codec.append_text("H.264");
+    record_codec.append_text("H.265");
+    record_codec.append_text("VP8");
+    record_codec.append_text("VP9");
+    record_codec.append_text("AV1");
+    record_codec.set_active(match settings.record_codec {
+        VideoCodec::H264 => Some(0),
+        VideoCodec::H265 => Some(1),
+        VideoCodec::Vp8 => Some(2),
+        VideoCodec::Vp9 => Some(3),
+        VideoCodec::Av1 => Some(4),
+    });
+    record_codec.set_hexpand(true);
+
+    update_record_containers(
+        &record_container,
+        settings.record_codec,
+        settings.record_container,
+    );
+    record_container.set_hexpand(true);
+
+    recording_page.attach(&format_label, 0, 0, 1, 1);
+    recording_page.attach(&record_codec, 1, 0, 1, 1);
+    recording_page.attach(&record_container, 2, 0, 2, 1);
+
+    // Exposure lock combobox plus the label next to it
+    let exposure_lock_label = gtk::Label::new(Some("Exposure lock"));
+    let exposure_lock = gtk::ComboBoxText::new();
+
+    exposure_lock_label.set_halign(gtk::Align::Start);
+
+    exposure_lock.append_text("Off");
+    exposure_lock.append_text("While recording");
+    exposure_lock.append_text("Always");
+    exposure_lock.set_active(match settings.exposure_lock {
+        ExposureLock::Off => Some(0),
+        ExposureLock::WhileRecording => Some(1),
+        ExposureLock::Always => Some(2),
+    });
+    exposure_lock.set_hexpand(true);
+
+    // Only cameras with exposure controls can lock the exposure, so don't even show the option
+    // otherwise. The widget still exists and keeps the configured value when saving
+    if app.supports_exposure_lock() {
+        camera_page.attach(&exposure_lock_label, 0, 8, 1, 1);
+        camera_page.attach(&exposure_lock, 1, 8, 3, 1);
+    }
+
+    // Check button for enabling the HTTP preview plus the port spin button next to it
+    let http_preview_enabled = gtk::CheckButton::new_with_label("Serve preview over HTTP on port");
+    // Only allow unprivileged ports
+    let http_preview_port =
+        gtk::SpinButton::new_with_range(HTTP_PREVIEW_PORT_RANGE.0, HTTP_PREVIEW_PORT_RANGE.1, 1.);
+
+    http_preview_enabled.set_active(settings.http_preview_enabled);
+    http_preview_port.set_value(f64::from(settings.http_preview_port));
+    http_preview_port.set_sensitive(settings.http_preview_enabled);
+
+    general_page.attach(&http_preview_enabled, 0, 5, 1, 1);
+    general_page.attach(&http_preview_port, 1, 5, 3, 1);
+
+    // Record output preset combobox plus the label next to it
+    let record_output_label = gtk::Label::new(Some("Recording output"));
+    let record_output_preset = gtk::ComboBoxText::new();
+
+    record_output_label.set_halign(gtk::Align::Start);
+
+    record_output_preset.append_text("Reliable");
+    record_output_preset.append_text("Low-latency");
+    record_output_preset.append_text("Advanced");
+    record_output_preset.set_active(match settings.record_output_preset {
+        RecordOutputPreset::Reliable => Some(0),
+        RecordOutputPreset::LowLatency => Some(1),
+        RecordOutputPreset::Advanced => Some(2),
+    });
+    record_output_preset.set_hexpand(true);
+
+    recording_page.attach(&record_output_label, 0, 12, 1, 1);
+    recording_page.attach(&record_output_preset, 1, 12, 3, 1);
+
+    // The raw queue/filesink properties, only shown for the advanced preset
+    let record_output_advanced = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let record_output_sync = gtk::CheckButton::new_with_label("Sync");
+    let record_output_async = gtk::CheckButton::new_with_label("Async");
+    let record_output_leaky = gtk::CheckButton::new_with_label("Drop frames");
+    let record_output_buffer_mode = gtk::ComboBoxText::new();
+
+    record_output_sync.set_active(settings.record_output_tuning.sync);
+    record_output_async.set_active(settings.record_output_tuning.async_);
+    record_output_leaky.set_active(settings.record_output_tuning.leaky);
+
+    record_output_buffer_mode.append_text("Default");
+    record_output_buffer_mode.append_text("Full");
+    record_output_buffer_mode.append_text("Line");
+    record_output_buffer_mode.append_text("Unbuffered");
+    record_output_buffer_mode.set_active(match settings.record_output_tuning.buffer_mode {
+        FileSinkBufferMode::Default => Some(0),
+        FileSinkBufferMode::Full => Some(1),
+        FileSinkBufferMode::Line => Some(2),
+        FileSinkBufferMode::Unbuffered => Some(3),
+    });
+
+    record_output_advanced.pack_start(&record_output_sync, false, false, 0);
+    record_output_advanced.pack_start(&record_output_async, false, false, 0);
+    record_output_advanced.pack_start(&record_output_leaky, false, false, 0);
+    record_output_advanced.pack_start(&record_output_buffer_mode, true, true, 0);
+
+    // Show the children already but don't let the dialog's show_all() show the box itself. We
+    // show and hide it depending on the selected preset
+    record_output_advanced.show_all();
+    record_output_advanced.set_no_show_all(true);
+    record_output_advanced
+        .set_visible(settings.record_output_preset == RecordOutputPreset::Advanced);
+
+    recording_page.attach(&record_output_advanced, 1, 13, 3, 1);
+
+    // Capture sound combobox plus the label next to it, and a file chooser for custom sounds
+    // that is only shown if "Custom…" is selected
+    let capture_sound_label = gtk::Label::new(Some("Capture sound"));
+    let capture_sound = gtk::ComboBoxText::new();
+    let capture_sound_chooser =
+        gtk::FileChooserButton::new("Pick a sound file", gtk::FileChooserAction::Open);
+
+    capture_sound_label.set_halign(gtk::Align::Start);
+
+    capture_sound.append_text("None");
+    capture_sound.append_text("Shutter");
+    capture_sound.append_text("Bell");
+    capture_sound.append_text("Click");
+    capture_sound.append_text("Custom…");
+    capture_sound.set_active(match settings.capture_sound {
+        CaptureSound::None => Some(0),
+        CaptureSound::Shutter => Some(1),
+        CaptureSound::Bell => Some(2),
+        CaptureSound::Click => Some(3),
+        CaptureSound::Custom(_) => Some(4),
+    });
+    capture_sound.set_hexpand(true);
+
+    let audio_filter = gtk::FileFilter::new();
+    audio_filter.add_mime_type("audio/*");
+    capture_sound_chooser.set_filter(&audio_filter);
+    if let CaptureSound::Custom(ref path) = settings.capture_sound {
+        capture_sound_chooser.set_filename(path);
+    }
+    capture_sound_chooser.set_no_show_all(true);
+    capture_sound_chooser.set_visible(capture_sound.get_active() == Some(4));
+
+    snapshots_page.attach(&capture_sound_label, 0, 8, 1, 1);
+    snapshots_page.attach(&capture_sound, 1, 8, 1, 1);
+    snapshots_page.attach(&capture_sound_chooser, 2, 8, 2, 1);
+
+    // Check button for asking before quitting during a recording
+    let confirm_quit_while_recording =
+        gtk::CheckButton::new_with_label("Ask before quitting while recording");
+    confirm_quit_while_recording.set_active(settings.confirm_quit_while_recording);
+
+    general_page.attach(&confirm_quit_while_recording, 0, 1, 4, 1);
+
+    // Check button for recording with constant frame rate
+    let record_constant_framerate =
+        gtk::CheckButton::new_with_label("Record with constant frame rate");
+    record_constant_framerate.set_active(settings.record_constant_framerate);
+
+    recording_page.attach(&record_constant_framerate, 0, 4, 4, 1);
+
+    // Check button for continuing failed recordings with a fallback format
+    let record_format_fallback =
+        gtk::CheckButton::new_with_label("Continue failed recordings as VP8/WebM");
+    record_format_fallback.set_active(settings.record_format_fallback);
+
+    recording_page.attach(&record_format_fallback, 0, 6, 4, 1);
+
+    // Check button for disabling the preview
+    let disable_preview = gtk::CheckButton::new_with_label("Disable preview to save resources");
+    disable_preview.set_active(settings.disable_preview);
+
+    camera_page.attach(&disable_preview, 0, 7, 4, 1);
+
+    // H.264 profile combobox plus the label next to it
+    let h264_profile_label = gtk::Label::new(Some("H.264 profile"));
+    let h264_profile = gtk::ComboBoxText::new();
+
+    h264_profile_label.set_halign(gtk::Align::Start);
+
+    h264_profile.append_text("Baseline");
+    h264_profile.append_text("Main");
+    h264_profile.append_text("High");
+    h264_profile.set_active(match settings.h264_profile {
+        H264Profile::Baseline => Some(0),
+        H264Profile::Main => Some(1),
+        H264Profile::High => Some(2),
+    });
+    h264_profile.set_hexpand(true);
+
+    recording_page.attach(&h264_profile_label, 0, 2, 1, 1);
+    recording_page.attach(&h264_profile, 1, 2, 3, 1);
+
+    // Check button for taking a poster frame snapshot when recording
+    let snapshot_on_record_start =
+        gtk::CheckButton::new_with_label("Take a snapshot when starting a recording");
+    snapshot_on_record_start.set_active(settings.snapshot_on_record_start);
+
+    recording_page.attach(&snapshot_on_record_start, 0, 15, 4, 1);
+
+    // Minimum free memory spin button plus the label next to it
+    let record_min_free_memory_label = gtk::Label::new(Some("Minimum free memory (MiB)"));
+    let record_min_free_memory = gtk::SpinButton::new_with_range(0., 4096., 16.);
+
+    record_min_free_memory_label.set_halign(gtk::Align::Start);
+    record_min_free_memory.set_value(f64::from(settings.record_min_free_memory));
+    record_min_free_memory.set_hexpand(true);
+
+    recording_page.attach(&record_min_free_memory_label, 0, 10, 1, 1);
+    recording_page.attach(&record_min_free_memory, 1, 10, 3, 1);
+
+    // Pre-allocation size spin button plus the label next to it
+    let record_preallocate_size_label = gtk::Label::new(Some("Pre-allocate recordings (MiB)"));
+    let record_preallocate_size = gtk::SpinButton::new_with_range(0., 65536., 256.);
+
+    record_preallocate_size_label.set_halign(gtk::Align::Start);
+    record_preallocate_size.set_value(f64::from(settings.record_preallocate_size));
+    record_preallocate_size.set_hexpand(true);
+
+    recording_page.attach(&record_preallocate_size_label, 0, 11, 1, 1);
+    recording_page.attach(&record_preallocate_size, 1, 11, 3, 1);
+
+    // Check button for buffering changes in this dialog until they are applied
+    let settings_apply_on_close =
+        gtk::CheckButton::new_with_label("Apply settings only when closing this dialog");
+    settings_apply_on_close.set_active(settings.settings_apply_on_close);
+
+    general_page.attach(&settings_apply_on_close, 0, 0, 4, 1);
+
+    // Check button for recording in portrait orientation
+    let record_portrait = gtk::CheckButton::new_with_label("Record in portrait orientation");
+    record_portrait.set_active(settings.record_portrait);
+
+    recording_page.attach(&record_portrait, 0, 5, 4, 1);
+
+    // Check button for recording right after launching
+    let auto_record_on_start = gtk::CheckButton::new_with_label("Start recording on launch");
+    auto_record_on_start.set_active(settings.auto_record_on_start);
+
+    recording_page.attach(&auto_record_on_start, 0, 16, 4, 1);
+
+    // Filename timezone combobox plus the label next to it
+    let filename_timezone_label = gtk::Label::new(Some("Filename timezone"));
+    let filename_timezone = gtk::ComboBoxText::new();
+
+    filename_timezone_label.set_halign(gtk::Align::Start);
+
+    filename_timezone.append_text("Local");
+    filename_timezone.append_text("UTC");
+    filename_timezone.set_active(match settings.filename_timezone {
+        Timezone::Local => Some(0),
+        Timezone::Utc => Some(1),
+    });
+    filename_timezone.set_hexpand(true);
+
+    files_page.attach(&filename_timezone_label, 0, 8, 1, 1);
+    files_page.attach(&filename_timezone, 1, 8, 3, 1);
+
+    // Check button for writing metadata sidecar files
+    let write_capture_metadata =
+        gtk::CheckButton::new_with_label("Write metadata files next to captures");
+    write_capture_metadata.set_active(settings.write_capture_metadata);
+
+    files_page.attach(&write_capture_metadata, 0, 9, 4, 1);
+
+    // Check button for waiting for a clean frame for snapshots
+    let snapshot_wait_for_frame =
+        gtk::CheckButton::new_with_label("Take snapshots from the next clean frame");
+    snapshot_wait_for_frame.set_active(settings.snapshot_wait_for_frame);
+
+    snapshots_page.attach(&snapshot_wait_for_frame, 0, 3, 4, 1);
+
+    // Recording reminder spin button plus the label next to it
+    let record_reminder_interval_label = gtk::Label::new(Some("Recording reminder (minutes)"));
+    let record_reminder_interval = gtk::SpinButton::new_with_range(0., 240., 5.);
+
+    record_reminder_interval_label.set_halign(gtk::Align::Start);
+    record_reminder_interval.set_value(f64::from(settings.record_reminder_interval));
+    record_reminder_interval.set_hexpand(true);
+
+    recording_page.attach(&record_reminder_interval_label, 0, 14, 1, 1);
+    recording_page.attach(&record_reminder_interval, 1, 14, 3, 1);
+
+    // Snapshot source combobox plus the label next to it. This only takes effect after a reset
+    let snapshot_source_label = gtk::Label::new(Some("Snapshot source"));
+    let snapshot_source = gtk::ComboBoxText::new();
+
+    snapshot_source_label.set_halign(gtk::Align::Start);
+
+    snapshot_source.append_text("Preview");
+    snapshot_source.append_text("Full resolution");
+    snapshot_source.set_active(match settings.snapshot_source {
+        SnapshotSource::Preview => Some(0),
+        SnapshotSource::FullResolution => Some(1),
+    });
+    snapshot_source.set_hexpand(true);
+
+    snapshots_page.attach(&snapshot_source_label, 0, 2, 1, 1);
+    snapshots_page.attach(&snapshot_source, 1, 2, 3, 1);
+
+    // Audio source combobox plus the label next to it
+    let record_audio_source_label = gtk::Label::new(Some("Recording audio"));
+    let record_audio_source = gtk::ComboBoxText::new();
+
+    record_audio_source_label.set_halign(gtk::Align::Start);
+
+    record_audio_source.append_text("None");
+    record_audio_source.append_text("Microphone");
+    record_audio_source.append_text("System audio");
+    record_audio_source.set_active(match settings.record_audio_source {
+        AudioSource::None => Some(0),
+        AudioSource::Microphone => Some(1),
+        AudioSource::SystemAudio => Some(2),
+    });
+    record_audio_source.set_hexpand(true);
+
+    recording_page.attach(&record_audio_source_label, 0, 3, 1, 1);
+    recording_page.attach(&record_audio_source, 1, 3, 3, 1);
+
+    // Check button for collecting burst and interval snapshots in a ZIP archive
+    let snapshot_archive = gtk::CheckButton::new_with_label("Collect bursts in a ZIP archive");
+    snapshot_archive.set_active(settings.snapshot_archive);
+
+    snapshots_page.attach(&snapshot_archive, 0, 7, 4, 1);
+
+    // Check button for encrypting recordings
+    let record_encrypt = gtk::CheckButton::new_with_label("Encrypt recordings");
+    record_encrypt.set_active(settings.record_encrypt);
+
+    recording_page.attach(&record_encrypt, 0, 7, 4, 1);
+
+    // Check button for minimizing the preview while recording
+    let minimize_preview_while_recording =
+        gtk::CheckButton::new_with_label("Minimize preview while recording");
+    minimize_preview_while_recording.set_active(settings.minimize_preview_while_recording);
+
+    recording_page.attach(&minimize_preview_while_recording, 0, 17, 4, 1);
+
+    // Close action combobox plus the label next to it
+    let close_action_label = gtk::Label::new(Some("When closing the window"));
+    let close_action = gtk::ComboBoxText::new();
+
+    close_action_label.set_halign(gtk::Align::Start);
+
+    close_action.append_text("Quit");
+    close_action.append_text("Minimize");
+    close_action.append_text("Keep recording in background");
+    close_action.set_active(match settings.close_action {
+        CloseAction::Quit => Some(0),
+        CloseAction::Minimize => Some(1),
+        CloseAction::Background => Some(2),
+    });
+    close_action.set_hexpand(true);
+
+    general_page.attach(&close_action_label, 0, 2, 1, 1);
+    general_page.attach(&close_action, 1, 2, 3, 1);
+
+    // Check button for high contrast overlay
+    let high_contrast_overlay = gtk::CheckButton::new_with_label("High contrast overlay");
+    high_contrast_overlay.set_active(settings.high_contrast_overlay);
+
+    image_page.attach(&high_contrast_overlay, 0, 12, 4, 1);
+
+    // Naming comboboxes for snapshots and recordings plus the labels next to them
+    let snapshot_naming_label = gtk::Label::new(Some("Snapshot names"));
+    let snapshot_naming = gtk::ComboBoxText::new();
+    let record_naming_label = gtk::Label::new(Some("Recording names"));
+    let record_naming = gtk::ComboBoxText::new();
+
+    for &(ref label, ref combo, mode) in &[
+        (
+            &snapshot_naming_label,
+            &snapshot_naming,
+            settings.snapshot_naming,
+        ),
+        (&record_naming_label, &record_naming, settings.record_naming),
+    ] {
+        label.set_halign(gtk::Align::Start);
+
+        combo.append_text("Timestamp");
+        combo.append_text("Sequential");
+        combo.set_active(match mode {
+            NamingMode::Timestamp => Some(0),
+            NamingMode::Sequential => Some(1),
+        });
+        combo.set_hexpand(true);
+    }
+
+    files_page.attach(&snapshot_naming_label, 0, 2, 1, 1);
+    files_page.attach(&snapshot_naming, 1, 2, 3, 1);
+    files_page.attach(&record_naming_label, 0, 5, 1, 1);
+    files_page.attach(&record_naming, 1, 5, 3, 1);
+
+    // Playback decoder combobox plus the label next to it
+    let playback_decoder_label = gtk::Label::new(Some("Playback decoding"));
+    let playback_decoder = gtk::ComboBoxText::new();
+
+    playback_decoder_label.set_halign(gtk::Align::Start);
+
+    playback_decoder.append_text("Automatic");
+    playback_decoder.append_text("Software");
+    playback_decoder.append_text("Hardware");
+    playback_decoder.set_active(match settings.playback_decoder {
+        DecoderPreference::Automatic => Some(0),
+        DecoderPreference::Software => Some(1),
+        DecoderPreference::Hardware => Some(2),
+    });
+    playback_decoder.set_hexpand(true);
+
+    general_page.attach(&playback_decoder_label, 0, 4, 1, 1);
+    general_page.attach(&playback_decoder, 1, 4, 3, 1);
+
+    // Camera combobox plus the label next to it
+    let video_device_label = gtk::Label::new(Some("Camera"));
+    let video_device = gtk::ComboBoxText::new();
+
+    video_device_label.set_halign(gtk::Align::Start);
+
+    // The configured camera is also listed if it is not connected right now, so that the
+    // setting is not lost just by opening the dialog
+    let mut video_devices = Pipeline::list_video_devices();
+    let configured_position = settings.video_device.as_ref().map(|configured| {
+        // Also match by name for settings from older versions
+        match video_devices
+            .iter()
+            .position(|(id, name)| id == configured || name == configured)
+        {
+            Some(pos) => pos,
+            None => {
+                video_devices.push((configured.clone(), configured.clone()));
+                video_devices.len() - 1
+            }
+        }
+    });
+
+    video_device.append(None, "Default");
+    for (id, name) in &video_devices {
+        video_device.append(Some(id.as_str()), name);
+    }
+    video_device.set_active(Some(
+        configured_position.map(|pos| pos as u32 + 1).unwrap_or(0),
+    ));
+    video_device.set_hexpand(true);
+
+    camera_page.attach(&video_device_label, 0, 0, 1, 1);
+    camera_page.attach(&video_device, 1, 0, 3, 1);
+
+    // Capture resolution combobox plus the label next to it
+    let capture_resolution_label = gtk::Label::new(Some("Capture resolution"));
+    let capture_resolution = gtk::ComboBoxText::new();
+
+    capture_resolution_label.set_halign(gtk::Align::Start);
+
+    // A resolution that is not one of the common ones can only come from the settings file, keep
+    // it selectable nonetheless
+    let mut resolutions = vec![(640, 480), (1280, 720), (1920, 1080)];
+    let resolution = (settings.capture_width, settings.capture_height);
+    if resolution.0 != 0 && resolution.1 != 0 && !resolutions.contains(&resolution) {
+        resolutions.push(resolution);
+    }
+
+    capture_resolution.append_text("Automatic");
+    for &(width, height) in &resolutions {
+        capture_resolution.append_text(&format!("{}x{}", width, height));
+    }
+    capture_resolution.set_active(
+        resolutions
+            .iter()
+            .position(|r| *r == resolution)
+            .map(|pos| pos as u32 + 1)
+            .or(Some(0)),
+    );
+    capture_resolution.set_hexpand(true);
+
+    camera_page.attach(&capture_resolution_label, 0, 4, 1, 1);
+    camera_page.attach(&capture_resolution, 1, 4, 3, 1);
+
+    // Capture framerate spin button plus the label next to it
+    let capture_framerate_label = gtk::Label::new(Some("Capture framerate (0 for automatic)"));
+    // We allow up to 120 frames per second, in 1 frame per second steps
+    let capture_framerate = gtk::SpinButton::new_with_range(0., 120., 1.);
+
+    capture_framerate_label.set_halign(gtk::Align::Start);
+
+    capture_framerate.set_value(settings.capture_framerate as f64);
+
+    camera_page.attach(&capture_framerate_label, 0, 5, 1, 1);
+    camera_page.attach(&capture_framerate, 1, 5, 3, 1);
+
+    // Recording split duration spin button plus the label next to it
+    let record_max_duration_secs_label =
+        gtk::Label::new(Some("Split recordings after (in seconds, 0 for never)"));
+    // We allow up to 24 hours, in 1 minute steps
+    let record_max_duration_secs = gtk::SpinButton::new_with_range(0., 86400., 60.);
+
+    record_max_duration_secs_label.set_halign(gtk::Align::Start);
+    record_max_duration_secs.set_value(f64::from(settings.record_max_duration_secs));
+    record_max_duration_secs.set_hexpand(true);
+    record_max_duration_secs.set_tooltip_text(Some(
+        "Each part is saved as a separate file, numbered like \"Recording …_00000.mp4\", \"Recording …_00001.mp4\" and so on",
+    ));
+
+    recording_page.attach(&record_max_duration_secs_label, 0, 8, 1, 1);
+    recording_page.attach(&record_max_duration_secs, 1, 8, 3, 1);
+
+    // File name template entries for snapshots and recordings plus the labels next to them, and
+    // a preview of the resulting file names below each
+    let snapshot_filename_template_label = gtk::Label::new(Some("Snapshot name template"));
+    let snapshot_filename_template = gtk::Entry::new();
+    let snapshot_filename_preview = gtk::Label::new(None);
+    let record_filename_template_label = gtk::Label::new(Some("Recording name template"));
+    let record_filename_template = gtk::Entry::new();
+    let record_filename_preview = gtk::Label::new(None);
+
+    for &(ref label, ref entry, ref preview, template) in &[
+        (
+            &snapshot_filename_template_label,
+            &snapshot_filename_template,
+            &snapshot_filename_preview,
+            &settings.snapshot_filename_template,
+        ),
+        (
+            &record_filename_template_label,
+            &record_filename_template,
+            &record_filename_preview,
+            &settings.record_filename_template,
+        ),
+    ] {
+        label.set_halign(gtk::Align::Start);
+        preview.set_halign(gtk::Align::Start);
+
+        entry.set_text(template);
+        entry.set_hexpand(true);
+        entry.set_tooltip_text(Some(
+            "Date and time fields like %Y-%m-%d %H-%M-%S are replaced by the current time, {n} by a counter. Only used for timestamp names",
+        ));
+    }
+
+    files_page.attach(&snapshot_filename_template_label, 0, 3, 1, 1);
+    files_page.attach(&snapshot_filename_template, 1, 3, 3, 1);
+    files_page.attach(&snapshot_filename_preview, 1, 4, 3, 1);
+    files_page.attach(&record_filename_template_label, 0, 6, 1, 1);
+    files_page.attach(&record_filename_template, 1, 6, 3, 1);
+    files_page.attach(&record_filename_preview, 1, 7, 3, 1);
+
+    // Minimum free disk space spin button plus the label next to it
+    let record_min_free_space_label = gtk::Label::new(Some("Minimum free disk space (MiB)"));
+    let record_min_free_space = gtk::SpinButton::new_with_range(0., 65536., 100.);
+
+    record_min_free_space_label.set_halign(gtk::Align::Start);
+    record_min_free_space.set_value(f64::from(settings.record_min_free_space));
+    record_min_free_space.set_hexpand(true);
+
+    recording_page.attach(&record_min_free_space_label, 0, 9, 1, 1);
+    recording_page.attach(&record_min_free_space, 1, 9, 3, 1);
+
+    // Burst count and interval spin buttons plus the labels next to them
+    let burst_count_label = gtk::Label::new(Some("Snapshots per burst"));
+    // We allow up to 100 snapshots, 1 disables bursts
+    let burst_count = gtk::SpinButton::new_with_range(BURST_COUNT_RANGE.0, BURST_COUNT_RANGE.1, 1.);
+    let burst_interval_ms_label = gtk::Label::new(Some("Time between burst snapshots (ms)"));
+    let burst_interval_ms =
+        gtk::SpinButton::new_with_range(BURST_INTERVAL_MS_RANGE.0, BURST_INTERVAL_MS_RANGE.1, 50.);
+
+    burst_count_label.set_halign(gtk::Align::Start);
+    burst_interval_ms_label.set_halign(gtk::Align::Start);
+
+    burst_count.set_value(f64::from(settings.burst_count));
+    burst_count.set_hexpand(true);
+    burst_interval_ms.set_value(f64::from(settings.burst_interval_ms));
+    burst_interval_ms.set_hexpand(true);
+
+    snapshots_page.attach(&burst_count_label, 0, 5, 1, 1);
+    snapshots_page.attach(&burst_count, 1, 5, 3, 1);
+    snapshots_page.attach(&burst_interval_ms_label, 0, 6, 1, 1);
+    snapshots_page.attach(&burst_interval_ms, 1, 6, 3, 1);
+
+    // Sliders for the color adjustments of the preview plus the labels next to them
+    let brightness = gtk::Scale::new_with_range(
+        gtk::Orientation::Horizontal,
+        BRIGHTNESS_RANGE.0,
+        BRIGHTNESS_RANGE.1,
+        0.01,
+    );
+    let contrast = gtk::Scale::new_with_range(
+        gtk::Orientation::Horizontal,
+        CONTRAST_RANGE.0,
+        CONTRAST_RANGE.1,
+        0.01,
+    );
+    let saturation = gtk::Scale::new_with_range(
+        gtk::Orientation::Horizontal,
+        SATURATION_RANGE.0,
+        SATURATION_RANGE.1,
+        0.01,
+    );
+    let hue =
+        gtk::Scale::new_with_range(gtk::Orientation::Horizontal, HUE_RANGE.0, HUE_RANGE.1, 0.01);
+
+    for (row, &(name, scale, value, default)) in [
+        ("Brightness", &brightness, settings.brightness, 0.0),
+        ("Contrast", &contrast, settings.contrast, 1.0),
+        ("Saturation", &saturation, settings.saturation, 1.0),
+        ("Hue", &hue, settings.hue, 0.0),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::Start);
+
+        scale.set_value(value);
+        scale.set_hexpand(true);
+        // Mark the unchanged value so it's easy to go back to it
+        scale.add_mark(default, gtk::PositionType::Bottom, None);
+
+        image_page.attach(&label, 0, 0 + row as i32, 1, 1);
+        image_page.attach(scale, 1, 0 + row as i32, 3, 1);
+    }
+
+    // Video flip combobox plus the label next to it
+    let video_flip_label = gtk::Label::new(Some("Flip / rotate"));
+    let video_flip = gtk::ComboBoxText::new();
+
+    video_flip_label.set_halign(gtk::Align::Start);
+
+    video_flip.append_text("None");
+    video_flip.append_text("Mirror horizontally");
+    video_flip.append_text("Mirror vertically");
+    video_flip.append_text("Rotate 90°");
+    video_flip.append_text("Rotate 180°");
+    video_flip.append_text("Rotate 270°");
+    video_flip.set_active(match settings.video_flip {
+        VideoFlipMethod::None => Some(0),
+        VideoFlipMethod::Horizontal => Some(1),
+        VideoFlipMethod::Vertical => Some(2),
+        VideoFlipMethod::Rotate90 => Some(3),
+        VideoFlipMethod::Rotate180 => Some(4),
+        VideoFlipMethod::Rotate270 => Some(5),
+    });
+    video_flip.set_hexpand(true);
+    video_flip.set_tooltip_text(Some(
+        "Also applies to recordings and snapshots. Changes are applied once no recording is running",
+    ));
+
+    image_page.attach(&video_flip_label, 0, 5, 1, 1);
+    image_page.attach(&video_flip, 1, 5, 3, 1);
+
+    // Bitrate spin button plus the label next to it, only shown for codecs that use it, and the
+    // preset combobox plus label, only shown for H.264
+    let encoder_options_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let x264_preset_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    let record_video_bitrate_kbps_label = gtk::Label::new(Some("Bitrate (kbit/s)"));
+    // The encoder fails with a bitrate of 0, so start at 1
+    let record_video_bitrate_kbps = gtk::SpinButton::new_with_range(
+        VIDEO_BITRATE_KBPS_RANGE.0,
+        VIDEO_BITRATE_KBPS_RANGE.1,
+        256.,
+    );
+    let x264_preset_label = gtk::Label::new(Some("Preset"));
+    let x264_preset = gtk::ComboBoxText::new();
+
+    record_video_bitrate_kbps.set_value(f64::from(settings.record_video_bitrate_kbps));
+
+    x264_preset.append_text("Ultrafast");
+    x264_preset.append_text("Superfast");
+    x264_preset.append_text("Veryfast");
+    x264_preset.append_text("Faster");
+    x264_preset.append_text("Fast");
+    x264_preset.append_text("Medium");
+    x264_preset.append_text("Slow");
+    x264_preset.append_text("Slower");
+    x264_preset.append_text("Veryslow");
+    x264_preset.set_active(match settings.x264_preset {
+        X264Preset::Ultrafast => Some(0),
+        X264Preset::Superfast => Some(1),
+        X264Preset::Veryfast => Some(2),
+        X264Preset::Faster => Some(3),
+        X264Preset::Fast => Some(4),
+        X264Preset::Medium => Some(5),
+        X264Preset::Slow => Some(6),
+        X264Preset::Slower => Some(7),
+        X264Preset::Veryslow => Some(8),
+    });
+    x264_preset.set_tooltip_text(Some(
+        "Slower presets compress better but can drop frames on slow machines",
+    ));
+
+    x264_preset_box.pack_start(&x264_preset_label, false, false, 0);
+    x264_preset_box.pack_start(&x264_preset, true, true, 0);
+    x264_preset_box.show_all();
+    x264_preset_box.set_no_show_all(true);
+    x264_preset_box.set_visible(settings.record_codec == VideoCodec::H264);
+
+    encoder_options_box.pack_start(&record_video_bitrate_kbps_label, false, false, 0);
+    encoder_options_box.pack_start(&record_video_bitrate_kbps, true, true, 0);
+    encoder_options_box.pack_start(&x264_preset_box, true, true, 0);
+    encoder_options_box.show_all();
+    encoder_options_box.set_no_show_all(true);
+    encoder_options_box.set_visible(settings.record_codec.has_bitrate());
+
+    recording_page.attach(&encoder_options_box, 0, 1, 4, 1);
+
+    // Check button for notifications about saved snapshots and recordings
+    let notifications_enabled = gtk::CheckButton::new_with_label(
+        "Show a notification when a snapshot or recording is saved",
+    );
+    notifications_enabled.set_active(settings.notifications_enabled);
+
+    general_page.attach(&notifications_enabled, 0, 3, 4, 1);
+
+    // Check button for choosing the location of each snapshot and recording
+    let prompt_for_location =
+        gtk::CheckButton::new_with_label("Ask where to save each snapshot and recording");
+    prompt_for_location.set_active(settings.prompt_for_location);
+    prompt_for_location.set_tooltip_text(Some(
+        "Only single snapshots ask, bursts and timed snapshots are saved in the snapshot directory",
+    ));
+
+    files_page.attach(&prompt_for_location, 0, 10, 4, 1);
+
+    // Zoom slider plus the label next to it
+    let zoom_label = gtk::Label::new(Some("Zoom"));
+    let zoom = gtk::Scale::new_with_range(
+        gtk::Orientation::Horizontal,
+        ZOOM_RANGE.0,
+        ZOOM_RANGE.1,
+        0.1,
+    );
+
+    zoom_label.set_halign(gtk::Align::Start);
+    zoom.set_value(settings.zoom);
+    zoom.set_hexpand(true);
+    zoom.add_mark(1.0, gtk::PositionType::Bottom, None);
+    zoom.connect_format_value(|_, value| format!("{:.1}×", value));
+    zoom.set_tooltip_text(Some(
+        "Zooms into the center. Also applies to recordings and snapshots",
+    ));
+
+    image_page.attach(&zoom_label, 0, 4, 1, 1);
+    image_page.attach(&zoom, 1, 4, 3, 1);
+
+    // Check button for the burned-in timestamp, plus its position and font size below
+    let burn_timestamp =
+        gtk::CheckButton::new_with_label("Show the date and time in recordings and snapshots");
+    burn_timestamp.set_active(settings.burn_timestamp);
+
+    image_page.attach(&burn_timestamp, 0, 6, 4, 1);
+
+    let timestamp_position_label = gtk::Label::new(Some("Timestamp position"));
+    let timestamp_position = gtk::ComboBoxText::new();
+
+    timestamp_position_label.set_halign(gtk::Align::Start);
+
+    timestamp_position.append_text("Top left");
+    timestamp_position.append_text("Top right");
+    timestamp_position.append_text("Bottom left");
+    timestamp_position.append_text("Bottom right");
+    timestamp_position.set_active(match settings.timestamp_position {
+        OverlayPosition::TopLeft => Some(0),
+        OverlayPosition::TopRight => Some(1),
+        OverlayPosition::BottomLeft => Some(2),
+        OverlayPosition::BottomRight => Some(3),
+    });
+    timestamp_position.set_hexpand(true);
+
+    image_page.attach(&timestamp_position_label, 0, 7, 1, 1);
+    image_page.attach(&timestamp_position, 1, 7, 3, 1);
+
+    let timestamp_font_size_label = gtk::Label::new(Some("Timestamp size (pt)"));
+    let timestamp_font_size = gtk::SpinButton::new_with_range(
+        TIMESTAMP_FONT_SIZE_RANGE.0,
+        TIMESTAMP_FONT_SIZE_RANGE.1,
+        1.,
+    );
+
+    timestamp_font_size_label.set_halign(gtk::Align::Start);
+    timestamp_font_size.set_value(f64::from(settings.timestamp_font_size));
+    timestamp_font_size.set_hexpand(true);
+
+    image_page.attach(&timestamp_font_size_label, 0, 8, 1, 1);
+    image_page.attach(&timestamp_font_size, 1, 8, 3, 1);
+
+    // Check button for the watermark plus the file chooser for its image next to it, and its
+    // position and opacity below
+    let watermark_enabled = gtk::CheckButton::new_with_label("Watermark image");
+    let watermark_chooser =
+        gtk::FileChooserButton::new("Pick an image file", gtk::FileChooserAction::Open);
+
+    watermark_enabled.set_active(settings.watermark_path.is_some());
+    let image_filter = gtk::FileFilter::new();
+    image_filter.add_pixbuf_formats();
+    watermark_chooser.set_filter(&image_filter);
+    if let Some(ref path) = settings.watermark_path {
+        watermark_chooser.set_filename(path);
+    }
+    watermark_chooser.set_sensitive(settings.watermark_path.is_some());
+    watermark_chooser.set_hexpand(true);
+
+    image_page.attach(&watermark_enabled, 0, 9, 1, 1);
+    image_page.attach(&watermark_chooser, 1, 9, 3, 1);
+
+    let watermark_position_label = gtk::Label::new(Some("Watermark position"));
+    let watermark_position = gtk::ComboBoxText::new();
+
+    watermark_position_label.set_halign(gtk::Align::Start);
+
+    watermark_position.append_text("Top left");
+    watermark_position.append_text("Top right");
+    watermark_position.append_text("Bottom left");
+    watermark_position.append_text("Bottom right");
+    watermark_position.set_active(match settings.watermark_position {
+        OverlayPosition::TopLeft => Some(0),
+        OverlayPosition::TopRight => Some(1),
+        OverlayPosition::BottomLeft => Some(2),
+        OverlayPosition::BottomRight => Some(3),
+    });
+    watermark_position.set_hexpand(true);
+
+    image_page.attach(&watermark_position_label, 0, 10, 1, 1);
+    image_page.attach(&watermark_position, 1, 10, 3, 1);
+
+    let watermark_opacity_label = gtk::Label::new(Some("Watermark opacity"));
+    let watermark_opacity = gtk::Scale::new_with_range(
+        gtk::Orientation::Horizontal,
+        WATERMARK_OPACITY_RANGE.0,
+        WATERMARK_OPACITY_RANGE.1,
+        0.05,
+    );
+
+    watermark_opacity_label.set_halign(gtk::Align::Start);
+    watermark_opacity.set_value(settings.watermark_opacity);
+    watermark_opacity.set_hexpand(true);
+
+    image_page.attach(&watermark_opacity_label, 0, 11, 1, 1);
+    image_page.attach(&watermark_opacity, 1, 11, 3, 1);
+
+    // Check button for additionally saving snapshots as PNG
+    let snapshot_also_png =
+        gtk::CheckButton::new_with_label("Also save each snapshot as lossless PNG");
+    snapshot_also_png.set_active(settings.snapshot_also_png);
+    snapshot_also_png.set_tooltip_text(Some(
+        "The PNG is written next to the snapshot with the same name, unless PNG is already the snapshot format",
+    ));
+
+    snapshots_page.attach(&snapshot_also_png, 0, 1, 4, 1);
+
+    // Entry for a custom pipeline instead of the camera plus the label next to it
+    let custom_pipeline_label = gtk::Label::new(Some("Custom pipeline"));
+    let custom_pipeline = gtk::Entry::new();
+    custom_pipeline_label.set_halign(gtk::Align::Start);
+    custom_pipeline.set_text(
+        settings
+            .custom_pipeline
+            .as_ref()
+            .map(String::as_str)
+            .unwrap_or(""),
+    );
+    custom_pipeline.set_placeholder_text(Some("Use the camera"));
+    custom_pipeline.set_hexpand(true);
+    custom_pipeline.set_tooltip_text(Some(
+        "A GStreamer pipeline that is used instead of the camera, e.g. rtspsrc location=rtsp://… ! decodebin ! videoconvert ! tee name=tee ! queue ! gtksink name=sink. It needs a tee named tee and a gtksink named sink. Changes are applied once Enter is pressed or the entry is left",
+    ));
+
+    camera_page.attach(&custom_pipeline_label, 0, 9, 1, 1);
+    camera_page.attach(&custom_pipeline, 1, 9, 3, 1);
+
+    // Video source combobox, and the URL entry and latency spin button for network cameras plus
+    // the labels next to them. The camera selection is only used for local cameras, the others
+    // only for network cameras
+    let source_type_label = gtk::Label::new(Some("Video source"));
+    let source_type = gtk::ComboBoxText::new();
+    source_type_label.set_halign(gtk::Align::Start);
+    source_type.append_text("Local camera");
+    source_type.append_text("Network camera (RTSP)");
+    source_type.set_active(match settings.source_type {
+        SourceType::Device => Some(0),
+        SourceType::Rtsp => Some(1),
+    });
+    source_type.set_hexpand(true);
+
+    let rtsp_url_label = gtk::Label::new(Some("Network camera URL"));
+    let rtsp_url = gtk::Entry::new();
+    rtsp_url_label.set_halign(gtk::Align::Start);
+    rtsp_url.set_text(&settings.rtsp_url);
+    rtsp_url.set_placeholder_text(Some("rtsp://camera:554/stream"));
+    rtsp_url.set_hexpand(true);
+    rtsp_url.set_tooltip_text(Some(
+        "Changes are applied once Enter is pressed or the entry is left",
+    ));
+
+    let rtsp_latency_ms_label = gtk::Label::new(Some("Network buffer (ms)"));
+    let rtsp_latency_ms = gtk::SpinButton::new_with_range(0., 5000., 50.);
+    rtsp_latency_ms_label.set_halign(gtk::Align::Start);
+    rtsp_latency_ms.set_value(f64::from(settings.rtsp_latency_ms));
+    rtsp_latency_ms.set_hexpand(true);
+    rtsp_latency_ms.set_tooltip_text(Some(
+        "Higher values give a smoother video on unreliable networks, at the cost of more delay. Changes are applied once Enter is pressed or the entry is left",
+    ));
+
+    let is_rtsp = settings.source_type == SourceType::Rtsp;
+    video_device.set_sensitive(!is_rtsp);
+    for widget in &[
+        rtsp_url_label.upcast_ref::<gtk::Widget>(),
+        rtsp_url.upcast_ref(),
+        rtsp_latency_ms_label.upcast_ref(),
+        rtsp_latency_ms.upcast_ref(),
+    ] {
+        widget.set_sensitive(is_rtsp);
+    }
+
+    camera_page.attach(&source_type_label, 0, 1, 1, 1);
+    camera_page.attach(&source_type, 1, 1, 3, 1);
+    camera_page.attach(&rtsp_url_label, 0, 2, 1, 1);
+    camera_page.attach(&rtsp_url, 1, 2, 3, 1);
+    camera_page.attach(&rtsp_latency_ms_label, 0, 3, 1, 1);
+    camera_page.attach(&rtsp_latency_ms, 1, 3, 3, 1);
+
+    // Check button for deinterlacing the video
+    let deinterlace = gtk::CheckButton::new_with_label("Deinterlace video");
+    deinterlace.set_active(settings.deinterlace);
+    deinterlace.set_tooltip_text(Some(
+        "For capture cards and cameras with interlaced video, which otherwise shows combing artifacts on movement. Applies to the preview, snapshots and recordings",
+    ));
+
+    camera_page.attach(&deinterlace, 0, 6, 4, 1);
+
+    // Check button for allowing other machines to connect to the HTTP preview
+    let http_preview_remote =
+        gtk::CheckButton::new_with_label("Allow HTTP preview connections from other computers");
+    http_preview_remote.set_active(settings.http_preview_remote);
+    http_preview_remote.set_sensitive(settings.http_preview_enabled);
+    http_preview_remote.set_tooltip_text(Some(
+        "Anybody who can reach this computer can watch the preview, there is no password",
+    ));
+
+    general_page.attach(&http_preview_remote, 0, 6, 4, 1);
+
+    // Snapshot file collision combobox plus the label next to it
+    let snapshot_file_collision_label = gtk::Label::new(Some("If a snapshot file already exists"));
+    let snapshot_file_collision = gtk::ComboBoxText::new();
+
+    snapshot_file_collision_label.set_halign(gtk::Align::Start);
+
+    snapshot_file_collision.append_text("Append a number");
+    snapshot_file_collision.append_text("Ask");
+    snapshot_file_collision.set_active(match settings.snapshot_file_collision {
+        FileCollision::AppendSuffix => Some(0),
+        FileCollision::Ask => Some(1),
+    });
+    snapshot_file_collision.set_hexpand(true);
+
+    files_page.attach(&snapshot_file_collision_label, 0, 11, 1, 1);
+    files_page.attach(&snapshot_file_collision, 1, 11, 3, 1);
+
+    // Put the notebook into the dialog's content area
+    let content_area = dialog.get_content_area();
+    content_area.pack_start(&notebook, true, true, 0);
+    content_area.set_border_width(10);
+
+    let settings_dialog = SettingsDialog(Rc::new(SettingsDialogInner {
+        snapshot_directory_chooser,
+        snapshot_format,
+        snapshot_jpeg_quality_box,
+        snapshot_jpeg_quality,
+        timer_entry,
+        burst_count,
+        burst_interval_ms,
+        video_device,
+        capture_resolution,
+        capture_framerate,
+        snapshot_source,
+        record_directory_chooser,
+        record_codec,
+        record_container,
+        record_audio_source,
+        h264_profile,
+        encoder_options_box,
+        x264_preset_box,
+        record_video_bitrate_kbps,
+        x264_preset,
+        filename_timezone,
+        exposure_lock,
+        http_preview_enabled,
+        http_preview_port,
+        record_output_preset,
+        record_output_advanced,
+        record_output_sync,
+        record_output_async,
+        record_output_leaky,
+        record_output_buffer_mode,
+        capture_sound,
+        capture_sound_chooser,
+        confirm_quit_while_recording,
+        record_constant_framerate,
+        record_format_fallback,
+        record_min_free_memory,
+        record_min_free_space,
+        record_preallocate_size,
+        record_max_duration_secs,
+        record_reminder_interval,
+        disable_preview,
+        snapshot_on_record_start,
+        settings_apply_on_close,
+        record_portrait,
+        auto_record_on_start,
+        write_capture_metadata,
+        snapshot_wait_for_frame,
+        snapshot_archive,
+        snapshot_file_collision,
+        record_encrypt,
+        minimize_preview_while_recording,
+        close_action,
+        high_contrast_overlay,
+        notifications_enabled,
+        prompt_for_location,
+        snapshot_naming,
+        record_naming,
+        snapshot_filename_template,
+        snapshot_filename_preview,
+        record_filename_template,
+        record_filename_preview,
+        playback_decoder,
+        brightness,
+        contrast,
+        saturation,
+        hue,
+        video_flip,
+        zoom,
+        burn_timestamp,
+        timestamp_position,
+        timestamp_font_size,
+        watermark_enabled,
+        watermark_chooser,
+        watermark_position,
+        watermark_opacity,
+        snapshot_also_png,
+        custom_pipeline,
+        source_type,
+        rtsp_url,
+        rtsp_latency_ms,
+        deinterlace,
+        http_preview_remote,
+        apply_on_close,
+        app: app.downgrade(),
+        settings: RefCell::new(settings),
+        save_timeout: RefCell::new(None),
+    }));
+
+    // Finally connect to all kinds of change notification signals for the different UI widgets.
     // Whenever something is changing we directly save the configuration file with the new values.
-    let settings_dialog_weak = settings_dialog.downgrade();
     settings_dialog
         .snapshot_directory_chooser
-        .connect_file_set(move |_| {
+        .connect_file_set(settings_dialog.on_changed_callback());
+
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .snapshot_format
+        .connect_changed(move |snapshot_format| {
             let settings_dialog = upgrade_weak!(settings_dialog_weak);
-            settings_dialog.save_settings();
+            settings_dialog.snapshot_jpeg_quality_box.set_visible(
+                get_combo_value::<SnapshotFormat>(&snapshot_format) == SnapshotFormat::JPEG,
+            );
+            settings_dialog.on_changed();
+        });
+
+    settings_dialog
+        .snapshot_jpeg_quality
+        .connect_value_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .timer_entry
+        .connect_value_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .burst_count
+        .connect_value_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .burst_interval_ms
+        .connect_value_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .record_directory_chooser
+        .connect_file_set(settings_dialog.on_changed_callback());
+
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .record_codec
+        .connect_changed(move |record_codec| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+
+            let codec = get_combo_value::<VideoCodec>(&record_codec);
+            let container = get_combo_value::<Container>(&settings_dialog.record_container);
+            update_record_containers(&settings_dialog.record_container, codec, container);
+            settings_dialog
+                .encoder_options_box
+                .set_visible(codec.has_bitrate());
+            settings_dialog
+                .x264_preset_box
+                .set_visible(codec == VideoCodec::H264);
+
+            settings_dialog.on_changed();
         });
 
     let settings_dialog_weak = settings_dialog.downgrade();
-    settings_dialog.snapshot_format.connect_changed(move |_| {
-        let settings_dialog = upgrade_weak!(settings_dialog_weak);
-        settings_dialog.save_settings();
-    });
+    settings_dialog
+        .record_container
+        .connect_changed(move |record_container| {
+            // Nothing is selected while the list of containers is updated
+            if record_container.get_active().is_none() {
+                return;
+            }
+
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog.on_changed();
+        });
+
+    settings_dialog
+        .snapshot_source
+        .connect_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .record_audio_source
+        .connect_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .filename_timezone
+        .connect_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .h264_profile
+        .connect_changed(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .exposure_lock
+        .connect_changed(settings_dialog.on_changed_callback());
 
     let settings_dialog_weak = settings_dialog.downgrade();
-    settings_dialog.timer_entry.connect_value_changed(move |_| {
-        let settings_dialog = upgrade_weak!(settings_dialog_weak);
-        settings_dialog.save_settings();
-    });
+    settings_dialog
+        .http_preview_enabled
+        .connect_toggled(move |http_preview_enabled| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog
+                .http_preview_port
+                .set_sensitive(http_preview_enabled.get_active());
+            settings_dialog
+                .http_preview_remote
+                .set_sensitive(http_preview_enabled.get_active());
+            settings_dialog.on_changed();
+        });
+
+    settings_dialog
+        .http_preview_port
+        .connect_value_changed(settings_dialog.on_changed_callback());
 
     let settings_dialog_weak = settings_dialog.downgrade();
     settings_dialog
-        .record_directory_chooser
-        .connect_file_set(move |_| {
+        .record_output_preset
+        .connect_changed(move |record_output_preset| {
             let settings_dialog = upgrade_weak!(settings_dialog_weak);
-            settings_dialog.save_settings();
+            settings_dialog.record_output_advanced.set_visible(
+                get_combo_value::<RecordOutputPreset>(&record_output_preset)
+                    == RecordOutputPreset::Advanced,
+            );
+            settings_dialog.on_changed();
+        });
+
+    for check_button in &[
+        &settings_dialog.record_output_sync,
+        &settings_dialog.record_output_async,
+        &settings_dialog.record_output_leaky,
+    ] {
+        let settings_dialog_weak = settings_dialog.downgrade();
+        check_button.connect_toggled(move |_| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog.on_changed();
+        });
+    }
+
+    settings_dialog
+        .record_output_buffer_mode
+        .connect_changed(settings_dialog.on_changed_callback());
+
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .capture_sound
+        .connect_changed(move |capture_sound| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog
+                .capture_sound_chooser
+                .set_visible(capture_sound.get_active() == Some(4));
+            settings_dialog.on_changed();
+        });
+
+    // Check custom sounds when selecting them already instead of only noticing when taking the
+    // next snapshot
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .capture_sound_chooser
+        .connect_file_set(move |capture_sound_chooser| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+
+            if let Some(path) = capture_sound_chooser.get_filename() {
+                if !sounds::is_playable(&path) {
+                    utils::show_error_dialog(
+                        false,
+                        format!(
+                            "'{}' is not a playable sound file, a beep will be used instead",
+                            path.display()
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+
+            settings_dialog.on_changed();
+        });
+
+    settings_dialog
+        .confirm_quit_while_recording
+        .connect_toggled(settings_dialog.on_changed_callback());
+
+    settings_dialog
+        .record_constant_framerate
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_format_fallback
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .disable_preview
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .snapshot_on_record_start
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_min_free_memory
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_min_free_space
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_preallocate_size
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_max_duration_secs
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .settings_apply_on_close
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_portrait
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .auto_record_on_start
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .write_capture_metadata
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .snapshot_wait_for_frame
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_reminder_interval
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .snapshot_archive
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_encrypt
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .minimize_preview_while_recording
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .snapshot_file_collision
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .close_action
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .high_contrast_overlay
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .notifications_enabled
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .prompt_for_location
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .snapshot_naming
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_naming
+        .connect_changed(settings_dialog.on_changed_callback());
+    for entry in &[
+        &settings_dialog.snapshot_filename_template,
+        &settings_dialog.record_filename_template,
+    ] {
+        let settings_dialog_weak = settings_dialog.downgrade();
+        entry.connect_changed(move |_| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog.on_changed();
+        });
+    }
+    settings_dialog.update_filename_previews();
+    settings_dialog
+        .playback_decoder
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .record_video_bitrate_kbps
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .x264_preset
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .video_flip
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .burn_timestamp
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .timestamp_position
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .timestamp_font_size
+        .connect_value_changed(settings_dialog.on_changed_callback());
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .watermark_enabled
+        .connect_toggled(move |watermark_enabled| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog
+                .watermark_chooser
+                .set_sensitive(watermark_enabled.get_active());
+            settings_dialog.on_changed();
+        });
+    settings_dialog
+        .watermark_chooser
+        .connect_file_set(settings_dialog.on_changed_callback());
+    settings_dialog
+        .watermark_position
+        .connect_changed(settings_dialog.on_changed_callback());
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .watermark_opacity
+        .connect_value_changed(move |_| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            settings_dialog.on_slider_changed();
+        });
+    settings_dialog
+        .snapshot_also_png
+        .connect_toggled(settings_dialog.on_changed_callback());
+    // Every change of the custom pipeline rebuilds the pipeline, so only apply it once the user
+    // is done typing
+    settings_dialog
+        .custom_pipeline
+        .connect_activate(settings_dialog.on_changed_callback());
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .custom_pipeline
+        .connect_focus_out_event(move |_, _| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak, gtk::Inhibit(false));
+            settings_dialog.on_changed();
+            gtk::Inhibit(false)
+        });
+    let settings_dialog_weak = settings_dialog.downgrade();
+    let rtsp_widgets = [
+        rtsp_url_label.upcast::<gtk::Widget>(),
+        rtsp_latency_ms_label.upcast(),
+    ];
+    settings_dialog
+        .source_type
+        .connect_changed(move |source_type| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            let is_rtsp = get_combo_value::<SourceType>(&source_type) == SourceType::Rtsp;
+            settings_dialog.video_device.set_sensitive(!is_rtsp);
+            settings_dialog.rtsp_url.set_sensitive(is_rtsp);
+            settings_dialog.rtsp_latency_ms.set_sensitive(is_rtsp);
+            for widget in &rtsp_widgets {
+                widget.set_sensitive(is_rtsp);
+            }
+            settings_dialog.on_changed();
+        });
+    // Like the custom pipeline, the URL is only applied once the user is done typing
+    settings_dialog
+        .rtsp_url
+        .connect_activate(settings_dialog.on_changed_callback());
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .rtsp_url
+        .connect_focus_out_event(move |_, _| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak, gtk::Inhibit(false));
+            settings_dialog.on_changed();
+            gtk::Inhibit(false)
+        });
+    // Every change of the latency also rebuilds the pipeline, so don't apply every step while
+    // the value is changed
+    settings_dialog
+        .rtsp_latency_ms
+        .connect_activate(settings_dialog.on_changed_callback());
+    let settings_dialog_weak = settings_dialog.downgrade();
+    settings_dialog
+        .rtsp_latency_ms
+        .connect_focus_out_event(move |_, _| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak, gtk::Inhibit(false));
+            settings_dialog.on_changed();
+            gtk::Inhibit(false)
         });
+    settings_dialog
+        .deinterlace
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .http_preview_remote
+        .connect_toggled(settings_dialog.on_changed_callback());
+    settings_dialog
+        .video_device
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .capture_resolution
+        .connect_changed(settings_dialog.on_changed_callback());
+    settings_dialog
+        .capture_framerate
+        .connect_value_changed(settings_dialog.on_changed_callback());
 
+    // The zoom is applied right away while dragging, like the color adjustments below
     let settings_dialog_weak = settings_dialog.downgrade();
-    settings_dialog.record_format.connect_changed(move |_| {
+    let app_weak = app.downgrade();
+    settings_dialog.zoom.connect_value_changed(move |zoom| {
         let settings_dialog = upgrade_weak!(settings_dialog_weak);
-        settings_dialog.save_settings();
+        let app = upgrade_weak!(app_weak);
+        settings_dialog.on_slider_changed();
+        app.set_zoom(zoom.get_value());
     });
 
-    // Close the dialog when the close button is clicked. We don't need to save the settings here
-    // as we already did that whenever the user changed something in the UI.
+    // The color adjustments are applied to the preview right away while dragging, even if the
+    // changes are only saved once applied
+    for scale in &[
+        &settings_dialog.brightness,
+        &settings_dialog.contrast,
+        &settings_dialog.saturation,
+        &settings_dialog.hue,
+    ] {
+        let settings_dialog_weak = settings_dialog.downgrade();
+        let app_weak = app.downgrade();
+        scale.connect_value_changed(move |_| {
+            let settings_dialog = upgrade_weak!(settings_dialog_weak);
+            let app = upgrade_weak!(app_weak);
+            settings_dialog.on_slider_changed();
+            app.apply_color_balance(&settings_dialog.settings.borrow());
+        });
+    }
+
+    // Close the dialog when the close button is clicked. Unless the changes are only saved once
+    // applied, we don't need to save the settings here as we already did that whenever the user
+    // changed something in the UI. In any case we let the application apply all settings that
+    // don't only take effect for the next capture.
     //
     // The closure keeps the one and only strong reference to our settings dialog struct and it
     // will be freed once the dialog is destroyed
     let settings_dialog_storage = RefCell::new(Some(settings_dialog));
-    dialog.connect_response(move |dialog, _| {
+    let app_weak = app.downgrade();
+    dialog.connect_response(move |dialog, response| {
         dialog.destroy();
 
-        let _ = settings_dialog_storage.borrow_mut().take();
+        if let Some(settings_dialog) = settings_dialog_storage.borrow_mut().take() {
+            // Don't lose the last slider movement if the dialog is closed right after it
+            let save_pending = settings_dialog.cancel_pending_save();
+            if save_pending
+                || (settings_dialog.apply_on_close && response == gtk::ResponseType::Apply)
+            {
+                settings_dialog.save_settings();
+            }
+        }
+
+        let app = upgrade_weak!(app_weak);
+        app.apply_settings();
     });
 
-    dialog.set_resizable(false);
     dialog.show_all();
 }
+
+// Add a page with the given title to the settings notebook and return the grid for its widgets
+//
+// The page scrolls vertically if it does not fit on the screen
+fn add_settings_page(notebook: &gtk::Notebook, title: &str) -> gtk::Grid {
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_border_width(12);
+
+    let scrolled_window =
+        gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    scrolled_window.set_min_content_height(SETTINGS_PAGE_MIN_HEIGHT);
+    scrolled_window.add(&grid);
+
+    notebook.append_page(&scrolled_window, Some(&gtk::Label::new(Some(title))));
+
+    grid
+}
+
+// Fill the container combobox with all containers that support the codec and select the given
+// container, or the first one if it does not support the codec
+fn update_record_containers(
+    record_container: &gtk::ComboBoxText,
+    codec: VideoCodec,
+    container: Container,
+) {
+    record_container.remove_all();
+
+    let containers = Container::all()
+        .iter()
+        .cloned()
+        .filter(|container| container.supports(codec))
+        .collect::<Vec<_>>();
+    for container in &containers {
+        record_container.append_text(container.name());
+    }
+
+    let active = containers.iter().position(|c| *c == container).unwrap_or(0);
+    record_container.set_active(Some(active as u32));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Serializing the settings, parsing them again and serializing the result has to give the
+    // same as the first time
+    fn assert_round_trip(settings: &Settings, format: serde_any::Format) {
+        let serialized = settings
+            .to_format(format)
+            .expect("Failed to serialize settings");
+        let parsed = Settings::from_format(&serialized, format).expect("Failed to parse settings");
+        assert_eq!(
+            parsed
+                .to_format(format)
+                .expect("Failed to serialize parsed settings"),
+            serialized
+        );
+    }
+
+    // Settings with the optional values set and most of the enums and nested structs changed from
+    // their defaults
+    fn non_default_settings() -> Settings {
+        Settings {
+            snapshot_directory: PathBuf::from("/tmp/snapshots"),
+            timer_length: 10,
+            video_device: Some(String::from("/dev/video1")),
+            source_type: SourceType::Rtsp,
+            rtsp_url: String::from("rtsp://camera.local/stream"),
+            custom_pipeline: Some(String::from("videotestsrc ! videoconvert")),
+            capture_framerate: 25,
+            record_codec: VideoCodec::Vp8,
+            record_container: Container::WebM,
+            capture_sound: CaptureSound::Custom(PathBuf::from("/tmp/click.oga")),
+            close_action: CloseAction::Background,
+            snapshot_file_collision: FileCollision::Ask,
+            brightness: 0.25,
+            zoom: 2.5,
+            watermark_path: Some(PathBuf::from("/tmp/logo.png")),
+            watermark_position: OverlayPosition::BottomLeft,
+            watermark_opacity: 0.5,
+            window_position: Some((10, 20)),
+            window_maximized: true,
+            show_grid: true,
+            record_output_tuning: RecordOutputTuning {
+                sync: true,
+                async_: false,
+                buffer_mode: FileSinkBufferMode::Full,
+                leaky: true,
+            },
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        assert_round_trip(&Settings::default(), serde_any::Format::Toml);
+        assert_round_trip(&non_default_settings(), serde_any::Format::Toml);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_round_trip(&Settings::default(), serde_any::Format::Json);
+        assert_round_trip(&non_default_settings(), serde_any::Format::Json);
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        assert_round_trip(&Settings::default(), serde_any::Format::Yaml);
+        assert_round_trip(&non_default_settings(), serde_any::Format::Yaml);
+    }
+
+    // Settings files from newer versions can contain fields that we don't know yet
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let toml =
+            "timer_length = 10\nsome_future_option = true\n\n[some_future_table]\nvalue = 1\n";
+        let parsed =
+            Settings::from_format(toml, serde_any::Format::Toml).expect("Failed to parse settings");
+        assert_eq!(parsed.timer_length, 10);
+
+        let json = r#"{"timer_length": 10, "some_future_option": [1, 2, 3]}"#;
+        let parsed =
+            Settings::from_format(json, serde_any::Format::Json).expect("Failed to parse settings");
+        assert_eq!(parsed.timer_length, 10);
+    }
+
+    // Settings files from older versions don't contain the fields that were added since then
+    #[test]
+    fn missing_fields_are_defaulted() {
+        let toml = "timer_length = 10\nshow_grid = true\n";
+        let parsed =
+            Settings::from_format(toml, serde_any::Format::Toml).expect("Failed to parse settings");
+
+        let expected = Settings {
+            timer_length: 10,
+            show_grid: true,
+            ..Settings::default()
+        };
+        assert_eq!(
+            parsed.to_format(serde_any::Format::Toml).unwrap(),
+            expected.to_format(serde_any::Format::Toml).unwrap()
+        );
+
+        let parsed =
+            Settings::from_format("{}", serde_any::Format::Json).expect("Failed to parse settings");
+        assert_eq!(
+            parsed.to_format(serde_any::Format::Json).unwrap(),
+            Settings::default()
+                .to_format(serde_any::Format::Json)
+                .unwrap()
+        );
+    }
+
+    // The combined record format of older versions is split into codec and container
+    #[test]
+    fn legacy_record_format_is_migrated() {
+        for &(value, codec, container) in &[
+            ("H264Mp4", VideoCodec::H264, Container::Mp4),
+            ("H264Mkv", VideoCodec::H264, Container::Matroska),
+            ("Vp8WebM", VideoCodec::Vp8, Container::WebM),
+        ] {
+            let toml = format!("record_format = \"{}\"\n", value);
+            let parsed = Settings::from_format(&toml, serde_any::Format::Toml)
+                .expect("Failed to parse settings");
+            assert_eq!(parsed.record_codec, codec, "{}", value);
+            assert_eq!(parsed.record_container, container, "{}", value);
+            assert_eq!(parsed.record_format, None, "{}", value);
+
+            // And it is not written again
+            assert!(!parsed
+                .to_format(serde_any::Format::Toml)
+                .unwrap()
+                .contains("record_format ="));
+        }
+    }
+
+    #[test]
+    fn container_codec_combinations() {
+        assert!(Container::Mp4.supports(VideoCodec::H264));
+        assert!(Container::Mp4.supports(VideoCodec::H265));
+        assert!(!Container::Mp4.supports(VideoCodec::Vp8));
+        assert!(!Container::Mp4.supports(VideoCodec::Vp9));
+        assert!(!Container::Mp4.supports(VideoCodec::Av1));
+        assert!(!Container::WebM.supports(VideoCodec::H264));
+        assert!(Container::WebM.supports(VideoCodec::Vp9));
+
+        // Unsupported combinations from hand-edited files are replaced when loading
+        let toml = "record_codec = \"Vp9\"\nrecord_container = \"Mp4\"\n";
+        let parsed =
+            Settings::from_format(toml, serde_any::Format::Toml).expect("Failed to parse settings");
+        assert_eq!(parsed.record_codec, VideoCodec::Vp9);
+        assert_eq!(parsed.record_container, Container::Matroska);
+    }
+
+    #[test]
+    fn unsupported_combo_values() {
+        let garbage = || Some(glib::GString::from("garbage"));
+
+        assert_eq!(
+            SnapshotFormat::try_from(garbage()),
+            Err(UnsupportedValue::new("output format", "garbage"))
+        );
+        assert!(VideoCodec::try_from(garbage()).is_err());
+        assert!(Container::try_from(garbage()).is_err());
+        assert!(CloseAction::try_from(garbage()).is_err());
+        assert!(FileCollision::try_from(garbage()).is_err());
+        assert!(OverlayPosition::try_from(Some(glib::GString::from(""))).is_err());
+
+        // Nothing selected gives the default, and matching is case-insensitive
+        assert_eq!(
+            SnapshotFormat::try_from(None),
+            Ok(SnapshotFormat::default())
+        );
+        assert_eq!(
+            SnapshotFormat::try_from(Some(glib::GString::from("PNG"))),
+            Ok(SnapshotFormat::PNG)
+        );
+    }
+
+    #[test]
+    fn non_default_values_survive() {
+        let settings = non_default_settings();
+
+        for format in &[
+            serde_any::Format::Toml,
+            serde_any::Format::Json,
+            serde_any::Format::Yaml,
+        ] {
+            let serialized = settings
+                .to_format(*format)
+                .expect("Failed to serialize settings");
+            let parsed =
+                Settings::from_format(&serialized, *format).expect("Failed to parse settings");
+
+            assert_eq!(parsed.video_device, settings.video_device);
+            assert_eq!(parsed.source_type, settings.source_type);
+            assert_eq!(parsed.record_codec, settings.record_codec);
+            assert_eq!(parsed.record_container, settings.record_container);
+            assert_eq!(parsed.capture_sound, settings.capture_sound);
+            assert_eq!(parsed.close_action, settings.close_action);
+            assert_eq!(
+                parsed.snapshot_file_collision,
+                settings.snapshot_file_collision
+            );
+            assert_eq!(parsed.watermark_path, settings.watermark_path);
+            assert_eq!(parsed.window_position, settings.window_position);
+            assert_eq!(
+                parsed.record_output_tuning.buffer_mode,
+                settings.record_output_tuning.buffer_mode
+            );
+            assert!(parsed.record_output_tuning.leaky);
+        }
+    }
+
+    #[test]
+    fn defaults_in_range() {
+        let settings = Settings::default();
+        assert!(in_range(
+            f64::from(settings.timer_length),
+            TIMER_LENGTH_RANGE
+        ));
+        assert!(in_range(f64::from(settings.burst_count), BURST_COUNT_RANGE));
+        assert!(in_range(
+            f64::from(settings.burst_interval_ms),
+            BURST_INTERVAL_MS_RANGE
+        ));
+        assert!(in_range(
+            f64::from(settings.http_preview_port),
+            HTTP_PREVIEW_PORT_RANGE
+        ));
+        assert!(in_range(
+            f64::from(settings.record_video_bitrate_kbps),
+            VIDEO_BITRATE_KBPS_RANGE
+        ));
+        assert!(in_range(settings.brightness, BRIGHTNESS_RANGE));
+        assert!(in_range(settings.contrast, CONTRAST_RANGE));
+        assert!(in_range(settings.saturation, SATURATION_RANGE));
+        assert!(in_range(settings.hue, HUE_RANGE));
+        assert!(in_range(settings.zoom, ZOOM_RANGE));
+        assert!(in_range(
+            f64::from(settings.timestamp_font_size),
+            TIMESTAMP_FONT_SIZE_RANGE
+        ));
+        assert!(in_range(
+            settings.watermark_opacity,
+            WATERMARK_OPACITY_RANGE
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use gdk;
+use gio;
+use glib;
+use gst::{self, prelude::*};
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::settings::CaptureSound;
+use crate::APPLICATION_NAME;
+
+// The sounds we offer are part of the binary, so they don't depend on any sound theme being
+// installed
+const SHUTTER_SOUND: &[u8] = include_bytes!("../data/sounds/shutter.wav");
+const BELL_SOUND: &[u8] = include_bytes!("../data/sounds/bell.wav");
+const CLICK_SOUND: &[u8] = include_bytes!("../data/sounds/click.wav");
+
+thread_local! {
+    // Names of our own sounds that were already written to the cache directory by this process
+    static WRITTEN_SOUNDS: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+}
+
+impl CaptureSound {
+    // Get the path of the sound file to play, if any
+    pub fn get_path(&self) -> Option<PathBuf> {
+        let (name, data) = match *self {
+            CaptureSound::None => return None,
+            CaptureSound::Shutter => ("shutter.wav", SHUTTER_SOUND),
+            CaptureSound::Bell => ("bell.wav", BELL_SOUND),
+            CaptureSound::Click => ("click.wav", CLICK_SOUND),
+            CaptureSound::Custom(ref path) => return Some(path.clone()),
+        };
+
+        Some(get_bundled_sound_path(name, data))
+    }
+}
+
+// Get the path of one of our own sounds
+//
+// playbin can only play files and not data from memory, so the sound is written to the cache
+// directory the first time it is needed by this process. This also replaces the file from a
+// different version. If writing fails the file does not exist and the default beep is used instead
+fn get_bundled_sound_path(name: &'static str, data: &[u8]) -> PathBuf {
+    let mut path = glib::get_user_cache_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push(APPLICATION_NAME);
+    path.push("sounds");
+    path.push(name);
+
+    let first_use = WRITTEN_SOUNDS.with(|written_sounds| written_sounds.borrow_mut().insert(name));
+    if first_use {
+        let res = path
+            .parent()
+            .map(fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| fs::write(&path, data));
+        if let Err(err) = res {
+            println!("Failed to write sound file {}: {}", path.display(), err);
+        }
+    }
+
+    path
+}
+
+// Check if the file looks like something we can play
+pub fn is_playable(path: &Path) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+
+    let (content_type, _uncertain) = gio::content_type_guess(Some(path), &[]);
+    gio::content_type_is_a(&content_type, "audio/*")
+}
+
+// Play the configured sound asynchronously
+//
+// If the sound file can't be played for whatever reason, we fall back to the default beep
+pub fn play_capture_sound(sound: &CaptureSound) {
+    let path = match sound.get_path() {
+        None => return,
+        Some(path) => path,
+    };
+
+    if !is_playable(&path) {
+        gdk::beep();
+        return;
+    }
+
+    let uri = match glib::filename_to_uri(&path, None) {
+        Ok(uri) => uri,
+        Err(_) => {
+            gdk::beep();
+            return;
+        }
+    };
+
+    let playbin = match gst::ElementFactory::make("playbin", None) {
+        Ok(playbin) => playbin,
+        Err(_) => {
+            gdk::beep();
+            return;
+        }
+    };
+
+    playbin
+        .set_property("uri", &uri.as_str())
+        .expect("playbin had no uri property");
+
+    // Shut down the playbin again once the sound is finished or failed. This also removes the bus
+    // watch and with that the last reference to the playbin
+    let bus = playbin.get_bus().expect("playbin had no bus");
+    let playbin_clone = playbin.clone();
+    bus.add_watch(move |_bus, msg| {
+        use gst::MessageView;
+
+        match msg.view() {
+            MessageView::Eos(..) => (),
+            MessageView::Error(..) => gdk::beep(),
+            _ => return glib::Continue(true),
+        }
+
+        let _ = playbin_clone.set_state(gst::State::Null);
+
+        glib::Continue(false)
+    });
+
+    if playbin.set_state(gst::State::Playing).is_err() {
+        let _ = playbin.set_state(gst::State::Null);
+        gdk::beep();
+    }
+}
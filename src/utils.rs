@@ -2,49 +2,227 @@ use gio::{self, prelude::*};
 use glib;
 use gtk::{self, prelude::*};
 
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use serde_any;
 
-use crate::settings::Settings;
+use crate::settings::{Settings, Timezone};
 use crate::APPLICATION_NAME;
 
-// Get the default path for the settings file
+// Extensions of the supported settings file formats, in the order in which they are looked for
+const SETTINGS_FILE_EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml"];
+
+// Get the path of the settings file
+//
+// The settings can also be written as JSON or YAML, e.g. by configuration management, in which
+// case they are read from and saved to "settings.json" or "settings.yaml" instead. Without any
+// settings file the default is TOML
 pub fn get_settings_file_path() -> PathBuf {
     let mut path = glib::get_user_config_dir().unwrap_or_else(|| PathBuf::from("."));
     path.push(APPLICATION_NAME);
     path.push("settings.toml");
+
+    SETTINGS_FILE_EXTENSIONS
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|path| path.is_file())
+        .unwrap_or(path)
+}
+
+// Get the format of the settings file from its extension
+fn get_settings_file_format() -> serde_any::Format {
+    serde_any::guess_format(get_settings_file_path()).unwrap_or(serde_any::Format::Toml)
+}
+
+// Get the path of the optional user style-sheet
+pub fn get_style_file_path() -> PathBuf {
+    let mut path = glib::get_user_config_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push(APPLICATION_NAME);
+    path.push("style.css");
     path
 }
 
+// Get the default directory for snapshots
+pub fn get_default_snapshot_directory() -> PathBuf {
+    get_default_media_directory(
+        glib::UserDirectory::Pictures,
+        glib::UserDirectory::Videos,
+        "Pictures",
+    )
+}
+
+// Get the default directory for recordings
+pub fn get_default_record_directory() -> PathBuf {
+    get_default_media_directory(
+        glib::UserDirectory::Videos,
+        glib::UserDirectory::Pictures,
+        "Videos",
+    )
+}
+
+// Use the XDG special directory if it is configured and not shared with the other media type
+//
+// On minimal setups without XDG user dirs these are either unset or all point to the home
+// directory, in which case snapshots and recordings would end up mixed in the home directory or
+// even in the current working directory. Use separate subdirectories of our own instead
+//
+// The directory is only created once something is saved to it, see create_media_directory()
+fn get_default_media_directory(
+    directory: glib::UserDirectory,
+    other: glib::UserDirectory,
+    fallback_name: &str,
+) -> PathBuf {
+    choose_media_directory(
+        glib::get_user_special_dir(directory),
+        glib::get_user_special_dir(other),
+        glib::get_home_dir(),
+        fallback_name,
+    )
+}
+
+fn choose_media_directory(
+    special: Option<PathBuf>,
+    other: Option<PathBuf>,
+    home: Option<PathBuf>,
+    fallback_name: &str,
+) -> PathBuf {
+    if let Some(path) = special {
+        if Some(&path) != home.as_ref() && Some(&path) != other.as_ref() {
+            return path;
+        }
+    }
+
+    let mut path = home.unwrap_or_else(|| PathBuf::from("."));
+    path.push("cameraview");
+    path.push(fallback_name);
+    path
+}
+
+// Create the directory for snapshots or recordings if it does not exist yet, e.g. the default
+// directory before anything was saved there
+pub fn create_media_directory(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path)
+        .map_err(|err| format!("Failed to create directory {}: {}", path.display(), err))
+}
+
+// Get the path next to the given file with the given suffix appended, e.g. "settings.toml.bak"
+fn get_path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_else(|| OsString::from("settings.toml"));
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+// Path of the copy of the last successfully saved settings
+fn get_backup_file_path(path: &Path) -> PathBuf {
+    get_path_with_suffix(path, ".bak")
+}
+
+// Path to which a settings file that can't be parsed is moved
+fn get_quarantine_file_path(path: &Path) -> PathBuf {
+    get_path_with_suffix(path, ".bad")
+}
+
 // Save the provided settings to the settings path
-pub fn save_settings(settings: &Settings) {
+//
+// After every successful save a copy of the file is kept as backup, which is used if the settings
+// file ever becomes unreadable. Returns whether the settings were written
+pub fn save_settings(settings: &Settings) -> bool {
     let s = get_settings_file_path();
-    if let Err(e) = serde_any::to_file(&s, &settings) {
+    let res = settings
+        .to_format(get_settings_file_format())
+        .map_err(|e| e.to_string())
+        .and_then(|toml| fs::write(&s, toml).map_err(|e| e.to_string()));
+    if let Err(e) = res {
         show_error_dialog(
             false,
             format!("Error while trying to save file: {}", e).as_str(),
         );
+        return false;
+    }
+
+    if let Err(e) = fs::copy(&s, get_backup_file_path(&s)) {
+        println!("Failed to back up settings file: {}", e);
     }
+
+    true
+}
+
+// Read settings from the given file in the given format, independent of its own extension, e.g.
+// for the backup
+fn read_settings_file(path: &Path, format: serde_any::Format) -> Result<Settings, String> {
+    fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| Settings::from_format(&s, format).map_err(|e| e.to_string()))
 }
 
 // Load the current settings
+//
+// If the settings file can't be parsed, it is moved aside so that the user can inspect and fix it,
+// and the backup of the last successfully saved settings is used instead, if any
 pub fn load_settings() -> Settings {
-    let s = get_settings_file_path();
-    if s.exists() && s.is_file() {
-        match serde_any::from_file::<Settings, _>(&s) {
-            Ok(s) => s,
-            Err(e) => {
-                show_error_dialog(
-                    false,
-                    format!("Error while opening '{}': {}", s.display(), e).as_str(),
-                );
-                Settings::default()
+    // The format has to be known before a broken file is moved aside, as it is guessed from the
+    // settings file that exists and the backup is in the same format
+    let (settings, error) =
+        read_settings_from(&get_settings_file_path(), get_settings_file_format());
+
+    if let Some(text) = error {
+        show_error_dialog(false, text.as_str());
+    }
+
+    settings
+}
+
+// Read the settings from the given file, see load_settings()
+//
+// If the file can't be parsed, this also returns the message to show to the user
+fn read_settings_from(s: &Path, format: serde_any::Format) -> (Settings, Option<String>) {
+    if !s.is_file() {
+        return (Settings::default(), None);
+    }
+
+    let e = match read_settings_file(s, format) {
+        Ok(s) => return (s, None),
+        Err(e) => e,
+    };
+
+    let bad = get_quarantine_file_path(s);
+    let mut text = match fs::rename(s, &bad) {
+        Ok(_) => format!(
+            "Error while opening '{}': {}\n\nThe file was moved to '{}'.",
+            s.display(),
+            e,
+            bad.display()
+        ),
+        Err(_) => format!("Error while opening '{}': {}", s.display(), e),
+    };
+
+    let bak = get_backup_file_path(s);
+    let settings = match read_settings_file(&bak, format) {
+        Ok(settings) => {
+            text.push_str(&format!(" Restored settings from '{}'.", bak.display()));
+
+            // Put the backup in place of the broken file so that later loads see the same
+            // settings. If that fails the settings are still fine for now and the file is written
+            // again with the next save
+            if let Err(err) = fs::copy(&bak, s) {
+                println!("Failed to copy settings backup to {}: {}", s.display(), err);
             }
+
+            settings
         }
-    } else {
-        Settings::default()
-    }
+        Err(_) => {
+            text.push_str(" Using default settings.");
+            Settings::default()
+        }
+    };
+
+    (settings, Some(text))
 }
 
 // Shows an error dialog, and if it's fatal it will quit the application once
@@ -76,3 +254,667 @@ pub fn show_error_dialog(fatal: bool, text: &str) {
     dialog.set_resizable(false);
     dialog.show_all();
 }
+
+// Shows a question dialog with a "Don't ask again" check button
+//
+// The callback is called with whether the user confirmed and whether the check button was active
+pub fn show_confirmation_dialog<F: Fn(bool, bool) + 'static>(
+    text: &str,
+    confirm_label: &str,
+    callback: F,
+) {
+    let app = gio::Application::get_default()
+        .expect("No default application")
+        .downcast::<gtk::Application>()
+        .expect("Default application has wrong type");
+
+    let dialog = gtk::MessageDialog::new(
+        app.get_active_window().as_ref(),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        text,
+    );
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button(confirm_label, gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Cancel);
+
+    let dont_ask_again = gtk::CheckButton::new_with_label("Don't ask again");
+    dialog
+        .get_content_area()
+        .pack_start(&dont_ask_again, false, false, 0);
+
+    dialog.connect_response(move |dialog, response| {
+        let dont_ask_again = dont_ask_again.get_active();
+
+        dialog.destroy();
+
+        callback(response == gtk::ResponseType::Accept, dont_ask_again);
+    });
+
+    dialog.set_resizable(false);
+    dialog.show_all();
+}
+
+// What to do with a file that already exists, see show_overwrite_dialog()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwriteChoice {
+    Overwrite,
+    // Write the new file with a number appended to its name
+    KeepBoth,
+    Cancel,
+}
+
+// Shows a modal dialog asking whether to overwrite the existing file at the given path
+//
+// The callback is called with what the user chose
+pub fn show_overwrite_dialog<F: Fn(OverwriteChoice) + 'static>(path: &Path, callback: F) {
+    let app = gio::Application::get_default()
+        .expect("No default application")
+        .downcast::<gtk::Application>()
+        .expect("Default application has wrong type");
+
+    let dialog = gtk::MessageDialog::new(
+        app.get_active_window().as_ref(),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        &format!("{} already exists. Overwrite it?", path.display()),
+    );
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Keep both", gtk::ResponseType::Reject);
+    dialog.add_button("Overwrite", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Reject);
+
+    dialog.connect_response(move |dialog, response| {
+        dialog.destroy();
+
+        callback(match response {
+            gtk::ResponseType::Accept => OverwriteChoice::Overwrite,
+            gtk::ResponseType::Reject => OverwriteChoice::KeepBoth,
+            _ => OverwriteChoice::Cancel,
+        });
+    });
+
+    dialog.set_resizable(false);
+    dialog.show_all();
+}
+
+// Shows a dialog asking for a new passphrase, which has to be entered twice to avoid typos
+//
+// The callback is called with the passphrase, or None if the user cancelled
+pub fn show_passphrase_dialog<F: Fn(Option<String>) + 'static>(
+    text: &str,
+    confirm_label: &str,
+    callback: F,
+) {
+    let app = gio::Application::get_default()
+        .expect("No default application")
+        .downcast::<gtk::Application>()
+        .expect("Default application has wrong type");
+
+    let dialog = gtk::MessageDialog::new(
+        app.get_active_window().as_ref(),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        text,
+    );
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button(confirm_label, gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_response_sensitive(gtk::ResponseType::Accept, false);
+
+    // Pressing enter in the entries confirms, but only once something was entered and both
+    // entries match
+    let passphrase = gtk::Entry::new();
+    passphrase.set_visibility(false);
+    passphrase.set_activates_default(true);
+    passphrase.set_placeholder_text(Some("Passphrase"));
+    let confirmation = gtk::Entry::new();
+    confirmation.set_visibility(false);
+    confirmation.set_activates_default(true);
+    confirmation.set_placeholder_text(Some("Repeat passphrase"));
+
+    let content_area = dialog.get_content_area();
+    content_area.pack_start(&passphrase, false, false, 0);
+    content_area.pack_start(&confirmation, false, false, 0);
+
+    for entry in &[&passphrase, &confirmation] {
+        let dialog = dialog.clone();
+        let passphrase = passphrase.clone();
+        let confirmation = confirmation.clone();
+        entry.connect_changed(move |_| {
+            dialog.set_response_sensitive(
+                gtk::ResponseType::Accept,
+                passphrase.get_text_length() > 0
+                    && passphrase.get_text() == confirmation.get_text(),
+            );
+        });
+    }
+
+    dialog.connect_response(move |dialog, response| {
+        let text = passphrase.get_text().map(|text| text.to_string());
+
+        dialog.destroy();
+
+        match response {
+            gtk::ResponseType::Accept => callback(text),
+            _ => callback(None),
+        }
+    });
+
+    dialog.set_resizable(false);
+    dialog.show_all();
+}
+
+// Shows a dialog asking where to save a file, starting with the given file name
+//
+// The callback is called with the chosen file name, or None if the user cancelled
+pub fn show_save_dialog<F: Fn(Option<PathBuf>) + 'static>(
+    title: &str,
+    filename: &Path,
+    callback: F,
+) {
+    let app = gio::Application::get_default()
+        .expect("No default application")
+        .downcast::<gtk::Application>()
+        .expect("Default application has wrong type");
+
+    let dialog = gtk::FileChooserDialog::new(
+        Some(title),
+        app.get_active_window().as_ref(),
+        gtk::FileChooserAction::Save,
+    );
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Save", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_modal(true);
+    dialog.set_do_overwrite_confirmation(true);
+
+    if let Some(directory) = filename.parent() {
+        dialog.set_current_folder(directory);
+    }
+    if let Some(name) = filename.file_name() {
+        dialog.set_current_name(name);
+    }
+
+    dialog.connect_response(move |dialog, response| {
+        let filename = dialog.get_filename();
+
+        dialog.destroy();
+
+        match response {
+            gtk::ResponseType::Accept => callback(filename),
+            _ => callback(None),
+        }
+    });
+
+    dialog.show_all();
+}
+
+// Shows a dialog asking for an existing file to open
+//
+// The callback is called with the chosen file name, or None if the user cancelled
+pub fn show_open_dialog<F: Fn(Option<PathBuf>) + 'static>(title: &str, callback: F) {
+    let app = gio::Application::get_default()
+        .expect("No default application")
+        .downcast::<gtk::Application>()
+        .expect("Default application has wrong type");
+
+    let dialog = gtk::FileChooserDialog::new(
+        Some(title),
+        app.get_active_window().as_ref(),
+        gtk::FileChooserAction::Open,
+    );
+
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Open", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_modal(true);
+
+    dialog.connect_response(move |dialog, response| {
+        let filename = dialog.get_filename();
+
+        dialog.destroy();
+
+        match response {
+            gtk::ResponseType::Accept => callback(filename),
+            _ => callback(None),
+        }
+    });
+
+    dialog.show_all();
+}
+
+// Get the memory that is available for new allocations, in bytes
+//
+// Returns None if this can't be determined on the current platform
+#[cfg(target_os = "linux")]
+pub fn get_available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+
+    // The line looks like "MemAvailable:    1234567 kB"
+    meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_available_memory() -> Option<u64> {
+    None
+}
+
+// Get a path that does not exist yet, by appending "-1", "-2", etc. to the file name if needed
+//
+// Timestamp based file names collide if multiple files are created within the same second
+pub fn get_unique_path(path: PathBuf) -> PathBuf {
+    get_unique_path_with(path, |path| path.exists())
+}
+
+// Create a new file at a path that does not exist yet, see get_unique_path()
+//
+// Unlike checking first and creating the file afterwards this never overwrites a file that was
+// created in the meantime
+pub fn create_unique_file(path: PathBuf) -> Result<(PathBuf, fs::File), String> {
+    let mut candidate = get_unique_path(path.clone());
+    loop {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((candidate, file)),
+            // Someone else was faster, the next unique path skips that file now
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                candidate = get_unique_path(path.clone());
+            }
+            Err(err) => return Err(format!("{}: {}", candidate.display(), err)),
+        }
+    }
+}
+
+// Same as get_unique_path() but with a custom check whether a path is already taken, e.g. for
+// file names inside an archive
+pub fn get_unique_path_with<F: Fn(&Path) -> bool>(path: PathBuf, exists: F) -> PathBuf {
+    if !exists(&path) {
+        return path;
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|i| path.with_file_name(format!("{}-{}{}", stem, i, extension)))
+        .find(|path| !exists(path))
+        .expect("No unique path found")
+}
+
+// Expand a file name template with strftime-style fields for the current time and the counter n
+// for the {n} token, e.g. "Snapshot %Y-%m-%d {n}"
+//
+// Fails if the template is invalid or would not result in a usable file name
+pub fn expand_filename_template(
+    template: &str,
+    timezone: Timezone,
+    n: u32,
+) -> Result<String, String> {
+    use chrono::format::{Item, StrftimeItems};
+
+    // Formatting an invalid template would panic
+    if StrftimeItems::new(template).any(|item| item == Item::Error) {
+        return Err(String::from("Invalid time format"));
+    }
+
+    let name = timezone.format_now(template).replace("{n}", &n.to_string());
+    if name.trim().is_empty() || name == "." || name == ".." {
+        Err(String::from("The file name is empty"))
+    } else if name.contains('/') || name.contains('\\') {
+        Err(String::from(
+            "The file name must not contain path separators",
+        ))
+    } else {
+        Ok(name)
+    }
+}
+
+// Get a file name in the directory from the template plus extension
+//
+// The {n} token is replaced by the lowest number, starting at 1, for which no such file exists yet
+pub fn get_template_file_name(
+    directory: &Path,
+    template: &str,
+    timezone: Timezone,
+    extension: &str,
+) -> Result<String, String> {
+    if !template.contains("{n}") {
+        return Ok(format!(
+            "{}.{}",
+            expand_filename_template(template, timezone, 0)?,
+            extension
+        ));
+    }
+
+    for n in 1.. {
+        let name = format!(
+            "{}.{}",
+            expand_filename_template(template, timezone, n)?,
+            extension
+        );
+        if !directory.join(&name).exists() {
+            return Ok(name);
+        }
+    }
+
+    unreachable!()
+}
+
+// Get the next file name of a sequence like "IMG_0001.jpg" in the directory
+//
+// The last used number is kept in a counter file next to the settings so that numbers are not
+// reused after files were moved away. The directory is scanned too so that existing files are
+// never overwritten, e.g. after the counter file was lost or deleted to start over.
+//
+// Gaps in the sequence are never filled and the counter is never reset on its own. After 9999
+// the numbers simply get more digits. The counter is only updated by commit_sequential_file_name()
+// once the file was written, so that failed or cancelled captures don't use up a number
+pub fn get_sequential_file_name(directory: &Path, prefix: &str, extension: &str) -> String {
+    let stored = read_file_counter(&get_file_counter_path(prefix));
+
+    // The highest number already used in the directory, with any extension
+    let highest = fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    get_sequence_number(&entry.file_name().into_string().ok()?, prefix)
+                })
+                .max()
+                .unwrap_or(0)
+        })
+        .unwrap_or(0);
+
+    format!("{}_{:04}.{}", prefix, stored.max(highest) + 1, extension)
+}
+
+// Remember the number of a file from get_sequential_file_name() after it was written successfully
+//
+// File names that are not part of the sequence are ignored, and the counter never goes backwards
+pub fn commit_sequential_file_name(filename: &Path, prefix: &str) {
+    let number = match filename
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| get_sequence_number(name, prefix))
+    {
+        Some(number) => number,
+        None => return,
+    };
+
+    let counter_path = get_file_counter_path(prefix);
+    if number <= read_file_counter(&counter_path) {
+        return;
+    }
+
+    if let Err(err) = fs::write(&counter_path, number.to_string()) {
+        println!(
+            "Failed to write counter file {}: {}",
+            counter_path.display(),
+            err
+        );
+    }
+}
+
+// The counter file with the last used number of a sequence, next to the settings
+fn get_file_counter_path(prefix: &str) -> PathBuf {
+    get_settings_file_path().with_file_name(format!("{}-counter", prefix))
+}
+
+fn read_file_counter(counter_path: &Path) -> u32 {
+    fs::read_to_string(counter_path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .unwrap_or(0)
+}
+
+// Get the number of a file name of a sequence like "IMG_0001.jpg"
+//
+// Anything after the number belongs to the same file, e.g. "IMG_0001-1.jpg" if the name was taken
+// already, "IMG_0001-000.jpg" in a burst or "VID_0001_00000.mp4" for the fragments of a recording
+fn get_sequence_number(name: &str, prefix: &str) -> Option<u32> {
+    let prefix_ = format!("{}_", prefix);
+    if !name.starts_with(&prefix_) {
+        return None;
+    }
+
+    let number = name.get(prefix_.len()..)?;
+    let digits = number.bytes().take_while(|b| b.is_ascii_digit()).count();
+    number[..digits].parse::<u32>().ok()
+}
+
+// Get the free space of the filesystem the path is on, in bytes
+pub fn get_free_space(path: &Path) -> Option<u64> {
+    let file = gio::File::new_for_path(path);
+    let info = file
+        .query_filesystem_info("filesystem::free", None::<&gio::Cancellable>)
+        .ok()?;
+
+    Some(info.get_attribute_uint64("filesystem::free"))
+}
+
+// Format a number of bytes in a human readable way, e.g. "1.5 GB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["bytes", "kB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::process;
+
+    // Empty directory for the files of a single test
+    fn test_directory(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("cameraview-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("Failed to create test directory");
+        path
+    }
+
+    fn write_settings(path: &Path, settings: &Settings) {
+        let toml = settings
+            .to_format(serde_any::Format::Toml)
+            .expect("Failed to serialize settings");
+        fs::write(path, toml).expect("Failed to write settings");
+    }
+
+    #[test]
+    fn unique_path_suffix() {
+        let taken: HashSet<PathBuf> = [
+            "/tmp/Snapshot.jpg",
+            "/tmp/Snapshot-1.jpg",
+            "/tmp/Snapshot-2.jpg",
+            "/tmp/archive",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let exists = |path: &Path| taken.contains(path);
+
+        // Free paths are used as is
+        assert_eq!(
+            get_unique_path_with(PathBuf::from("/tmp/Recording.mkv"), exists),
+            PathBuf::from("/tmp/Recording.mkv")
+        );
+        // The first free number is used, and the extension is kept
+        assert_eq!(
+            get_unique_path_with(PathBuf::from("/tmp/Snapshot.jpg"), exists),
+            PathBuf::from("/tmp/Snapshot-3.jpg")
+        );
+        // Also without extension
+        assert_eq!(
+            get_unique_path_with(PathBuf::from("/tmp/archive"), exists),
+            PathBuf::from("/tmp/archive-1")
+        );
+    }
+
+    #[test]
+    fn unique_file_creation() {
+        let directory = test_directory("unique-file");
+        let path = directory.join("Snapshot.jpg");
+
+        let (first, _) = create_unique_file(path.clone()).expect("Failed to create file");
+        let (second, _) = create_unique_file(path.clone()).expect("Failed to create file");
+        assert_eq!(first, path);
+        assert_eq!(second, directory.join("Snapshot-1.jpg"));
+        assert_eq!(get_unique_path(path), directory.join("Snapshot-2.jpg"));
+    }
+
+    #[test]
+    fn media_directory_fallback() {
+        let home = Some(PathBuf::from("/home/user"));
+        let pictures = Some(PathBuf::from("/home/user/Pictures"));
+        let videos = Some(PathBuf::from("/home/user/Videos"));
+        let fallback = PathBuf::from("/home/user/cameraview/Pictures");
+
+        // Configured and separate from the other media type
+        assert_eq!(
+            choose_media_directory(pictures.clone(), videos, home.clone(), "Pictures"),
+            PathBuf::from("/home/user/Pictures")
+        );
+        // Not configured, pointing to the home directory or shared with the other media type
+        assert_eq!(
+            choose_media_directory(None, None, home.clone(), "Pictures"),
+            fallback
+        );
+        assert_eq!(
+            choose_media_directory(home.clone(), home.clone(), home.clone(), "Pictures"),
+            fallback
+        );
+        assert_eq!(
+            choose_media_directory(pictures.clone(), pictures, home, "Pictures"),
+            fallback
+        );
+        // Without home directory
+        assert_eq!(
+            choose_media_directory(None, None, None, "Videos"),
+            PathBuf::from("./cameraview/Videos")
+        );
+    }
+
+    #[test]
+    fn read_missing_settings_file() {
+        let directory = test_directory("missing");
+        let path = directory.join("settings.toml");
+
+        let (settings, error) = read_settings_from(&path, serde_any::Format::Toml);
+        assert!(error.is_none());
+        assert_eq!(settings.timer_length, Settings::default().timer_length);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn read_corrupt_settings_file_with_backup() {
+        let directory = test_directory("corrupt-main");
+        let path = directory.join("settings.toml");
+
+        let backup = Settings {
+            timer_length: 7,
+            ..Settings::default()
+        };
+        write_settings(&get_backup_file_path(&path), &backup);
+        fs::write(&path, "timer_length = [").unwrap();
+
+        let (settings, error) = read_settings_from(&path, serde_any::Format::Toml);
+        assert!(error.is_some());
+        assert_eq!(settings.timer_length, 7);
+
+        // The broken file is kept for the user and the backup is put in its place
+        assert_eq!(
+            fs::read_to_string(get_quarantine_file_path(&path)).unwrap(),
+            "timer_length = ["
+        );
+        let (settings, error) = read_settings_from(&path, serde_any::Format::Toml);
+        assert!(error.is_none());
+        assert_eq!(settings.timer_length, 7);
+    }
+
+    #[test]
+    fn read_corrupt_json_settings_file_with_backup() {
+        let directory = test_directory("corrupt-json");
+        let path = directory.join("settings.json");
+
+        let backup = Settings {
+            timer_length: 7,
+            ..Settings::default()
+        };
+        let json = backup
+            .to_format(serde_any::Format::Json)
+            .expect("Failed to serialize settings");
+        fs::write(get_backup_file_path(&path), json).unwrap();
+        fs::write(&path, "{ \"timer_length\": [").unwrap();
+
+        // The backup is read as JSON even though the JSON file is gone at that point
+        let (settings, error) = read_settings_from(&path, serde_any::Format::Json);
+        assert!(error.is_some());
+        assert_eq!(settings.timer_length, 7);
+        assert!(path.is_file());
+        assert!(!directory.join("settings.toml").exists());
+    }
+
+    #[test]
+    fn read_corrupt_settings_file_and_backup() {
+        let directory = test_directory("corrupt-backup");
+        let path = directory.join("settings.toml");
+
+        fs::write(get_backup_file_path(&path), "timer_length = [").unwrap();
+        fs::write(&path, "timer_length = [").unwrap();
+
+        let (settings, error) = read_settings_from(&path, serde_any::Format::Toml);
+        assert!(error.is_some());
+        assert_eq!(settings.timer_length, Settings::default().timer_length);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn sequence_numbers() {
+        assert_eq!(get_sequence_number("IMG_0001.jpg", "IMG"), Some(1));
+        assert_eq!(get_sequence_number("IMG_12345.png", "IMG"), Some(12345));
+        // Files that belong to a number
+        assert_eq!(get_sequence_number("IMG_0002-1.jpg", "IMG"), Some(2));
+        assert_eq!(get_sequence_number("IMG_0003-000.jpg", "IMG"), Some(3));
+        assert_eq!(get_sequence_number("VID_0004_00000.mp4", "VID"), Some(4));
+        assert_eq!(get_sequence_number("VID_0005.mkv.enc", "VID"), Some(5));
+        // Other files
+        assert_eq!(get_sequence_number("VID_0001.mp4", "IMG"), None);
+        assert_eq!(get_sequence_number("IMG_.jpg", "IMG"), None);
+        assert_eq!(get_sequence_number("IMG_copy.jpg", "IMG"), None);
+        assert_eq!(get_sequence_number("Snapshot.jpg", "IMG"), None);
+    }
+}